@@ -1,5 +1,13 @@
 use criterion::{Criterion, black_box, criterion_group, criterion_main};
-use qoi::qoi::{encoder::encode_, types::Pixel};
+use qoi::qoi::{
+    DecodeMode, EncoderOptions, QoiEncoder, QoiPixels,
+    decoder::Decoder,
+    encoder::{Encoder, encode_},
+    simd::{classify, classify_scalar},
+    types::Pixel,
+};
+
+const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
 
 fn bench_encode(c: &mut Criterion) {
     // Create test image
@@ -17,19 +25,153 @@ fn bench_encode(c: &mut Criterion) {
         }
     }
 
-    let mut array = [Pixel::default(); 64];
+    // the encoder resets its index for every image and reuses its output buffer
+    let mut encoder = Encoder::new();
 
     c.bench_function("encode_512x512", |b| {
         b.iter(|| {
-            let _ = encode_(
-                black_box(&image),
-                black_box(&mut array),
-                black_box(width),
-                black_box(height),
-            );
+            let _ = encoder.encode(black_box(&image), black_box(width), black_box(height));
         })
     });
 }
 
-criterion_group!(benches, bench_encode);
+fn bench_decode(c: &mut Criterion) {
+    // Create a larger test image mixing gradients, flat areas and noise so every op shows up
+    let width = 2048;
+    let height = 2048;
+    let mut state: u32 = 0x9E37_79B9;
+    let mut image = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let pixel = match (x / 64 + y / 64) % 3 {
+                0 => Pixel::new((x % 256) as u8, (y % 256) as u8, ((x + y) % 256) as u8, 255),
+                1 => Pixel::new(40, 80, 120, 255),
+                _ => Pixel::new(
+                    (state >> 24) as u8,
+                    (state >> 16) as u8,
+                    (state >> 8) as u8,
+                    255,
+                ),
+            };
+            image.push(pixel);
+        }
+    }
+
+    let encoded = encode_(&image, &mut [Pixel::default(); 64], width, height).unwrap();
+    let mut decoder = Decoder::new();
+
+    let mut group = c.benchmark_group("decode_2048x2048");
+    // the decoder as it was before decoding in one pass: a scan of the whole stream for the end
+    // marker, then the chunks before it decoded a pixel at a time into a growing Vec
+    group.bench_function("end_marker_scan", |b| {
+        b.iter(|| {
+            let bytestream = black_box(&encoded[..]);
+            let end = 14 + bytestream[14..]
+                .windows(8)
+                .position(|window| window == END_MARKER)
+                .unwrap();
+            let mut pixels = Vec::with_capacity((width * height) as usize);
+            let chunks = QoiPixels::new(&bytestream[..end])
+                .unwrap()
+                .with_mode(DecodeMode::Lenient);
+            for pixel in chunks {
+                pixels.push(pixel.unwrap());
+            }
+            pixels
+        })
+    });
+    group.bench_function("single_pass", |b| {
+        b.iter(|| {
+            let _ = decoder.decode(black_box(&encoded));
+        })
+    });
+    group.finish();
+}
+
+// a screen capture: flat backgrounds, window borders and some anti-aliased text
+fn screen(width: u32, height: u32) -> Vec<Pixel> {
+    let mut state: u32 = 0xABCD;
+    let mut image = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let pixel = if y % 40 < 2 || x % 300 < 2 {
+                Pixel::new(60, 60, 70, 255)
+            } else if (y % 40) > 10 && (y % 40) < 22 && x % 300 < 200 && state.is_multiple_of(3) {
+                let shade = (state >> 8) as u8;
+                Pixel::new(shade, shade, shade.wrapping_add(3), 255)
+            } else {
+                Pixel::new(240, 240, 245, 255)
+            };
+            image.push(pixel);
+        }
+    }
+    image
+}
+
+fn bench_simd(c: &mut Criterion) {
+    let width = 1920;
+    let height = 1080;
+    let image = screen(width, height);
+
+    // the same Encoder with crate::qoi::simd forced to its scalar code and left to pick AVX2,
+    // the streaming encoder still goes pixel by pixel
+    let mut group = c.benchmark_group("encode_screen_1920x1080");
+    for (name, simd) in [("scalar", false), ("simd", true)] {
+        let mut encoder = Encoder::with_options(EncoderOptions::new().simd(simd));
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let _ = encoder.encode(black_box(&image), width, height);
+            })
+        });
+    }
+    group.bench_function("pixel_at_a_time", |b| {
+        b.iter(|| {
+            let mut encoder = QoiEncoder::new(Vec::new(), width, height, 3, 0).unwrap();
+            encoder.write_pixels(black_box(&image)).unwrap();
+            encoder.finish().unwrap()
+        })
+    });
+    group.finish();
+
+    let mut classes = vec![0u8; image.len()];
+    let mut group = c.benchmark_group("classify_1920x1080");
+    group.bench_function("scalar", |b| {
+        b.iter(|| classify_scalar(black_box(&image), Pixel::default(), false, &mut classes))
+    });
+    group.bench_function("simd", |b| {
+        b.iter(|| classify(black_box(&image), Pixel::default(), false, &mut classes))
+    });
+    group.finish();
+}
+
+fn bench_threads(c: &mut Criterion) {
+    let width = 1920;
+    let height = 1080;
+    let image = screen(width, height);
+
+    let mut group = c.benchmark_group("encode_threads_1920x1080");
+    for threads in [1, 2, 4, 8] {
+        let mut encoder = Encoder::with_options(EncoderOptions::new().threads(threads));
+        group.bench_function(format!("{}", threads), |b| {
+            b.iter(|| {
+                let _ = encoder.encode(black_box(&image), width, height);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_encode,
+    bench_decode,
+    bench_simd,
+    bench_threads
+);
 criterion_main!(benches);
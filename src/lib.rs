@@ -1,2 +1,6 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
 // Or export the module directly
 pub mod qoi;
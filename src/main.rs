@@ -1,5 +1,4 @@
-mod qoi;
-use qoi::cli;
+use qoi::qoi::cli;
 
 fn main() {
     cli()
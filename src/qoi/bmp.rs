@@ -0,0 +1,472 @@
+//! Reader and writer for Windows BMP.
+//!
+//! The reader takes the 12 byte core header and the info headers up to V5, at 1, 4, 8, 16, 24
+//! and 32 bits per pixel: palettes, `BI_RGB`, `BI_BITFIELDS` and `BI_ALPHABITFIELDS`, `BI_RLE8`
+//! and `BI_RLE4`, stored bottom-up or top-down. Only bitfields carry an alpha, the fourth byte
+//! of a 32-bit `BI_RGB` pixel is padding. Pixels an RLE image skips over get palette entry 0,
+//! RLE images with more than 4096 pixels per byte of data are rejected.
+//!
+//! The writer produces 24-bit `BI_RGB`, or 32-bit `BI_BITFIELDS` with an alpha mask in a V4
+//! header when the image has an alpha, bottom-up as most readers expect.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::qoi::error::QoiError;
+use crate::qoi::limits::DecodeLimits;
+use crate::qoi::types::{Channels, Pixel};
+
+/// The 2 bytes every BMP file starts with.
+pub const MAGIC: [u8; 2] = *b"BM";
+
+const FILE_HEADER_SIZE: usize = 14;
+const CORE_HEADER_SIZE: u32 = 12;
+const INFO_HEADER_SIZE: u32 = 40;
+const V4_HEADER_SIZE: u32 = 108;
+
+const BI_RGB: u32 = 0;
+const BI_RLE8: u32 = 1;
+const BI_RLE4: u32 = 2;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+/// A decoded BMP image, always top-down whatever the order of the rows in the file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bmp {
+    width: u32,
+    height: u32,
+    bits_per_pixel: u16,
+    compression: u32,
+    top_down: bool,
+    has_alpha: bool,
+    pixels: Vec<Pixel>,
+}
+
+// one color of a bitfield pixel
+#[derive(Clone, Copy)]
+struct Field {
+    mask: u32,
+    shift: u32,
+    max: u32,
+}
+
+impl Field {
+    fn new(mask: u32) -> Result<Self, QoiError> {
+        let shift = if mask == 0 { 0 } else { mask.trailing_zeros() };
+        let max = mask >> shift;
+        // NOTE: a contiguous mask shifted down is one less than a power of two
+        if max & max.wrapping_add(1) != 0 {
+            return Err(QoiError::InvalidBmp("non-contiguous bitfield mask"));
+        }
+        Ok(Self { mask, shift, max })
+    }
+
+    // the field of `pixel` rescaled to 8 bits, `missing` without a mask
+    #[inline(always)]
+    fn extract(&self, pixel: u32, missing: u8) -> u8 {
+        if self.max == 0 {
+            return missing;
+        }
+        let value = ((pixel & self.mask) >> self.shift) as u64;
+        ((value * 255 + self.max as u64 / 2) / self.max as u64) as u8
+    }
+}
+
+pub fn read(bytes: &[u8]) -> Result<Bmp, QoiError> {
+    read_with_limits(bytes, &DecodeLimits::default())
+}
+
+/// Same as [`read`], with the dimensions checked against `limits` before anything gets
+/// allocated.
+pub fn read_with_limits(bytes: &[u8], limits: &DecodeLimits) -> Result<Bmp, QoiError> {
+    if !bytes.starts_with(&MAGIC) {
+        return Err(QoiError::InvalidBmp("not a BMP file"));
+    }
+    let word = |at: usize| {
+        bytes
+            .get(at..at + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .ok_or(QoiError::InvalidBmp("truncated header"))
+    };
+    let half = |at: usize| {
+        bytes
+            .get(at..at + 2)
+            .map(|b| u16::from_le_bytes(b.try_into().unwrap()))
+            .ok_or(QoiError::InvalidBmp("truncated header"))
+    };
+    let data_offset = word(10)? as usize;
+    let header_size = word(14)?;
+    let info = FILE_HEADER_SIZE;
+    let (width, height, planes, bits_per_pixel, compression, colors_used) =
+        if header_size == CORE_HEADER_SIZE {
+            let (width, height) = (half(info + 4)? as i32, half(info + 6)? as i32);
+            (width, height, half(info + 8)?, half(info + 10)?, BI_RGB, 0)
+        } else if header_size >= INFO_HEADER_SIZE {
+            (
+                word(info + 4)? as i32,
+                word(info + 8)? as i32,
+                half(info + 12)?,
+                half(info + 14)?,
+                word(info + 16)?,
+                word(info + 32)?,
+            )
+        } else {
+            return Err(QoiError::InvalidBmp("unknown header size"));
+        };
+    if planes != 1 {
+        return Err(QoiError::InvalidBmp("planes other than 1"));
+    }
+    if width <= 0 || height == 0 || height == i32::MIN {
+        return Err(QoiError::InvalidBmp("width or height out of range"));
+    }
+    // a negative height stores the rows top-down
+    let top_down = height < 0;
+    let (width, height) = (width as u32, height.unsigned_abs());
+    let valid = match compression {
+        BI_RGB => matches!(bits_per_pixel, 1 | 4 | 8 | 16 | 24 | 32),
+        BI_RLE8 => bits_per_pixel == 8,
+        BI_RLE4 => bits_per_pixel == 4,
+        BI_BITFIELDS | BI_ALPHABITFIELDS => matches!(bits_per_pixel, 16 | 32),
+        _ => return Err(QoiError::InvalidBmp("unknown compression")),
+    };
+    if !valid {
+        return Err(QoiError::InvalidBmp(
+            "bit depth not allowed for the compression",
+        ));
+    }
+    let rle = compression == BI_RLE8 || compression == BI_RLE4;
+    if rle && top_down {
+        return Err(QoiError::InvalidBmp("RLE images can't be top-down"));
+    }
+    limits.check(width, height, size_of::<Pixel>())?;
+
+    let mut at = info + header_size as usize;
+    let masks = match compression {
+        BI_BITFIELDS | BI_ALPHABITFIELDS => {
+            // NOTE: the masks follow an info header, the larger headers hold them instead
+            let count = if header_size > INFO_HEADER_SIZE {
+                ((header_size - INFO_HEADER_SIZE) / 4).min(4) as usize
+            } else {
+                let count = if compression == BI_ALPHABITFIELDS {
+                    4
+                } else {
+                    3
+                };
+                at += count * 4;
+                count
+            };
+            let from = info + INFO_HEADER_SIZE as usize;
+            let mut masks = [0u32; 4];
+            for (i, mask) in masks.iter_mut().enumerate().take(count) {
+                *mask = word(from + i * 4)?;
+            }
+            masks
+        }
+        _ if bits_per_pixel == 16 => [0x7C00, 0x03E0, 0x001F, 0],
+        _ => [0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0],
+    };
+    let fields = [
+        Field::new(masks[0])?,
+        Field::new(masks[1])?,
+        Field::new(masks[2])?,
+        Field::new(masks[3])?,
+    ];
+
+    let palette = if bits_per_pixel <= 8 {
+        // the core header has RGBTRIPLEs, the others RGBQUADs
+        let entry = if header_size == CORE_HEADER_SIZE {
+            3
+        } else {
+            4
+        };
+        let max = 1usize << bits_per_pixel;
+        // NOTE: without a count the palette is as large as the bit depth allows, some writers
+        //       only store the colors they use and start the pixels right after them
+        let count = if colors_used == 0 {
+            max.min(data_offset.saturating_sub(at) / entry)
+        } else {
+            (colors_used as usize).min(max)
+        };
+        let Some(table) = bytes.get(at..at + count * entry) else {
+            return Err(QoiError::InvalidBmp("truncated palette"));
+        };
+        table
+            .chunks_exact(entry)
+            .map(|bgr| Pixel::new(bgr[2], bgr[1], bgr[0], 255))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let Some(data) = bytes.get(data_offset..) else {
+        return Err(QoiError::InvalidBmp("pixel data offset past the end"));
+    };
+
+    let pixels = if rle {
+        read_rle(data, width, height, compression == BI_RLE4, &palette)?
+    } else {
+        read_uncompressed(
+            data,
+            width,
+            height,
+            bits_per_pixel,
+            top_down,
+            &palette,
+            &fields,
+        )?
+    };
+    Ok(Bmp {
+        width,
+        height,
+        bits_per_pixel,
+        compression,
+        top_down,
+        has_alpha: fields[3].max != 0,
+        pixels,
+    })
+}
+
+#[inline(always)]
+fn palette_entry(palette: &[Pixel], index: usize) -> Result<Pixel, QoiError> {
+    palette
+        .get(index)
+        .copied()
+        .ok_or(QoiError::InvalidBmp("palette index out of range"))
+}
+
+fn read_uncompressed(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    bits_per_pixel: u16,
+    top_down: bool,
+    palette: &[Pixel],
+    fields: &[Field; 4],
+) -> Result<Vec<Pixel>, QoiError> {
+    // rows are padded to 4 bytes
+    let stride = (width as u64 * bits_per_pixel as u64).div_ceil(32) * 4;
+    if (data.len() as u64) < stride * height as u64 {
+        return Err(QoiError::InvalidBmp("truncated pixel data"));
+    }
+    let (width, height, stride) = (width as usize, height as usize, stride as usize);
+    let mut pixels = vec![Pixel::default(); width * height];
+    for (row, src) in data.chunks_exact(stride).take(height).enumerate() {
+        let y = if top_down { row } else { height - 1 - row };
+        let out = &mut pixels[y * width..(y + 1) * width];
+        match bits_per_pixel {
+            1 | 4 | 8 => {
+                let per_byte = 8 / bits_per_pixel as usize;
+                let mask = ((1u16 << bits_per_pixel) - 1) as u8;
+                for (x, pixel) in out.iter_mut().enumerate() {
+                    let shift = 8 - bits_per_pixel as usize * (x % per_byte + 1);
+                    let index = (src[x / per_byte] >> shift) & mask;
+                    *pixel = palette_entry(palette, index as usize)?;
+                }
+            }
+            24 => {
+                for (pixel, bgr) in out.iter_mut().zip(src.chunks_exact(3)) {
+                    *pixel = Pixel::new(bgr[2], bgr[1], bgr[0], 255);
+                }
+            }
+            _ => {
+                let size = bits_per_pixel as usize / 8;
+                for (pixel, bytes) in out.iter_mut().zip(src.chunks_exact(size)) {
+                    let value = if size == 2 {
+                        u16::from_le_bytes([bytes[0], bytes[1]]) as u32
+                    } else {
+                        u32::from_le_bytes(bytes.try_into().unwrap())
+                    };
+                    *pixel = Pixel::new(
+                        fields[0].extract(value, 0),
+                        fields[1].extract(value, 0),
+                        fields[2].extract(value, 0),
+                        fields[3].extract(value, 255),
+                    );
+                }
+            }
+        }
+    }
+    Ok(pixels)
+}
+
+// RLE8 and RLE4: runs of a count and an index (two alternating ones for RLE4), or a 0 and an
+// escape: 0 ends the line, 1 the image, 2 moves by the next two bytes and 3 or more is that many
+// literal indexes padded to 2 bytes. Runs going past the right edge are cut.
+fn read_rle(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    rle4: bool,
+    palette: &[Pixel],
+) -> Result<Vec<Pixel>, QoiError> {
+    // NOTE: line ends, deltas and the end of bitmap skip any number of pixels, so nothing in the
+    //       format ties the size of the image to its data. Images larger than `RLE_MAX_RATIO`
+    //       pixels per byte of data are turned down before they get allocated
+    if width as u64 * height as u64 > (data.len() as u64).saturating_mul(RLE_MAX_RATIO) {
+        return Err(QoiError::InvalidBmp("not enough RLE data for the image"));
+    }
+    let (width, height) = (width as usize, height as usize);
+    // the rows bottom-up as in the file
+    let mut indexes = vec![0u8; width * height];
+    let (mut x, mut y) = (0usize, 0usize);
+    let mut put = |x: usize, y: usize, index: u8| {
+        if x < width {
+            indexes[y * width + x] = index;
+        }
+    };
+    let nibble = |byte: u8, k: usize| {
+        if k.is_multiple_of(2) {
+            byte >> 4
+        } else {
+            byte & 0x0F
+        }
+    };
+    let mut i = 0;
+    // NOTE: data that runs out before the end of bitmap marker ends the image there
+    while let (Some(&count), Some(&value), true) = (data.get(i), data.get(i + 1), y < height) {
+        i += 2;
+        match (count, value) {
+            (0, 0) => {
+                x = 0;
+                y += 1;
+            }
+            (0, 1) => break,
+            (0, 2) => {
+                let (Some(&dx), Some(&dy)) = (data.get(i), data.get(i + 1)) else {
+                    return Err(QoiError::InvalidBmp("truncated RLE delta"));
+                };
+                i += 2;
+                x += dx as usize;
+                y += dy as usize;
+            }
+            (0, count) => {
+                let count = count as usize;
+                let len = if rle4 { count.div_ceil(2) } else { count };
+                let Some(literal) = data.get(i..i + len) else {
+                    return Err(QoiError::InvalidBmp("truncated RLE literal run"));
+                };
+                for k in 0..count {
+                    let index = if rle4 {
+                        nibble(literal[k / 2], k)
+                    } else {
+                        literal[k]
+                    };
+                    put(x, y, index);
+                    x += 1;
+                }
+                i += len + len % 2;
+            }
+            (count, value) => {
+                for k in 0..count as usize {
+                    let index = if rle4 { nibble(value, k) } else { value };
+                    put(x, y, index);
+                    x += 1;
+                }
+            }
+        }
+    }
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in indexes.chunks_exact(width).rev() {
+        for &index in row {
+            pixels.push(palette_entry(palette, index as usize)?);
+        }
+    }
+    Ok(pixels)
+}
+
+// the most pixels an RLE image may have per byte of data, enough for rows 8192 wide that are
+// nothing but a line end
+const RLE_MAX_RATIO: u64 = 4096;
+
+/// Writes `pixels` as a bottom-up BMP, 32-bit with an alpha mask for [`Channels::Rgba`] and
+/// 24-bit otherwise.
+pub fn write_bmp(pixels: &[Pixel], width: u32, height: u32, chanels: Channels) -> Vec<u8> {
+    let alpha = chanels == Channels::Rgba;
+    let (header_size, bits_per_pixel) = if alpha {
+        (V4_HEADER_SIZE, 32u16)
+    } else {
+        (INFO_HEADER_SIZE, 24)
+    };
+    let stride = (width as usize * bits_per_pixel as usize / 8).next_multiple_of(4);
+    let data_offset = FILE_HEADER_SIZE as u32 + header_size;
+    let data_size = stride * height as usize;
+
+    let mut output = Vec::with_capacity(data_offset as usize + data_size);
+    output.extend_from_slice(&MAGIC);
+    output.extend_from_slice(&((data_offset as usize + data_size) as u32).to_le_bytes());
+    output.extend_from_slice(&[0; 4]);
+    output.extend_from_slice(&data_offset.to_le_bytes());
+
+    output.extend_from_slice(&header_size.to_le_bytes());
+    output.extend_from_slice(&width.to_le_bytes());
+    output.extend_from_slice(&height.to_le_bytes());
+    output.extend_from_slice(&1u16.to_le_bytes());
+    output.extend_from_slice(&bits_per_pixel.to_le_bytes());
+    let compression = if alpha { BI_BITFIELDS } else { BI_RGB };
+    output.extend_from_slice(&compression.to_le_bytes());
+    output.extend_from_slice(&(data_size as u32).to_le_bytes());
+    // 72 dpi, as pixels per meter
+    output.extend_from_slice(&2835u32.to_le_bytes());
+    output.extend_from_slice(&2835u32.to_le_bytes());
+    output.extend_from_slice(&[0; 8]);
+    if alpha {
+        for mask in [0x00FF_0000u32, 0x0000_FF00, 0x0000_00FF, 0xFF00_0000] {
+            output.extend_from_slice(&mask.to_le_bytes());
+        }
+        // LCS_sRGB, the endpoints and gamma that follow are ignored with it
+        output.extend_from_slice(b"BGRs");
+        output.extend_from_slice(&[0; 36 + 12]);
+    }
+
+    let padding = stride - width as usize * bits_per_pixel as usize / 8;
+    if width > 0 {
+        for row in pixels.chunks_exact(width as usize).rev() {
+            for pixel in row {
+                let (r, g, b, a) = pixel.extract();
+                output.extend_from_slice(&[b, g, r, a][..bits_per_pixel as usize / 8]);
+            }
+            output.extend_from_slice(&[0; 3][..padding]);
+        }
+    }
+    output
+}
+
+impl Bmp {
+    #[inline(always)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    #[inline(always)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    /// Bits per pixel in the file, 1, 4, 8, 16, 24 or 32
+    #[inline(always)]
+    pub fn bits_per_pixel(&self) -> u16 {
+        self.bits_per_pixel
+    }
+    /// The compression field: 0 `BI_RGB`, 1 `BI_RLE8`, 2 `BI_RLE4`, 3 `BI_BITFIELDS` or 6
+    /// `BI_ALPHABITFIELDS`
+    #[inline(always)]
+    pub fn compression(&self) -> u32 {
+        self.compression
+    }
+    /// Whether the rows were stored top-down, the pixels are top-down either way
+    #[inline(always)]
+    pub fn top_down(&self) -> bool {
+        self.top_down
+    }
+    /// Whether the file has an alpha mask
+    #[inline(always)]
+    pub fn has_alpha(&self) -> bool {
+        self.has_alpha
+    }
+    /// The pixels in row-major order, from the top row down.
+    #[inline(always)]
+    pub fn pixels(&self) -> &[Pixel] {
+        &self.pixels
+    }
+
+    pub fn to_pixels(&self) -> Vec<Pixel> {
+        self.pixels.clone()
+    }
+}
@@ -1,11 +1,16 @@
-use clap::{Command, arg, command, value_parser};
+use clap::{ArgMatches, Command, arg, command, value_parser};
 use std::io::Write;
 use std::{fs, path::PathBuf};
 
-use crate::qoi::encode;
-use crate::qoi::decoder::decode_to_p6_8_bit;
-use crate::qoi::encoder::bytestream_to_pixelstream;
-use crate::qoi::types::{DynamicPixel, Pixel, };
+use crate::qoi::bmp::write_bmp;
+use crate::qoi::decoder::{decode_16, decode_region, Decoder};
+use crate::qoi::encoder::{bytestream_to_pixelstream, Encoder};
+use crate::qoi::netpbm::{rescale, write_pam, write_pam_16, write_ppm, write_ppm_16};
+use crate::qoi::options::EncoderOptions;
+use crate::qoi::png::{write_png, write_png_16};
+use crate::qoi::tiled::encode_tiled;
+use crate::qoi::types::{Channels, ColorSpace, Pixel, QoiHeader};
+use crate::qoi::types16::Pixel16;
 
 pub fn cli() {
     let matches = command!()
@@ -14,12 +19,16 @@ pub fn cli() {
             Command::new("encode")
                 .about("encodes an image according to the QOI specification")
                 .arg(
-                    arg!(-i --input <FILE> "input file, from which  to read the data")
+                    arg!(-i --input <FILE> "input file, a PNG, BMP or Netpbm image, from which  to read the data")
                         .required(true)
                         .value_parser(value_parser!(PathBuf)),
                 ).arg(arg!(-o --output <FILE> "output file, to which the output is written, defaulted to stdout")
                         .required(false)
                         .value_parser(value_parser!(PathBuf)))
+                .arg(arg!(-t --threads <N> "number of threads encoding the image, each on a band of rows")
+                        .required(false)
+                        .default_value("1")
+                        .value_parser(value_parser!(usize)))
         )
         .subcommand(
             Command::new("decode")
@@ -28,39 +37,135 @@ pub fn cli() {
                     arg!(-i --input <FILE> "input file, from which  to read the data")
                         .required(true)
                         .value_parser(value_parser!(PathBuf)),
-                ).arg(arg!(-o --output <FILE> "output file, to which the output is written, defaulted to stdout")
+                ).arg(arg!(-o --output <FILE> "output file, to which the output is written, defaulted to stdout, picks PNG, BMP or PAM from a .png, .bmp or .pam extension and PPM otherwise")
                         .required(false)
                         .value_parser(value_parser!(PathBuf)))
+                .arg(arg!(--crop <REGION> "only decodes the region x,y,width,height")
+                        .required(false)
+                        .num_args(1)
+                        .value_delimiter(',')
+                        .value_parser(value_parser!(u32)))
 
         )
+        .subcommand(
+            Command::new("tile")
+                .about("builds a tiled file, in which each tile is a QOI image, from a .qoi, PNG, BMP or Netpbm file")
+                .arg(
+                    arg!(-i --input <FILE> "input file, from which  to read the data")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                ).arg(arg!(-o --output <FILE> "output file, to which the output is written, defaulted to stdout")
+                        .required(false)
+                        .value_parser(value_parser!(PathBuf)))
+                .arg(arg!(-s --"tile-size" <N> "width and height of the tiles")
+                        .required(false)
+                        .default_value("256")
+                        .value_parser(value_parser!(u32)))
+        )
         .get_matches();
     match matches.subcommand(){
         Some(("encode", sub_m)) => {
             let input: &PathBuf = sub_m.get_one("input").expect("Couldn't open the input file correctly");
             let buffer = fs::read(input).unwrap_or_else(|err| panic!("Error reading the input file: {}", err));
-            let mut array: [DynamicPixel; 64] = [DynamicPixel::Pixel(Pixel::default());64];
-            let bytestream = bytestream_to_pixelstream(&buffer);
-            let contents: Vec<u8> = encode(&bytestream.0, &mut array, bytestream.1, bytestream.2, bytestream.3).expect("Error in the encoding");
+            let bytestream = bytestream_to_pixelstream(&buffer).unwrap_or_else(|err| panic!("Error reading the input image: {}", err));
+            let threads: usize = *sub_m.get_one("threads").unwrap();
+            let mut encoder = Encoder::with_options(EncoderOptions::new().threads(threads));
+            let contents: &[u8] = encoder.encode_dynamic(&bytestream.0, bytestream.1, bytestream.2, bytestream.3).unwrap_or_else(|err| panic!("Error in the encoding: {}", err));
             if let Some(output) = sub_m.get_one::<PathBuf>("output"){
                 fs::write(output, contents).expect("Error writing into the output file") 
             } else {
-                std::io::stdout().write_all(&contents).expect("Error writing data into stdout")
+                std::io::stdout().write_all(contents).expect("Error writing data into stdout")
             }
             
         }
         Some(("decode", sub_m)) => {
             let input: &PathBuf = sub_m.get_one("input").unwrap();
             let buffer = fs::read(input).unwrap_or_else(|err|panic!("Error reading the input file: {}", err));
-            let contents: Vec<u8> = decode_to_p6_8_bit(&buffer, &mut[Pixel::default();64]);
+            // the output format follows the extension, PNG, BMP and PAM keep the alpha, PPM is written everywhere else
+            let extension = sub_m.get_one::<PathBuf>("output").and_then(|output| output.extension()).map(|ext| ext.to_ascii_lowercase());
+            let format = extension.as_ref().and_then(|ext| ext.to_str()).unwrap_or("ppm");
+            if buffer.starts_with(b"qo16") {
+                if sub_m.contains_id("crop") {
+                    panic!("--crop only works on 8-bit images");
+                }
+                let decoded = decode_16(&buffer, &mut [Pixel16::default(); 64]).unwrap_or_else(|err| panic!("Error in the decoding: {}", err));
+                let chanels = Channels::try_from(decoded.3).unwrap();
+                let contents = match format {
+                    "png" => write_png_16(&decoded.0, decoded.1, decoded.2, chanels),
+                    // BMP only holds 8 bits per sample
+                    "bmp" => {
+                        let scale = |sample| rescale(sample, u16::MAX, 255) as u8;
+                        let pixels: Vec<Pixel> = decoded.0.iter().map(|pixel| {
+                            let (r, g, b, a) = pixel.extract();
+                            Pixel::new(scale(r), scale(g), scale(b), scale(a))
+                        }).collect();
+                        write_bmp(&pixels, decoded.1, decoded.2, chanels)
+                    }
+                    "pam" => write_pam_16(&decoded.0, decoded.1, decoded.2, chanels),
+                    _ => write_ppm_16(&decoded.0, decoded.1, decoded.2),
+                };
+                write_output(sub_m, &contents);
+                return;
+            }
+            let header = QoiHeader::parse(&buffer).unwrap_or_else(|err| panic!("Error in the decoding: {}", err));
+            let mut decoder = Decoder::new();
+            let region: Vec<Pixel>;
+            let (pixels, width, height): (&[Pixel], u32, u32) = if let Some(crop) = sub_m.get_many::<u32>("crop") {
+                let crop: Vec<u32> = crop.copied().collect();
+                let [x, y, width, height] = crop[..] else {
+                    panic!("--crop takes 4 numbers: x,y,width,height")
+                };
+                region = decode_region(&buffer, x, y, width, height).unwrap_or_else(|err| panic!("Error in the decoding: {}", err));
+                (&region, width, height)
+            } else {
+                let (header, pixels) = decoder.decode(&buffer).unwrap_or_else(|err| panic!("Error in the decoding: {}", err));
+                (pixels, header.width(), header.height())
+            };
+            let chanels = Channels::try_from(header.chanels()).unwrap();
+            let contents = match format {
+                "png" => write_png(pixels, width, height, chanels),
+                "bmp" => write_bmp(pixels, width, height, chanels),
+                "pam" => write_pam(pixels, width, height, chanels),
+                _ => write_ppm(pixels, width, height),
+            };
+            write_output(sub_m, &contents);
+
+        }
+        Some(("tile", sub_m)) => {
+            let input: &PathBuf = sub_m.get_one("input").expect("Couldn't open the input file correctly");
+            let buffer = fs::read(input).unwrap_or_else(|err| panic!("Error reading the input file: {}", err));
+            let tile_size: u32 = *sub_m.get_one("tile-size").unwrap();
+            let (pixels, width, height, options) = if buffer.starts_with(b"qoif") {
+                let mut decoder = Decoder::new();
+                let (header, pixels) = decoder.decode(&buffer).unwrap_or_else(|err| panic!("Error in the decoding: {}", err));
+                let options = EncoderOptions::new()
+                    .chanels(Channels::try_from(header.chanels()).unwrap())
+                    .colorspace(ColorSpace::try_from(header.colorspace()).unwrap());
+                (pixels.to_vec(), header.width(), header.height(), options)
+            } else {
+                let bytestream = bytestream_to_pixelstream(&buffer).unwrap_or_else(|err| panic!("Error reading the input image: {}", err));
+                let pixels = bytestream.0.iter().map(|pixel| pixel.as_pixel()).collect::<Result<Vec<Pixel>, _>>()
+                    .unwrap_or_else(|err| panic!("Only 8-bit images can be tiled: {}", err));
+                (pixels, bytestream.1, bytestream.2, EncoderOptions::new())
+            };
+            let contents = encode_tiled(&pixels, width, height, tile_size, &options).unwrap_or_else(|err| panic!("Error in the encoding: {}", err));
             if let Some(output) = sub_m.get_one::<PathBuf>("output"){
                 fs::write(output, contents).expect("Error writing into the output file") 
             } else {
                 std::io::stdout().write_all(&contents).expect("Error writing data into stdout")
             }
-
         }
         _ => {
             eprintln!("Command not found, use the --help flag to understand how the command works")
         }
     }
 }
+
+// writes `contents` to the --output file, or to stdout without one
+fn write_output(sub_m: &ArgMatches, contents: &[u8]) {
+    if let Some(output) = sub_m.get_one::<PathBuf>("output"){
+        fs::write(output, contents).expect("Error writing into the output file")
+    } else {
+        std::io::stdout().write_all(contents).expect("Error writing data into stdout")
+    }
+}
@@ -1,122 +1,980 @@
-use crate::qoi::types::Pixel;
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::{ErrorKind, Read};
+#[cfg(feature = "std")]
+use std::path::Path;
 
-// returns the pixel stream, the width, the height, the chanels and the colorspace respectively
-pub fn decode(bytestream: &[u8], array: &mut [Pixel; 64]) -> (Vec<Pixel>, u32, u32, u8, u8) {
-    assert!(bytestream[0] == 0x71);
-    assert!(bytestream[1] == 0x6F);
-    assert!(bytestream[2] == 0x69);
-    assert!(bytestream[3] == 0x66);
-    let width = u32::from_be_bytes([bytestream[4], bytestream[5], bytestream[6], bytestream[7]]);
-    let height = u32::from_be_bytes([bytestream[8], bytestream[9], bytestream[10], bytestream[11]]);
-    let chanels = bytestream[12];
-    let colorspace = bytestream[13];
-
-    //PERFORMANCE: extremely slow loop, O(N) on a massive array of bytes, temporary solution
-    let mut i = 14;
-    while bytestream[i..i + 8] != [0, 0, 0, 0, 0, 0, 0, 1] {
-        i += 1
-    }
-    let mut pixel_stream: Vec<Pixel> = Vec::with_capacity((i - 14) >> 2); // every pixel is 4 bytes long
-    array[0] = Pixel::new(0, 0, 0, 1);
-    array[1..64].copy_from_slice(&[Pixel::new(0, 0, 0, 0); 63]);
+use crate::qoi::error::QoiError;
+use crate::qoi::limits::DecodeLimits;
+use crate::qoi::netpbm::write_ppm_16;
+use crate::qoi::types::{Pixel, PixelLayout, QoiHeader};
+use crate::qoi::types16::{Pixel16, QoiHeader16};
 
-    let mut j = 14;
-    let mut prev = Pixel::new(0, 0, 0, 255);
-    while j < i {
-        if bytestream[j] >> 6 == 0 {
-            // it is a 1-bit chunk and the tag is 00, so its QoiOpIndex
-            let h = bytestream[j] & 0b00111111;
-            prev = array[h as usize];
-            pixel_stream.push(prev);
-            j += 1;
-            continue;
+const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+/// Reads and validates only the 14-byte header from `reader`, the cheap way to get at the
+/// metadata of an image.
+#[cfg(feature = "std")]
+pub fn probe<R: Read>(mut reader: R) -> Result<QoiHeader, QoiError> {
+    let mut header = [0u8; 14];
+    let mut len = 0;
+    while len < header.len() {
+        match reader.read(&mut header[len..]) {
+            Ok(0) => return Err(QoiError::TruncatedHeader(len)),
+            Ok(n) => len += n,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    QoiHeader::parse(&header)
+}
+
+/// Same as [`probe`] on the file at `path`.
+#[cfg(feature = "std")]
+pub fn probe_file<P: AsRef<Path>>(path: P) -> Result<QoiHeader, QoiError> {
+    probe(File::open(path)?)
+}
+
+/// The state a decoder carries from one chunk to the next: the color index, the previous pixel
+/// and the pixels left in the current run.
+pub(crate) struct DecodeState {
+    array: [Pixel; 64],
+    prev: Pixel,
+    run: u8,
+}
+
+impl DecodeState {
+    pub(crate) fn new() -> Self {
+        Self {
+            // the spec requires a zero-initialized index
+            array: [Pixel::new(0, 0, 0, 0); 64],
+            prev: Pixel::new(0, 0, 0, 255),
+            run: 0,
         }
-        if bytestream[j] >> 6 == 1 {
+    }
+
+    /// Produces the next pixel, reading a chunk from `bytestream[*j..]` unless a run is pending.
+    /// A chunk running past the end of `bytestream` is reported at its offset in `bytestream`.
+    #[inline(always)]
+    pub(crate) fn next_pixel(
+        &mut self,
+        bytestream: &[u8],
+        j: &mut usize,
+    ) -> Result<Pixel, QoiError> {
+        if self.run > 0 {
+            self.run -= 1;
+            return Ok(self.prev);
+        }
+        let tag = *bytestream
+            .get(*j)
+            .ok_or(QoiError::TruncatedChunk { offset: *j })?;
+        let len = match tag {
+            0xFE => 4,
+            0xFF => 5,
+            _ if tag >> 6 == 2 => 2,
+            _ => 1,
+        };
+        if *j + len > bytestream.len() {
+            return Err(QoiError::TruncatedChunk { offset: *j });
+        }
+        let chunk = &bytestream[*j..*j + len];
+        let extracted_prev = self.prev.extract();
+        if tag == 0xFE {
+            self.prev = Pixel::new(chunk[1], chunk[2], chunk[3], extracted_prev.3);
+        } else if tag == 0xFF {
+            self.prev = Pixel::new(chunk[1], chunk[2], chunk[3], chunk[4]);
+        } else if tag >> 6 == 0 {
+            // it is a 1-bit chunk and the tag is 00, so its QoiOpIndex
+            self.prev = self.array[(tag & 0b00111111) as usize];
+        } else if tag >> 6 == 1 {
             // it is a 1-bit chunk and the tag is 01, so its QoiOpDiff
-            let extracted_prev = prev.extract();
-            let dr = (bytestream[j] >> 4) & 0b11;
-            let dg = (bytestream[j] >> 2) & 0b11;
-            let db = bytestream[j] & 0b11;
-            prev = Pixel::new(
+            let dr = (tag >> 4) & 0b11;
+            let dg = (tag >> 2) & 0b11;
+            let db = tag & 0b11;
+            self.prev = Pixel::new(
                 (extracted_prev.0 as i32 + dr as i32 - 2i32) as u8,
                 (extracted_prev.1 as i32 + dg as i32 - 2i32) as u8,
                 (extracted_prev.2 as i32 + db as i32 - 2i32) as u8,
                 extracted_prev.3,
             );
-            let h = prev.hash() as usize;
-            array[h] = prev;
-            pixel_stream.push(prev);
-            j += 1;
-            continue;
-        }
-        if bytestream[j] >> 6 == 3 && !(62..=63).contains(&(bytestream[j] & 0b00111111)) {
-            for _ in 0..(bytestream[j] & 0b00111111) + 1 {
-                pixel_stream.push(prev)
-            }
-            j += 1;
-            continue;
-        }
-        if bytestream[j] >> 6 == 2 {
-            let diff_green = bytestream[j] & 0b00111111;
-            let dr_dg = bytestream[j + 1] & 0b11110000;
-            let db_dg = bytestream[j + 1] & 0b00001111;
-            let extracted_prev = prev.extract();
-            prev = Pixel::new(
+        } else if tag >> 6 == 2 {
+            let diff_green = tag & 0b00111111;
+            let dr_dg = chunk[1] >> 4;
+            let db_dg = chunk[1] & 0b00001111;
+            self.prev = Pixel::new(
                 (dr_dg as i32 + extracted_prev.0 as i32 + diff_green as i32 - 40i32) as u8,
                 (extracted_prev.1 as i32 + diff_green as i32 - 32i32) as u8,
                 (db_dg as i32 + extracted_prev.2 as i32 + diff_green as i32 - 40i32) as u8,
                 extracted_prev.3,
             );
-            let h = prev.hash() as usize;
-            array[h] = prev;
-            pixel_stream.push(prev);
-            j += 2;
-            continue;
+        } else {
+            // the run length is stored with a bias of -1, this pixel is the first of the run
+            self.run = tag & 0b00111111;
         }
-        if bytestream[j] == 0xFE {
-            let extracted_prev = prev.extract();
-            prev = Pixel::new(
-                bytestream[j + 1],
-                bytestream[j + 2],
-                bytestream[j + 3],
-                extracted_prev.3,
-            );
-            let h = prev.hash() as usize;
-            array[h] = prev;
+        // every pixel seen goes into the index, including the ones of a run
+        self.array[self.prev.hash() as usize] = self.prev;
+        *j += len;
+        Ok(self.prev)
+    }
 
-            pixel_stream.push(prev);
-            j += 4;
-            continue;
+    /// Decodes pixels until `out` is full, the pixels of a run are written all at once.
+    pub(crate) fn fill(
+        &mut self,
+        bytestream: &[u8],
+        j: &mut usize,
+        out: &mut [Pixel],
+    ) -> Result<(), QoiError> {
+        let mut k = 0;
+        while k < out.len() {
+            out[k] = self.next_pixel(bytestream, j)?;
+            k += 1;
+            let run = (self.run as usize).min(out.len() - k);
+            out[k..k + run].fill(self.prev);
+            self.run -= run as u8;
+            k += run;
         }
-        if bytestream[j] == 0xFF {
-            prev = Pixel::new(
-                bytestream[j + 1],
-                bytestream[j + 2],
-                bytestream[j + 3],
-                bytestream[j + 4],
-            );
-            let h = prev.hash() as usize;
-            array[h] = prev;
+        Ok(())
+    }
 
-            pixel_stream.push(prev);
-            j += 5;
-            continue;
-        } else {
-            eprintln!("Error! Non-aligned chunks");
+    /// Steps over the next `count` pixels without writing them anywhere.
+    pub(crate) fn skip(
+        &mut self,
+        bytestream: &[u8],
+        j: &mut usize,
+        mut count: u64,
+    ) -> Result<(), QoiError> {
+        while count > 0 {
+            self.next_pixel(bytestream, j)?;
+            count -= 1;
+            let run = (self.run as u64).min(count);
+            self.run -= run as u8;
+            count -= run;
         }
+        Ok(())
+    }
+}
+
+/// What a decoder does with a stream that doesn't end exactly where the last pixel does.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DecodeMode {
+    /// The last pixel must be followed by the end marker and nothing else, a run may not spill
+    /// past `width * height`
+    #[default]
+    Strict,
+    /// Decoding stops after `width * height` pixels, whatever comes next is ignored like
+    /// the reference `qoi.h` does
+    Lenient,
+}
+
+// the shortest stream holding `pixel_count` pixels is nothing but runs of 62, a header claiming
+// more than that is rejected before anything gets allocated for it
+pub(crate) fn check_plausible(pixel_count: u64, len: usize) -> Result<(), QoiError> {
+    if pixel_count > (len as u64).saturating_sub(14) * 62 {
+        return Err(QoiError::TruncatedChunk { offset: len });
+    }
+    Ok(())
+}
+
+// checks what follows the last pixel, `j` is the offset right after its chunk and `run` the
+// pixels its run would still have produced
+fn check_end(
+    bytestream: &[u8],
+    j: usize,
+    run: u8,
+    expected: u64,
+    mode: DecodeMode,
+) -> Result<(), QoiError> {
+    if mode == DecodeMode::Lenient {
+        return Ok(());
+    }
+    if run > 0 {
+        return Err(QoiError::PixelCountMismatch {
+            expected,
+            actual: expected + run as u64,
+        });
+    }
+    if bytestream.get(j..j + 8) != Some(&END_MARKER[..]) {
+        return Err(QoiError::MissingEndMarker);
+    }
+    if bytestream.len() > j + 8 {
+        return Err(QoiError::TrailingData { offset: j + 8 });
+    }
+    Ok(())
+}
+
+// returns the pixel stream, the width, the height, the chanels and the colorspace respectively
+pub fn decode(
+    bytestream: &[u8],
+    array: &mut [Pixel; 64],
+) -> Result<(Vec<Pixel>, u32, u32, u8, u8), QoiError> {
+    decode_with_limits(
+        bytestream,
+        array,
+        &DecodeLimits::default(),
+        DecodeMode::Strict,
+    )
+}
+
+/// Same as [`decode`], with the header checked against `limits` before decoding and the end of
+/// the stream checked as `mode` says.
+pub fn decode_with_limits(
+    bytestream: &[u8],
+    array: &mut [Pixel; 64],
+    limits: &DecodeLimits,
+    mode: DecodeMode,
+) -> Result<(Vec<Pixel>, u32, u32, u8, u8), QoiError> {
+    let mut state = DecodeState::new();
+    let mut pixel_stream = Vec::new();
+    let header = decode_pixels(&mut state, bytestream, limits, mode, &mut pixel_stream)?;
+    *array = state.array;
+
+    Ok((
+        pixel_stream,
+        header.width(),
+        header.height(),
+        header.chanels(),
+        header.colorspace(),
+    ))
+}
+
+// replaces the contents of `pixels` with the decoded image, `state` is reset first so nothing
+// leaks from a previous image
+fn decode_pixels(
+    state: &mut DecodeState,
+    bytestream: &[u8],
+    limits: &DecodeLimits,
+    mode: DecodeMode,
+    pixels: &mut Vec<Pixel>,
+) -> Result<QoiHeader, QoiError> {
+    let header = QoiHeader::parse(bytestream)?;
+    limits.check(header.width(), header.height(), size_of::<Pixel>())?;
+    let expected = header.pixel_count();
+    check_plausible(expected, bytestream.len())?;
+
+    // exactly width * height pixels in a single pass, the end marker is only looked for after the
+    // last one since its bytes can just as well show up inside a QoiOpRGB(A)
+    pixels.clear();
+    pixels.resize(expected as usize, Pixel::default());
+    *state = DecodeState::new();
+    let mut j = 14;
+    state.fill(bytestream, &mut j, pixels)?;
+    check_end(bytestream, j, state.run, expected, mode)?;
+    Ok(header)
+}
+
+/// Decodes the `width` by `height` region at `x`, `y`, the pixels are in row-major order with a
+/// row of `width`.
+///
+/// The pixels before the region are stepped over without being stored and decoding stops right
+/// after the last one of the region, the rest of the stream isn't looked at.
+pub fn decode_region(
+    bytestream: &[u8],
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> Result<Vec<Pixel>, QoiError> {
+    decode_region_with_limits(bytestream, x, y, width, height, &DecodeLimits::default())
+}
+
+/// Same as [`decode_region`], with the region checked against `limits`.
+pub fn decode_region_with_limits(
+    bytestream: &[u8],
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    limits: &DecodeLimits,
+) -> Result<Vec<Pixel>, QoiError> {
+    let header = QoiHeader::parse(bytestream)?;
+    let inside = |start: u32, len: u32, end: u32| start.checked_add(len).is_some_and(|e| e <= end);
+    if !inside(x, width, header.width()) || !inside(y, height, header.height()) {
+        return Err(QoiError::RegionOutOfBounds {
+            x,
+            y,
+            width,
+            height,
+        });
     }
+    limits.check(width, height, size_of::<Pixel>())?;
+    if width == 0 || height == 0 {
+        return Ok(Vec::new());
+    }
+    let stride = header.width() as u64;
+    // pixels up to the last one of the region, the stream must at least be able to hold them
+    let last = (y + height - 1) as u64 * stride + (x + width) as u64;
+    check_plausible(last, bytestream.len())?;
 
-    (pixel_stream, width, height, chanels, colorspace)
+    let mut region = vec![Pixel::default(); width as usize * height as usize];
+    let mut state = DecodeState::new();
+    let mut j = 14;
+    state.skip(bytestream, &mut j, y as u64 * stride + x as u64)?;
+    for (i, row) in region.chunks_exact_mut(width as usize).enumerate() {
+        if i > 0 {
+            state.skip(bytestream, &mut j, stride - width as u64)?;
+        }
+        state.fill(bytestream, &mut j, row)?;
+    }
+    Ok(region)
 }
 
-pub fn decode_to_p6_8_bit(bytestream: &[u8], array: &mut [Pixel; 64]) -> Vec<u8> {
-    let decoded = decode(bytestream, array);
+pub fn decode_to_p6_8_bit(bytestream: &[u8], array: &mut [Pixel; 64]) -> Result<Vec<u8>, QoiError> {
+    let decoded = decode(bytestream, array)?;
     let mut output: Vec<u8> = Vec::new();
-    output.extend_from_slice(format!("P6\n{} {}\n255\n", decoded.1, decoded.2,).as_bytes());
+    output.extend_from_slice(alloc::format!("P6\n{} {}\n255\n", decoded.1, decoded.2,).as_bytes());
     let mut extracted: (u8, u8, u8, u8);
     for pixel in decoded.0 {
         extracted = pixel.extract();
         output.extend_from_slice(&[extracted.0, extracted.1, extracted.2])
     }
-    output
+    Ok(output)
+}
+
+/// Decodes a QOI16 stream into a P6 with a maxval of 65535, the alpha is dropped.
+pub fn decode_to_p6_16_bit(
+    bytestream: &[u8],
+    array: &mut [Pixel16; 64],
+) -> Result<Vec<u8>, QoiError> {
+    let decoded = decode_16(bytestream, array)?;
+    Ok(write_ppm_16(&decoded.0, decoded.1, decoded.2))
+}
+
+// returns the pixel stream, the width, the height, the chanels and the colorspace respectively,
+// see crate::qoi::types16 for the QOI16 format
+pub fn decode_16(
+    bytestream: &[u8],
+    array: &mut [Pixel16; 64],
+) -> Result<(Vec<Pixel16>, u32, u32, u8, u8), QoiError> {
+    decode_16_with_limits(
+        bytestream,
+        array,
+        &DecodeLimits::default(),
+        DecodeMode::Strict,
+    )
+}
+
+/// Same as [`decode_16`], with the header checked against `limits` before decoding and the end
+/// of the stream checked as `mode` says.
+pub fn decode_16_with_limits(
+    bytestream: &[u8],
+    array: &mut [Pixel16; 64],
+    limits: &DecodeLimits,
+    mode: DecodeMode,
+) -> Result<(Vec<Pixel16>, u32, u32, u8, u8), QoiError> {
+    let header = QoiHeader16::parse(bytestream)?;
+    limits.check(header.width(), header.height(), size_of::<Pixel16>())?;
+    check_plausible(header.pixel_count(), bytestream.len())?;
+    let mut pixel_stream: Vec<Pixel16> = Vec::with_capacity(header.pixel_count() as usize);
+    *array = [Pixel16::default(); 64];
+    let mut prev = Pixel16::new(0, 0, 0, u16::MAX);
+    let mut run = 0;
+    let mut j = 14;
+    let sample = |j: usize| u16::from_be_bytes([bytestream[j], bytestream[j + 1]]);
+    while (pixel_stream.len() as u64) < header.pixel_count() {
+        if run > 0 {
+            run -= 1;
+            pixel_stream.push(prev);
+            continue;
+        }
+        let tag = *bytestream
+            .get(j)
+            .ok_or(QoiError::TruncatedChunk { offset: j })?;
+        let len = match tag {
+            0xFE => 7,
+            0xFF => 9,
+            _ if tag >> 6 == 2 => 3,
+            _ => 1,
+        };
+        if j + len > bytestream.len() {
+            return Err(QoiError::TruncatedChunk { offset: j });
+        }
+        let extracted_prev = prev.extract();
+        if tag == 0xFE {
+            prev = Pixel16::new(
+                sample(j + 1),
+                sample(j + 3),
+                sample(j + 5),
+                extracted_prev.3,
+            );
+        } else if tag == 0xFF {
+            prev = Pixel16::new(sample(j + 1), sample(j + 3), sample(j + 5), sample(j + 7));
+        } else if tag >> 6 == 0 {
+            prev = array[(tag & 0b00111111) as usize];
+        } else if tag >> 6 == 1 {
+            let dr = ((tag >> 4) & 0b11) as u16;
+            let dg = ((tag >> 2) & 0b11) as u16;
+            let db = (tag & 0b11) as u16;
+            prev = Pixel16::new(
+                extracted_prev.0.wrapping_add(dr).wrapping_sub(2),
+                extracted_prev.1.wrapping_add(dg).wrapping_sub(2),
+                extracted_prev.2.wrapping_add(db).wrapping_sub(2),
+                extracted_prev.3,
+            );
+        } else if tag >> 6 == 2 {
+            let bits = u32::from_be_bytes([0, tag, bytestream[j + 1], bytestream[j + 2]]);
+            let diff_green = ((bits >> 12) & 0x3FF) as u16;
+            let dr_dg = ((bits >> 6) & 0x3F) as u16;
+            let db_dg = (bits & 0x3F) as u16;
+            // every bias is removed at once, green's is 512 and red/blue's 32
+            let dg = diff_green.wrapping_sub(512);
+            prev = Pixel16::new(
+                extracted_prev
+                    .0
+                    .wrapping_add(dg)
+                    .wrapping_add(dr_dg)
+                    .wrapping_sub(32),
+                extracted_prev.1.wrapping_add(dg),
+                extracted_prev
+                    .2
+                    .wrapping_add(dg)
+                    .wrapping_add(db_dg)
+                    .wrapping_sub(32),
+                extracted_prev.3,
+            );
+        } else {
+            run = tag & 0b00111111;
+        }
+        array[prev.hash() as usize] = prev;
+        pixel_stream.push(prev);
+        j += len;
+    }
+    check_end(bytestream, j, run, header.pixel_count(), mode)?;
+
+    Ok((
+        pixel_stream,
+        header.width(),
+        header.height(),
+        header.chanels(),
+        header.colorspace(),
+    ))
+}
+
+/// Decodes `bytestream` into interleaved bytes laid out as the header's chanels say: RGBA keeps
+/// the alpha of every pixel, RGB drops it.
+pub fn decode_to_bytes(bytestream: &[u8]) -> Result<(Vec<u8>, QoiHeader), QoiError> {
+    let header = QoiHeader::parse(bytestream)?;
+    let layout = match header.chanels() {
+        3 => PixelLayout::Rgb8,
+        _ => PixelLayout::Rgba8,
+    };
+    DecodeLimits::default().check(header.width(), header.height(), layout.bytes_per_pixel())?;
+    check_plausible(header.pixel_count(), bytestream.len())?;
+    let mut output = vec![0; header.pixel_count() as usize * layout.bytes_per_pixel()];
+    decode_into(bytestream, &mut output, layout)?;
+    Ok((output, header))
+}
+
+/// Decodes `bytestream` straight into `buffer` with the given layout, without allocating. The
+/// first `width * height * layout.bytes_per_pixel()` bytes are written, the rest is untouched.
+pub fn decode_into(
+    bytestream: &[u8],
+    buffer: &mut [u8],
+    layout: PixelLayout,
+) -> Result<QoiHeader, QoiError> {
+    decode_into_with_mode(bytestream, buffer, layout, DecodeMode::Strict)
+}
+
+/// Same as [`decode_into`], with the end of the stream checked as `mode` says.
+pub fn decode_into_with_mode(
+    bytestream: &[u8],
+    buffer: &mut [u8],
+    layout: PixelLayout,
+    mode: DecodeMode,
+) -> Result<QoiHeader, QoiError> {
+    let header = QoiHeader::parse(bytestream)?;
+    let bytes_per_pixel = layout.bytes_per_pixel();
+    // NOTE: no buffer holds u64::MAX bytes, so a size that overflows saturates and never fits
+    let needed = header.pixel_count().saturating_mul(bytes_per_pixel as u64);
+    let actual = buffer.len();
+    // the size is checked and the buffer cut with the same usize, whatever its width
+    let Some(pixels) = usize::try_from(needed)
+        .ok()
+        .and_then(|needed| buffer.get_mut(..needed))
+    else {
+        return Err(QoiError::BufferTooSmall { needed, actual });
+    };
+    check_plausible(header.pixel_count(), bytestream.len())?;
+    let mut state = DecodeState::new();
+    let mut j = 14;
+    for out in pixels.chunks_exact_mut(bytes_per_pixel) {
+        let extracted = state.next_pixel(bytestream, &mut j)?.extract();
+        out.copy_from_slice(
+            &[extracted.0, extracted.1, extracted.2, extracted.3][..bytes_per_pixel],
+        );
+    }
+    check_end(bytestream, j, state.run, header.pixel_count(), mode)?;
+    Ok(header)
+}
+
+/// A reusable decoder that owns its color index and pixel buffer.
+///
+/// The index is reset for every image and the buffer only ever grows, so decoding images of
+/// similar sizes in a loop doesn't allocate after the first one.
+pub struct Decoder {
+    limits: DecodeLimits,
+    mode: DecodeMode,
+    state: DecodeState,
+    pixels: Vec<Pixel>,
+    output: Vec<u8>,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self {
+            limits: DecodeLimits::default(),
+            mode: DecodeMode::Strict,
+            state: DecodeState::new(),
+            pixels: Vec::new(),
+            output: Vec::new(),
+        }
+    }
+
+    pub fn with_limits(mut self, limits: DecodeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_mode(mut self, mode: DecodeMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Decodes `bytestream`, the pixels stay valid until the next call.
+    pub fn decode(&mut self, bytestream: &[u8]) -> Result<(QoiHeader, &[Pixel]), QoiError> {
+        let header = decode_pixels(
+            &mut self.state,
+            bytestream,
+            &self.limits,
+            self.mode,
+            &mut self.pixels,
+        )?;
+        Ok((header, &self.pixels))
+    }
+
+    /// Same as [`decode_to_p6_8_bit`], the file stays valid until the next call.
+    pub fn decode_to_p6_8_bit(&mut self, bytestream: &[u8]) -> Result<&[u8], QoiError> {
+        let header = self.decode(bytestream)?.0;
+        let output = &mut self.output;
+        output.clear();
+        output.extend_from_slice(
+            alloc::format!("P6\n{} {}\n255\n", header.width(), header.height()).as_bytes(),
+        );
+        for pixel in &self.pixels {
+            let extracted = pixel.extract();
+            output.extend_from_slice(&[extracted.0, extracted.1, extracted.2]);
+        }
+        Ok(output)
+    }
+}
+
+// large enough to amortize the reads, the biggest chunk (QoiOpRGBA) is 5 bytes long
+#[cfg(feature = "std")]
+const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Decodes a QOI stream incrementally from any [`Read`]er.
+///
+/// The header is parsed up front, the pixels are then produced one at a time or one scanline at
+/// a time. Memory use is bounded by an internal read buffer and a single scanline, whatever the
+/// size of the image.
+#[cfg(feature = "std")]
+pub struct QoiDecoder<R: Read> {
+    reader: R,
+    header: QoiHeader,
+    state: DecodeState,
+    buffer: Vec<u8>,
+    start: usize,
+    end: usize,
+    // bytes of the stream that came before `buffer[0]`
+    consumed: u64,
+    eof: bool,
+    remaining: u64,
+    // whether the end of the stream has been looked at, an image without pixels still has one
+    end_checked: bool,
+    row: Vec<Pixel>,
+    mode: DecodeMode,
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> QoiDecoder<R> {
+    /// Reads and validates the 14-byte header, no pixel data is read yet.
+    pub fn new(reader: R) -> Result<Self, QoiError> {
+        Self::with_limits(reader, &DecodeLimits::default())
+    }
+
+    /// Same as [`QoiDecoder::new`], with the header checked against `limits`.
+    pub fn with_limits(mut reader: R, limits: &DecodeLimits) -> Result<Self, QoiError> {
+        let header = probe(&mut reader)?;
+        limits.check(header.width(), header.height(), size_of::<Pixel>())?;
+        Ok(Self {
+            reader,
+            header,
+            state: DecodeState::new(),
+            buffer: vec![0; STREAM_BUFFER_SIZE],
+            start: 0,
+            end: 0,
+            consumed: 14,
+            eof: false,
+            remaining: header.pixel_count(),
+            end_checked: false,
+            row: Vec::new(),
+            mode: DecodeMode::Strict,
+        })
+    }
+
+    /// Sets how the end of the stream is checked, in [`DecodeMode::Strict`] the reader must run
+    /// dry right after the end marker.
+    pub fn with_mode(mut self, mode: DecodeMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn header(&self) -> &QoiHeader {
+        &self.header
+    }
+
+    pub fn width(&self) -> u32 {
+        self.header.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.header.height()
+    }
+
+    pub fn chanels(&self) -> u8 {
+        self.header.chanels()
+    }
+
+    pub fn colorspace(&self) -> u8 {
+        self.header.colorspace()
+    }
+
+    /// Number of pixels not yet decoded.
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    // makes sure at least `wanted` bytes are buffered, unless the reader runs dry first
+    fn fill(&mut self, wanted: usize) -> Result<(), QoiError> {
+        if self.end - self.start >= wanted || self.eof {
+            return Ok(());
+        }
+        self.buffer.copy_within(self.start..self.end, 0);
+        self.consumed += self.start as u64;
+        self.end -= self.start;
+        self.start = 0;
+        while self.end < wanted {
+            match self.reader.read(&mut self.buffer[self.end..]) {
+                Ok(0) => {
+                    self.eof = true;
+                    break;
+                }
+                Ok(n) => self.end += n,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(())
+    }
+
+    // offsets reported by `DecodeState` are relative to the buffer, make them absolute
+    fn locate(&self, err: QoiError) -> QoiError {
+        match err {
+            QoiError::TruncatedChunk { offset } => QoiError::TruncatedChunk {
+                offset: self.consumed as usize + offset,
+            },
+            err => err,
+        }
+    }
+
+    /// Decodes the next pixel, `Ok(None)` once all `width * height` pixels have been produced and
+    /// the end marker has been checked.
+    pub fn next_pixel(&mut self) -> Result<Option<Pixel>, QoiError> {
+        if self.remaining == 0 {
+            self.check_end_marker()?;
+            return Ok(None);
+        }
+        if self.state.run == 0 {
+            self.fill(5)?;
+        }
+        let mut j = self.start;
+        let pixel = self
+            .state
+            .next_pixel(&self.buffer[..self.end], &mut j)
+            .map_err(|err| self.locate(err))?;
+        self.start = j;
+        self.remaining -= 1;
+        if self.remaining == 0 {
+            self.check_end_marker()?;
+        }
+        Ok(Some(pixel))
+    }
+
+    // only looks at the end of the stream the first time it's called
+    fn check_end_marker(&mut self) -> Result<(), QoiError> {
+        if self.end_checked || self.mode == DecodeMode::Lenient {
+            return Ok(());
+        }
+        self.end_checked = true;
+        // one byte more than the marker, anything there is trailing data
+        self.fill(END_MARKER.len() + 1)?;
+        let offset = self.consumed as usize + self.start;
+        let available = &self.buffer[self.start..self.end];
+        check_end(
+            available,
+            0,
+            self.state.run,
+            self.header.pixel_count(),
+            self.mode,
+        )
+        .map_err(|err| match err {
+            QoiError::TrailingData { offset: at } => QoiError::TrailingData {
+                offset: offset + at,
+            },
+            err => err,
+        })?;
+        self.start += END_MARKER.len();
+        Ok(())
+    }
+
+    /// Fills `pixels` with the next pixels of the image and returns how many were written, which
+    /// is only less than `pixels.len()` at the end of the image.
+    pub fn read_pixels(&mut self, pixels: &mut [Pixel]) -> Result<usize, QoiError> {
+        let mut written = 0;
+        while written < pixels.len() {
+            match self.next_pixel()? {
+                Some(pixel) => pixels[written] = pixel,
+                None => break,
+            }
+            written += 1;
+        }
+        Ok(written)
+    }
+
+    /// Decodes the next scanline into an internal buffer of `width` pixels.
+    pub fn next_row(&mut self) -> Result<Option<&[Pixel]>, QoiError> {
+        if self.remaining == 0 {
+            self.check_end_marker()?;
+            return Ok(None);
+        }
+        let mut row = core::mem::take(&mut self.row);
+        row.resize(self.width() as usize, Pixel::default());
+        let written = self.read_pixels(&mut row);
+        self.row = row;
+        written?;
+        Ok(Some(&self.row))
+    }
+
+    /// Gives the reader back, bytes already read ahead into the internal buffer are lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Iterator for QoiDecoder<R> {
+    type Item = Result<Pixel, QoiError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_pixel() {
+            Ok(pixel) => pixel.map(Ok),
+            Err(err) => {
+                // a failed stream can't be resumed
+                self.remaining = 0;
+                self.end_checked = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Decodes the pixels of an in-memory QOI stream one at a time, as they are asked for.
+///
+/// Nothing is allocated, only the color index and the offset of the next chunk are kept. The
+/// first error ends the iteration.
+pub struct QoiPixels<'a> {
+    bytestream: &'a [u8],
+    header: QoiHeader,
+    state: DecodeState,
+    j: usize,
+    remaining: u64,
+    // whether the end of the stream has been looked at, an image without pixels still has one
+    end_checked: bool,
+    mode: DecodeMode,
+}
+
+impl<'a> QoiPixels<'a> {
+    /// Parses and validates the header, no chunk is decoded yet.
+    pub fn new(bytestream: &'a [u8]) -> Result<Self, QoiError> {
+        Self::with_limits(bytestream, &DecodeLimits::default())
+    }
+
+    /// Same as [`QoiPixels::new`], with the header checked against `limits`.
+    pub fn with_limits(bytestream: &'a [u8], limits: &DecodeLimits) -> Result<Self, QoiError> {
+        let header = QoiHeader::parse(bytestream)?;
+        limits.check(header.width(), header.height(), size_of::<Pixel>())?;
+        check_plausible(header.pixel_count(), bytestream.len())?;
+        Ok(Self {
+            bytestream,
+            header,
+            state: DecodeState::new(),
+            j: 14,
+            remaining: header.pixel_count(),
+            end_checked: false,
+            mode: DecodeMode::Strict,
+        })
+    }
+
+    /// Sets how the end of the stream is checked once the last pixel is produced.
+    pub fn with_mode(mut self, mode: DecodeMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn header(&self) -> &QoiHeader {
+        &self.header
+    }
+
+    /// Number of pixels not yet decoded.
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    /// Decodes the next pixel, `Ok(None)` once all `width * height` pixels have been produced and
+    /// the end of the stream has been checked.
+    pub fn next_pixel(&mut self) -> Result<Option<Pixel>, QoiError> {
+        if self.remaining == 0 {
+            self.check_end()?;
+            return Ok(None);
+        }
+        let pixel = self.state.next_pixel(self.bytestream, &mut self.j)?;
+        self.remaining -= 1;
+        if self.remaining == 0 {
+            self.check_end()?;
+        }
+        Ok(Some(pixel))
+    }
+
+    /// Fills `pixels` with the next pixels of the image and returns how many were written, which
+    /// is only less than `pixels.len()` at the end of the image.
+    pub fn read_pixels(&mut self, pixels: &mut [Pixel]) -> Result<usize, QoiError> {
+        let len = (pixels.len() as u64).min(self.remaining) as usize;
+        if len == 0 {
+            return Ok(0);
+        }
+        // runs are written all at once, a run longer than `pixels` carries over to the next call
+        self.state
+            .fill(self.bytestream, &mut self.j, &mut pixels[..len])?;
+        self.remaining -= len as u64;
+        if self.remaining == 0 {
+            self.check_end()?;
+        }
+        Ok(len)
+    }
+
+    // only looks at the end of the stream the first time it's called
+    fn check_end(&mut self) -> Result<(), QoiError> {
+        if self.end_checked {
+            return Ok(());
+        }
+        self.end_checked = true;
+        check_end(
+            self.bytestream,
+            self.j,
+            self.state.run,
+            self.header.pixel_count(),
+            self.mode,
+        )
+    }
+}
+
+impl Iterator for QoiPixels<'_> {
+    type Item = Result<Pixel, QoiError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_pixel() {
+            Ok(pixel) => pixel.map(Ok),
+            Err(err) => {
+                // a failed stream can't be resumed
+                self.remaining = 0;
+                self.end_checked = true;
+                Some(Err(err))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = usize::try_from(self.remaining).unwrap_or(usize::MAX);
+        (0, Some(remaining))
+    }
+}
+
+/// Decodes an in-memory QOI stream one scanline at a time, only a single row of pixels is ever
+/// held.
+///
+/// [`QoiRows::next_row`] reuses the same row for every call, the [`Iterator`] allocates a `Vec`
+/// for each row it hands out.
+pub struct QoiRows<'a> {
+    pixels: QoiPixels<'a>,
+    row: Vec<Pixel>,
+}
+
+impl<'a> QoiRows<'a> {
+    /// Parses and validates the header, no chunk is decoded yet.
+    pub fn new(bytestream: &'a [u8]) -> Result<Self, QoiError> {
+        Self::with_limits(bytestream, &DecodeLimits::default())
+    }
+
+    /// Same as [`QoiRows::new`], with the header checked against `limits`.
+    pub fn with_limits(bytestream: &'a [u8], limits: &DecodeLimits) -> Result<Self, QoiError> {
+        Ok(Self {
+            pixels: QoiPixels::with_limits(bytestream, limits)?,
+            row: Vec::new(),
+        })
+    }
+
+    /// Sets how the end of the stream is checked once the last row is produced.
+    pub fn with_mode(mut self, mode: DecodeMode) -> Self {
+        self.pixels.mode = mode;
+        self
+    }
+
+    pub fn header(&self) -> &QoiHeader {
+        &self.pixels.header
+    }
+
+    /// Decodes the next scanline into an internal buffer of `width` pixels, the same buffer for
+    /// every row. `None` once every row is through or after the first error.
+    pub fn next_row(&mut self) -> Option<Result<&[Pixel], QoiError>> {
+        if self.pixels.remaining == 0 {
+            return self.pixels.check_end().err().map(Err);
+        }
+        self.row
+            .resize(self.pixels.header.width() as usize, Pixel::default());
+        if let Err(err) = self.pixels.read_pixels(&mut self.row) {
+            // a failed stream can't be resumed
+            self.pixels.remaining = 0;
+            self.pixels.end_checked = true;
+            return Some(Err(err));
+        }
+        Some(Ok(&self.row))
+    }
+}
+
+/// Every row is copied into a `Vec` of its own, use [`QoiRows::next_row`] to go through the image
+/// without allocating.
+impl Iterator for QoiRows<'_> {
+    type Item = Result<Vec<Pixel>, QoiError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_row().map(|row| row.map(<[Pixel]>::to_vec))
+    }
 }
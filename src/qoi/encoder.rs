@@ -1,64 +1,81 @@
+use crate::qoi::bmp;
+use crate::qoi::error::QoiError;
+use crate::qoi::limits::DecodeLimits;
+use crate::qoi::netpbm;
+use crate::qoi::options::{EncoderOptions, Preset};
+use crate::qoi::png;
+use crate::qoi::simd;
 use crate::qoi::types::{
-    DynamicPixel, Pixel, PixelDiff, QoiHeader, QoiOpDiff, QoiOpIndex, QoiOpLuma, QoiOpRGB,
+    Channels, DynamicPixel, Pixel, QoiHeader, QoiOpDiff, QoiOpIndex, QoiOpLuma, QoiOpRGB,
     QoiOpRGBA, QoiOpRun, Range,
 };
-use crate::qoi::types16::Pixel16;
-use std::result::Result;
-use std::string::String;
-use std::vec::Vec;
-
-#[inline(always)]
-fn read_number(bytestream: &[u8], start: &mut usize) -> u32 {
-    while bytestream[*start] == b' ' || bytestream[*start] == b'\n' {
-        *start += 1;
-    }
-    let mut end = *start;
-    while bytestream[end] != b' ' && bytestream[end] != b'\n' {
-        end += 1;
-    }
-    let num = std::str::from_utf8(&bytestream[*start..end])
-        .unwrap()
-        .parse::<u32>()
-        .unwrap();
-    *start = end;
-    num
-}
-// NOTE: this is specific to the ppm (P6 specification) file format, other formats required
-//       different implementations
-pub fn bytestream_to_pixelstream(bytestream: &[u8]) -> (Vec<DynamicPixel>, u32, u32, u32) {
-    if bytestream.starts_with("P6".as_bytes()) {
-        let mut i = 2;
-        let width = read_number(bytestream, &mut i);
-        let height = read_number(bytestream, &mut i);
-        let max_col_val = read_number(bytestream, &mut i);
-        i += 1;
-        let mut image: Vec<DynamicPixel> = Vec::with_capacity((width * height) as usize);
-        if max_col_val <= 256 {
-            while i < (width * height * 3) as usize {
-                image.push(DynamicPixel::Pixel(Pixel::new(
-                    bytestream[i],
-                    bytestream[i + 1],
-                    bytestream[i + 2],
-                    0,
-                )));
-                i += 3;
-            }
-            return (image, width, height, max_col_val);
-        } else {
-            while i < (width * height * 6) as usize {
-                image.push(DynamicPixel::Pixel16(Pixel16::new(
-                    u16::from_be_bytes([bytestream[i], bytestream[i + 1]]),
-                    u16::from_be_bytes([bytestream[i + 2], bytestream[i + 3]]),
-                    u16::from_be_bytes([bytestream[i + 4], bytestream[i + 6]]),
-                    0,
-                )));
-                i += 6;
-            }
-            return (image, width, height, max_col_val);
-        }
+use crate::qoi::types16::{
+    Pixel16, PixelDiff16, QoiHeader16, QoiOpLuma16, QoiOpRGB16, QoiOpRGBA16,
+};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::Write;
+
+const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+/// Reads a PNG, a BMP or any Netpbm image (P1 to P7) into pixels along with its width, height
+/// and the maxval of the pixels, see [`crate::qoi::png`], [`crate::qoi::bmp`] and
+/// [`crate::qoi::netpbm`]. Images with a maxval up to 255 are rescaled to 8 bits with a maxval
+/// of 255, the others to 16 bits with a maxval of 65535.
+pub fn bytestream_to_pixelstream(
+    bytestream: &[u8],
+) -> Result<(Vec<DynamicPixel>, u32, u32, u32), QoiError> {
+    bytestream_to_pixelstream_with_limits(bytestream, &DecodeLimits::default())
+}
+
+/// Same as [`bytestream_to_pixelstream`], with the dimensions checked against `limits` before
+/// anything gets allocated.
+pub fn bytestream_to_pixelstream_with_limits(
+    bytestream: &[u8],
+    limits: &DecodeLimits,
+) -> Result<(Vec<DynamicPixel>, u32, u32, u32), QoiError> {
+    // PNG and BMP are told apart by their magic bytes, anything else goes to the Netpbm reader
+    if bytestream.starts_with(&png::SIGNATURE) {
+        let png = png::read_with_limits(bytestream, limits)?;
+        let (image, max_col_val) =
+            dynamic_pixels(png.maxval(), || png.to_pixels(), || png.to_pixels16());
+        return Ok((image, png.width(), png.height(), max_col_val));
+    }
+    if bytestream.starts_with(&bmp::MAGIC) {
+        let bmp = bmp::read_with_limits(bytestream, limits)?;
+        let image = bmp.pixels().iter().copied().map(DynamicPixel::Pixel).collect();
+        return Ok((image, bmp.width(), bmp.height(), 255));
+    }
+    let netpbm = netpbm::read_with_limits(bytestream, limits)?;
+    let (image, max_col_val) = dynamic_pixels(
+        netpbm.maxval(),
+        || netpbm.to_pixels(),
+        || netpbm.to_pixels16(),
+    );
+    Ok((image, netpbm.width(), netpbm.height(), max_col_val))
+}
+
+// 8-bit pixels for a maxval up to 255 and 16-bit ones above, with the maxval they end up with
+fn dynamic_pixels(
+    maxval: u16,
+    to_pixels: impl FnOnce() -> Vec<Pixel>,
+    to_pixels16: impl FnOnce() -> Vec<Pixel16>,
+) -> (Vec<DynamicPixel>, u32) {
+    if maxval <= 255 {
+        (
+            to_pixels().into_iter().map(DynamicPixel::Pixel).collect(),
+            255,
+        )
+    } else {
+        let max_col_val = u16::MAX as u32;
+        (
+            to_pixels16()
+                .into_iter()
+                .map(DynamicPixel::Pixel16)
+                .collect(),
+            max_col_val,
+        )
     }
-    //NOTE: Add other file formats here
-    (Vec::new(), 0, 0, 0)
 }
 
 pub fn encode(
@@ -67,116 +84,753 @@ pub fn encode(
     width: u32,
     height: u32,
     max_col_val: u32,
-) -> Result<Vec<u8>, String> {
+) -> Result<Vec<u8>, QoiError> {
     if image.is_empty() {
-        return Err("The image parsing deduced the image doesn't abide by our logic".to_string());
+        return Err(QoiError::EmptyImage);
     }
     match image[0] {
         DynamicPixel::Pixel(_) => {
             let image_ = image
                 .iter()
-                .map(|p| p.as_pixel().unwrap())
-                .collect::<Vec<Pixel>>();
+                .map(|p| p.as_pixel().map_err(|_| QoiError::MixedPixelDepth))
+                .collect::<Result<Vec<Pixel>, QoiError>>()?;
 
+            // the index is reset by the encoder, the caller's entries only get overwritten
             let mut array_ = [Pixel::default(); 64];
-            for (i, dp) in array.iter().enumerate().take(64) {
-                array_[i] = dp.as_pixel().expect("expected Pixel variant in array");
+            let encoded = encode_(&image_[..], &mut array_, width, height)?;
+            for (dp, p) in array.iter_mut().zip(array_) {
+                *dp = DynamicPixel::Pixel(p);
             }
-            encode_(&image_[..], &mut array_, width, height)
+            Ok(encoded)
         }
         DynamicPixel::Pixel16(_) => {
             let image_ = image
                 .iter()
-                .map(|p| p.as_pixel16().unwrap())
-                .collect::<Vec<Pixel16>>();
+                .map(|p| p.as_pixel16().map_err(|_| QoiError::MixedPixelDepth))
+                .collect::<Result<Vec<Pixel16>, QoiError>>()?;
 
             let mut array_ = [Pixel16::default(); 64];
-            for (i, dp) in array.iter().enumerate().take(64) {
-                array_[i] = dp.as_pixel16().expect("expected Pixel variant in array");
+            let encoded = encode_16(&image_[..], &mut array_, width, height, max_col_val)?;
+            for (dp, p) in array.iter_mut().zip(array_) {
+                *dp = DynamicPixel::Pixel16(p);
+            }
+            Ok(encoded)
+        }
+    }
+}
+
+/// The state an encoder carries from one pixel to the next: the color index, the previous pixel
+/// and the length of the run being accumulated.
+pub(crate) struct EncodeState {
+    array: [Pixel; 64],
+    prev: Pixel,
+    run: u8,
+    keep_alpha: bool,
+    use_index: bool,
+    simd: bool,
+}
+
+impl EncodeState {
+    /// With 3 chanels the alpha of every pixel is taken as 255, like the reference encoder does.
+    pub(crate) fn new(chanels: u8) -> Self {
+        Self {
+            keep_alpha: chanels == 4,
+            use_index: true,
+            simd: true,
+            // the spec requires a zero-initialized index, stale entries would produce invalid QoiOpIndex
+            array: [Pixel::default(); 64],
+            prev: Pixel::new(0, 0, 0, 255),
+            run: 0,
+        }
+    }
+
+    /// Appends the chunks for `pixel` to `output`, runs are only written once they end, see
+    /// [`EncodeState::flush`].
+    // NOTE: only the streaming encoder feeds pixels one at a time, whole images go through
+    //       `push_all`. Both classify the difference with the same code
+    #[cfg(feature = "std")]
+    #[inline(always)]
+    pub(crate) fn push(&mut self, pixel: Pixel, output: &mut Vec<u8>) {
+        let pixel = if self.keep_alpha {
+            pixel
+        } else {
+            let values = pixel.extract();
+            Pixel::new(values.0, values.1, values.2, 255)
+        };
+        if pixel == self.prev {
+            self.run += 1;
+            if self.run == 62 {
+                self.flush(output);
+            }
+            return;
+        }
+        let class = simd::classify_pixel(pixel, self.prev, false);
+        self.push_classified(pixel, class, output);
+    }
+
+    /// A fresh state with the settings of `self`, as a decoder has it after the first `start`
+    /// pixels of `image`, see `encode_bands`.
+    #[cfg(feature = "std")]
+    fn at(&self, image: &[Pixel], start: usize) -> Self {
+        let mask = |pixel: Pixel| {
+            let values = pixel.extract();
+            if self.keep_alpha {
+                pixel
+            } else {
+                Pixel::new(values.0, values.1, values.2, 255)
+            }
+        };
+        let mut state = Self {
+            array: [Pixel::default(); 64],
+            prev: Pixel::new(0, 0, 0, 255),
+            run: 0,
+            keep_alpha: self.keep_alpha,
+            use_index: self.use_index,
+            simd: self.simd,
+        };
+        if start == 0 {
+            return state;
+        }
+        state.prev = mask(image[start - 1]);
+        let first = start.saturating_sub(BAND_LOOKBACK);
+        let mut found = 0u64;
+        for pixel in image[first..start].iter().rev() {
+            let pixel = mask(*pixel);
+            let h = pixel.hash() as usize;
+            if found & (1 << h) == 0 {
+                found |= 1 << h;
+                state.array[h] = pixel;
+                if found == u64::MAX {
+                    break;
+                }
+            }
+        }
+        // a zero pixel in any other slot can't be hit, only slot 0 needs something else when
+        // the decoder may hold a pixel there that wasn't looked at
+        if found & 1 == 0 && first > 0 {
+            state.array[0] = Pixel::new(1, 0, 0, 0);
+        }
+        state
+    }
+
+    /// Writes out the pending run, if any.
+    #[inline(always)]
+    pub(crate) fn flush(&mut self, output: &mut Vec<u8>) {
+        if self.run > 0 {
+            QoiOpRun::new(self.run).append_self(output);
+            self.run = 0;
+        }
+    }
+
+    /// Same as [`EncodeState::push`] on every pixel of `image`, runs are scanned and differences
+    /// classified a block at a time with [`crate::qoi::simd`].
+    pub(crate) fn push_all(&mut self, image: &[Pixel], output: &mut Vec<u8>) {
+        let opaque = !self.keep_alpha;
+        let mut classes = [0u8; CLASSIFY_BLOCK];
+        let mut i = 0;
+        while i < image.len() {
+            let end = (i + CLASSIFY_BLOCK).min(image.len());
+            if self.simd {
+                simd::classify(&image[i..end], self.prev, opaque, &mut classes);
+            } else {
+                simd::classify_scalar(&image[i..end], self.prev, opaque, &mut classes);
+            }
+            let mut k = i;
+            // a run may carry `k` past the block, the next one is classified from there
+            while k < end {
+                let class = classes[k - i];
+                if class & simd::EQUAL != 0 {
+                    let run = if self.simd {
+                        simd::run_length(&image[k..], self.prev, opaque)
+                    } else {
+                        simd::run_length_scalar(&image[k..], self.prev, opaque)
+                    };
+                    self.extend_run(run, output);
+                    k += run;
+                    continue;
+                }
+                let values = image[k].extract();
+                let pixel = if opaque {
+                    Pixel::new(values.0, values.1, values.2, 255)
+                } else {
+                    image[k]
+                };
+                self.push_classified(pixel, class, output);
+                k += 1;
             }
-            encode_16(&image_[..], &array_, width, height, max_col_val)
+            i = k;
+        }
+    }
+
+    // adds `len` pixels to the pending run, every 62 of them make a full QoiOpRun
+    #[inline(always)]
+    fn extend_run(&mut self, len: usize, output: &mut Vec<u8>) {
+        let mut run = self.run as usize + len;
+        while run >= 62 {
+            QoiOpRun::new(62).append_self(output);
+            run -= 62;
+        }
+        self.run = run as u8;
+    }
+
+    // the part of `push` after the run check, with the diff ranges already known from `class`
+    #[inline(always)]
+    fn push_classified(&mut self, pixel: Pixel, class: u8, output: &mut Vec<u8>) {
+        let prev = self.prev;
+        self.flush(output);
+        self.prev = pixel;
+        if self.use_index {
+            let h = pixel.hash();
+            if self.array[h as usize] == pixel {
+                QoiOpIndex::new(h).append_self(output);
+                return;
+            }
+            self.array[h as usize] = pixel;
+        }
+        let values = pixel.extract();
+        let before = prev.extract();
+        let dr = values.0.wrapping_sub(before.0) as i8;
+        let dg = values.1.wrapping_sub(before.1) as i8;
+        let db = values.2.wrapping_sub(before.2) as i8;
+        if class & simd::DIFF != 0 {
+            QoiOpDiff::new(dr, dg, db).append_self(output);
+        } else if class & simd::LUMA != 0 {
+            QoiOpLuma::new(dg, dr.wrapping_sub(dg), db.wrapping_sub(dg)).append_self(output);
+        } else if class & simd::ALPHA_EQUAL != 0 {
+            QoiOpRGB::new(values.0, values.1, values.2).append_self(output);
+        } else {
+            QoiOpRGBA::new(values.0, values.1, values.2, values.3).append_self(output);
         }
     }
 }
 
+// pixels classified per call to simd::classify, small enough for the classes to sit on the stack
+const CLASSIFY_BLOCK: usize = 256;
+
 pub fn encode_(
     image: &[Pixel],
     array: &mut [Pixel; 64],
     width: u32,
     height: u32,
-) -> Result<Vec<u8>, String> {
-    let output: &mut Vec<u8> = &mut Vec::with_capacity(22usize + (width * height * 5) as usize);
-    QoiHeader::new(width, height, 3, 0).append_self(output);
-    let mut prev: &Pixel = &Pixel::new(0, 0, 0, 255);
-    let mut i: usize = 0;
-    let n = image.len();
-    while (0..n).contains(&i) {
-        let pixel = &image[i];
+) -> Result<Vec<u8>, QoiError> {
+    // NOTE: always 3 chanels like before `EncoderOptions`, the alpha of translucent pixels is
+    //       dropped rather than switching the stream to RGBA
+    let options = EncoderOptions::new().chanels(Channels::Rgb);
+    let (output, index) = encode_state(image, width, height, &options)?;
+    *array = index;
+    Ok(output)
+}
+
+/// Encodes `image` with `chanels` written into the header: with 4 the alpha of every pixel is
+/// encoded, with 3 it is dropped and every pixel is taken as opaque.
+pub fn encode_chanels(
+    image: &[Pixel],
+    array: &mut [Pixel; 64],
+    width: u32,
+    height: u32,
+    chanels: u8,
+) -> Result<Vec<u8>, QoiError> {
+    let options = EncoderOptions::new().chanels(Channels::try_from(chanels)?);
+    let (output, index) = encode_state(image, width, height, &options)?;
+    *array = index;
+    Ok(output)
+}
+
+/// Encodes `image` as `options` say, see [`EncoderOptions`] for the defaults.
+pub fn encode_with(
+    image: &[Pixel],
+    width: u32,
+    height: u32,
+    options: &EncoderOptions,
+) -> Result<Vec<u8>, QoiError> {
+    encode_state(image, width, height, options).map(|(output, _)| output)
+}
+
+// encodes and hands back the final color index along with the stream
+fn encode_state(
+    image: &[Pixel],
+    width: u32,
+    height: u32,
+    options: &EncoderOptions,
+) -> Result<(Vec<u8>, [Pixel; 64]), QoiError> {
+    let mut state = EncodeState::new(3);
+    let mut output = Vec::with_capacity(22usize + image.len() * 5);
+    encode_pixels(&mut state, image, width, height, options, &mut output)?;
+    Ok((output, state.array))
+}
+
+/// The chanels `options` encode `image` with.
+pub(crate) fn chanels_for(image: &[Pixel], options: &EncoderOptions) -> Channels {
+    match options.chanels {
+        Some(chanels) => chanels,
+        // only pay for the alpha channel when some pixel actually uses it
+        None if options.keep_alpha && image.iter().any(|pixel| pixel.extract().3 != 255) => {
+            Channels::Rgba
+        }
+        None => Channels::Rgb,
+    }
+}
+
+// appends the whole stream to `output`, `state` is reset first so nothing leaks from a previous
+// image
+fn encode_pixels(
+    state: &mut EncodeState,
+    image: &[Pixel],
+    width: u32,
+    height: u32,
+    options: &EncoderOptions,
+    output: &mut Vec<u8>,
+) -> Result<(), QoiError> {
+    let expected = width as u64 * height as u64;
+    if image.len() as u64 != expected {
+        return Err(QoiError::PixelCountMismatch {
+            expected,
+            actual: image.len() as u64,
+        });
+    }
+    let chanels = chanels_for(image, options);
+    QoiHeader::new(width, height, chanels as u8, options.colorspace as u8).append_self(output);
+    *state = EncodeState::new(chanels as u8);
+    state.keep_alpha &= options.keep_alpha;
+    state.use_index = options.preset != Preset::Speed;
+    state.simd = options.simd;
+    #[cfg(feature = "std")]
+    // NOTE: a zero width leaves nothing to split, the bands would be empty
+    if options.threads > 1 && width > 0 && height > 1 {
+        encode_bands(state, image, width, height, options.threads, output);
+        output.extend_from_slice(&END_MARKER);
+        return Ok(());
+    }
+    state.push_all(image, output);
+    state.flush(output);
+    output.extend_from_slice(&END_MARKER);
+    Ok(())
+}
+
+// pixels looked at before a band to rebuild the color index a decoder holds when it gets there
+#[cfg(feature = "std")]
+const BAND_LOOKBACK: usize = 4096;
+
+// splits `image` into `threads` bands of whole rows and encodes them side by side, `state` has
+// the settings to use and ends up as the state after the last band
+//
+// NOTE: every band starts from the pixel right before it and from the index the decoder has at
+//       that point, so the chunks can just be put one after the other. That index is rebuilt
+//       from the last BAND_LOOKBACK pixels: a decoder stores every pixel it outputs, so a slot
+//       holds the last pixel with that hash. Slots not found there are left out, with a pixel
+//       of another hash in them so they never match. The only difference with the sequential
+//       stream is a run or an index hit that would have crossed into a band.
+#[cfg(feature = "std")]
+fn encode_bands(
+    state: &mut EncodeState,
+    image: &[Pixel],
+    width: u32,
+    height: u32,
+    threads: usize,
+    output: &mut Vec<u8>,
+) {
+    let rows = (height as usize).div_ceil(threads.min(height as usize));
+    let band_len = rows * width as usize;
+    let template = &*state;
+    let bands: Vec<(Vec<u8>, EncodeState)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..image.len().div_ceil(band_len))
+            .map(|band| {
+                scope.spawn(move || {
+                    let start = band * band_len;
+                    let end = (start + band_len).min(image.len());
+                    let mut state = template.at(image, start);
+                    let mut output = Vec::with_capacity((end - start) * 5);
+                    state.push_all(&image[start..end], &mut output);
+                    state.flush(&mut output);
+                    (output, state)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("an encoding thread panicked"))
+            .collect()
+    });
+    for (chunks, band_state) in bands {
+        output.extend_from_slice(&chunks);
+        *state = band_state;
+    }
+}
+
+/// The 16-bit counterpart of [`EncodeState`], see [`crate::qoi::types16`] for the format.
+pub(crate) struct EncodeState16 {
+    array: [Pixel16; 64],
+    prev: Pixel16,
+    run: u8,
+}
+
+impl EncodeState16 {
+    pub(crate) fn new() -> Self {
+        Self {
+            array: [Pixel16::default(); 64],
+            prev: Pixel16::new(0, 0, 0, u16::MAX),
+            run: 0,
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn push(&mut self, pixel: Pixel16, output: &mut Vec<u8>) {
+        let prev = self.prev;
         if pixel == prev {
-            let mut run: u8 = 1;
-            while (i + run as usize) < n && (&image[i + run as usize] == pixel) && (run < 62) {
-                run += 1;
+            self.run += 1;
+            if self.run == 62 {
+                self.flush(output);
             }
-            i += run as usize;
-            QoiOpRun::new(run).append_self(output);
-
-            prev = pixel;
-            continue;
+            return;
         }
+        self.flush(output);
+        self.prev = pixel;
         let h = pixel.hash();
-        if array[h as usize] == *pixel {
+        if self.array[h as usize] == pixel {
             QoiOpIndex::new(h).append_self(output);
-            prev = pixel;
-            i += 1;
-            continue;
+            return;
         }
-        array[h as usize] = *pixel;
-        let diff: PixelDiff = PixelDiff::new(pixel, prev);
+        self.array[h as usize] = pixel;
+        let diff = PixelDiff16::new(&pixel, &prev);
         if diff.belongs(
-            Range::new(PixelDiff::new2(-2, -2, -2, 0), PixelDiff::new2(1, 1, 1, 0)).unwrap(),
+            Range::new(
+                PixelDiff16::new2(-2, -2, -2, 0),
+                PixelDiff16::new2(1, 1, 1, 0),
+            )
+            .unwrap(),
         ) {
-            let rgba: (i8, i8, i8, i8) = diff.extract();
-            QoiOpDiff::new(rgba.0, rgba.1, rgba.2).append_self(output);
-            prev = pixel;
-            i += 1;
-            continue;
+            let rgba = diff.extract();
+            QoiOpDiff::new(rgba.0 as i8, rgba.1 as i8, rgba.2 as i8).append_self(output);
+            return;
         }
-        let diff_diff: PixelDiff = PixelDiff::new_diff(pixel, prev);
+        let diff_diff = PixelDiff16::new_diff(&pixel, &prev);
         if diff_diff.belongs(
             Range::new(
-                PixelDiff::new2(-8, -32, -8, 0),
-                PixelDiff::new2(7, 31, 7, 0),
+                PixelDiff16::new2(-32, -512, -32, 0),
+                PixelDiff16::new2(31, 511, 31, 0),
             )
             .unwrap(),
         ) {
             let extracted = diff_diff.extract();
-            QoiOpLuma::new(extracted.1, extracted.0, extracted.2).append_self(output);
-            prev = pixel;
-            i += 1;
-            continue;
+            QoiOpLuma16::new(extracted.1, extracted.0, extracted.2).append_self(output);
+            return;
         }
-        let values: (u8, u8, u8, u8) = pixel.extract();
+        let values = pixel.extract();
         if diff_diff.is_alpha_zero() {
-            QoiOpRGB::new(values.0, values.1, values.2).append_self(output);
-            prev = pixel;
-            i += 1;
-            continue;
+            QoiOpRGB16::new(values.0, values.1, values.2).append_self(output);
+        } else {
+            QoiOpRGBA16::new(values.0, values.1, values.2, values.3).append_self(output);
         }
-        QoiOpRGBA::new(values.0, values.1, values.2, values.3).append_self(output);
+    }
 
-        prev = pixel;
-        i += 1;
+    #[inline(always)]
+    pub(crate) fn flush(&mut self, output: &mut Vec<u8>) {
+        if self.run > 0 {
+            QoiOpRun::new(self.run).append_self(output);
+            self.run = 0;
+        }
     }
-    output.extend_from_slice(&[0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 1u8]);
-    Ok(output.to_vec())
 }
 
+/// Encodes 16-bit pixels as QOI16, samples are first rescaled from `0..=max_col_val` to the
+/// full `0..=65535` range. The header gets 4 chanels only if some pixel isn't opaque.
 pub fn encode_16(
     image: &[Pixel16],
-    mut array: &[Pixel16; 64],
+    array: &mut [Pixel16; 64],
+    width: u32,
+    height: u32,
+    max_col_val: u32,
+) -> Result<Vec<u8>, QoiError> {
+    let mut state = EncodeState16::new();
+    let mut output = Vec::with_capacity(22usize + image.len() * 9);
+    encode_pixels_16(&mut state, image, width, height, max_col_val, &mut output)?;
+    *array = state.array;
+    Ok(output)
+}
+
+fn encode_pixels_16(
+    state: &mut EncodeState16,
+    image: &[Pixel16],
     width: u32,
     height: u32,
     max_col_val: u32,
-) -> Result<Vec<u8>, String> {
-    todo!()
+    output: &mut Vec<u8>,
+) -> Result<(), QoiError> {
+    let expected = width as u64 * height as u64;
+    if image.len() as u64 != expected {
+        return Err(QoiError::PixelCountMismatch {
+            expected,
+            actual: image.len() as u64,
+        });
+    }
+    let max = max_col_val.clamp(1, u16::MAX as u32);
+    let rescale = |value: u16| -> u16 {
+        if max == u16::MAX as u32 {
+            value
+        } else {
+            ((value.min(max as u16) as u32 * u16::MAX as u32 + max / 2) / max) as u16
+        }
+    };
+    let chanels = if image.iter().any(|pixel| pixel.extract().3 != u16::MAX) {
+        4
+    } else {
+        3
+    };
+    QoiHeader16::new(width, height, chanels, 0).append_self(output);
+    *state = EncodeState16::new();
+    for pixel in image {
+        let values = pixel.extract();
+        state.push(
+            Pixel16::new(
+                rescale(values.0),
+                rescale(values.1),
+                rescale(values.2),
+                values.3,
+            ),
+            output,
+        );
+    }
+    state.flush(output);
+    output.extend_from_slice(&END_MARKER);
+    Ok(())
+}
+
+/// A reusable encoder that owns its color index and output buffer.
+///
+/// The index is reset for every image and the buffers only ever grow, so encoding images of
+/// similar sizes in a loop doesn't allocate after the first one.
+pub struct Encoder {
+    options: EncoderOptions,
+    state: EncodeState,
+    state16: EncodeState16,
+    output: Vec<u8>,
+    pixels: Vec<Pixel>,
+    pixels16: Vec<Pixel16>,
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::with_options(EncoderOptions::default())
+    }
+
+    pub fn with_options(options: EncoderOptions) -> Self {
+        Self {
+            options,
+            state: EncodeState::new(3),
+            state16: EncodeState16::new(),
+            output: Vec::new(),
+            pixels: Vec::new(),
+            pixels16: Vec::new(),
+        }
+    }
+
+    pub fn options(&self) -> &EncoderOptions {
+        &self.options
+    }
+
+    pub fn set_options(&mut self, options: EncoderOptions) {
+        self.options = options;
+    }
+
+    /// Encodes `image`, the stream stays valid until the next call.
+    pub fn encode(&mut self, image: &[Pixel], width: u32, height: u32) -> Result<&[u8], QoiError> {
+        self.output.clear();
+        encode_pixels(
+            &mut self.state,
+            image,
+            width,
+            height,
+            &self.options,
+            &mut self.output,
+        )?;
+        Ok(&self.output)
+    }
+
+    /// Same as [`encode_16`], only the `max_col_val` rescaling applies, the options don't.
+    pub fn encode_16(
+        &mut self,
+        image: &[Pixel16],
+        width: u32,
+        height: u32,
+        max_col_val: u32,
+    ) -> Result<&[u8], QoiError> {
+        self.output.clear();
+        encode_pixels_16(
+            &mut self.state16,
+            image,
+            width,
+            height,
+            max_col_val,
+            &mut self.output,
+        )?;
+        Ok(&self.output)
+    }
+
+    /// Same as [`encode`], 8-bit pixels go through [`Encoder::encode`] and 16-bit ones through
+    /// [`Encoder::encode_16`].
+    pub fn encode_dynamic(
+        &mut self,
+        image: &[DynamicPixel],
+        width: u32,
+        height: u32,
+        max_col_val: u32,
+    ) -> Result<&[u8], QoiError> {
+        match image.first() {
+            None => Err(QoiError::EmptyImage),
+            Some(DynamicPixel::Pixel(_)) => {
+                // the converted pixels go into a buffer that is kept for the next call
+                let mut pixels = core::mem::take(&mut self.pixels);
+                pixels.clear();
+                let result = image
+                    .iter()
+                    .try_for_each(|pixel| {
+                        pixels.push(pixel.as_pixel().map_err(|_| QoiError::MixedPixelDepth)?);
+                        Ok(())
+                    })
+                    .and_then(|_| {
+                        self.output.clear();
+                        encode_pixels(
+                            &mut self.state,
+                            &pixels,
+                            width,
+                            height,
+                            &self.options,
+                            &mut self.output,
+                        )
+                    });
+                self.pixels = pixels;
+                result.map(|_| &self.output[..])
+            }
+            Some(DynamicPixel::Pixel16(_)) => {
+                // the converted pixels go into a buffer that is kept for the next call
+                let mut pixels = core::mem::take(&mut self.pixels16);
+                pixels.clear();
+                let result = image
+                    .iter()
+                    .try_for_each(|pixel| {
+                        pixels.push(pixel.as_pixel16().map_err(|_| QoiError::MixedPixelDepth)?);
+                        Ok(())
+                    })
+                    .and_then(|_| {
+                        self.output.clear();
+                        encode_pixels_16(
+                            &mut self.state16,
+                            &pixels,
+                            width,
+                            height,
+                            max_col_val,
+                            &mut self.output,
+                        )
+                    });
+                self.pixels16 = pixels;
+                result.map(|_| &self.output[..])
+            }
+        }
+    }
+}
+
+// the buffered chunks are handed to the writer once they grow past this size
+#[cfg(feature = "std")]
+const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Encodes a QOI stream incrementally into any [`Write`]r.
+///
+/// The header is written up front, pixels are then fed a scanline or a chunk at a time. Runs and
+/// the color index carry across calls, so the output is the same as encoding the whole image at
+/// once. [`QoiEncoder::finish`] must be called to write the end marker.
+#[cfg(feature = "std")]
+pub struct QoiEncoder<W: Write> {
+    writer: W,
+    width: u32,
+    height: u32,
+    state: EncodeState,
+    buffer: Vec<u8>,
+    written: u64,
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> QoiEncoder<W> {
+    /// Validates the header fields and queues the header, nothing is written to `writer` until
+    /// enough chunks have been buffered.
+    pub fn new(
+        writer: W,
+        width: u32,
+        height: u32,
+        chanels: u8,
+        colorspace: u8,
+    ) -> Result<Self, QoiError> {
+        if !(3..=4).contains(&chanels) {
+            return Err(QoiError::InvalidChannels(chanels));
+        }
+        if colorspace > 1 {
+            return Err(QoiError::InvalidColorspace(colorspace));
+        }
+        let mut buffer = Vec::with_capacity(STREAM_BUFFER_SIZE + 5);
+        QoiHeader::new(width, height, chanels, colorspace).append_self(&mut buffer);
+        Ok(Self {
+            writer,
+            width,
+            height,
+            state: EncodeState::new(chanels),
+            buffer,
+            written: 0,
+        })
+    }
+
+    fn expected(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    /// Encodes the next pixels, in row-major order. Going past `width * height` pixels is an error.
+    pub fn write_pixels(&mut self, pixels: &[Pixel]) -> Result<(), QoiError> {
+        let actual = self.written + pixels.len() as u64;
+        if actual > self.expected() {
+            return Err(QoiError::PixelCountMismatch {
+                expected: self.expected(),
+                actual,
+            });
+        }
+        for pixel in pixels {
+            self.state.push(*pixel, &mut self.buffer);
+            if self.buffer.len() >= STREAM_BUFFER_SIZE {
+                self.writer.write_all(&self.buffer)?;
+                self.buffer.clear();
+            }
+        }
+        self.written = actual;
+        Ok(())
+    }
+
+    /// Encodes the next scanline, which must be exactly `width` pixels long.
+    pub fn write_row(&mut self, row: &[Pixel]) -> Result<(), QoiError> {
+        if row.len() != self.width as usize {
+            return Err(QoiError::RowLengthMismatch {
+                expected: self.width,
+                actual: row.len(),
+            });
+        }
+        self.write_pixels(row)
+    }
+
+    /// Writes the pending run and the end marker, flushes and gives the writer back. Fails if
+    /// fewer than `width * height` pixels were written.
+    pub fn finish(mut self) -> Result<W, QoiError> {
+        if self.written != self.expected() {
+            return Err(QoiError::PixelCountMismatch {
+                expected: self.expected(),
+                actual: self.written,
+            });
+        }
+        self.state.flush(&mut self.buffer);
+        self.buffer.extend_from_slice(&END_MARKER);
+        self.writer.write_all(&self.buffer)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
 }
@@ -0,0 +1,134 @@
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+
+/// Everything that can go wrong while encoding or decoding a QOI stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QoiError {
+    /// The stream doesn't start with the `qoif` magic bytes
+    InvalidMagic([u8; 4]),
+    /// The stream is shorter than the 14 bytes of the header
+    TruncatedHeader(usize),
+    /// The channels field is neither 3 (RGB) nor 4 (RGBA)
+    InvalidChannels(u8),
+    /// The colorspace field is neither 0 (sRGB) nor 1 (linear)
+    InvalidColorspace(u8),
+    /// The chunk starting at `offset` runs past the end of the data
+    TruncatedChunk { offset: usize },
+    /// The 8-byte end marker `[0, 0, 0, 0, 0, 0, 0, 1]` was not found
+    MissingEndMarker,
+    /// Bytes follow the end marker, starting at `offset`
+    TrailingData { offset: usize },
+    /// The number of pixels doesn't match `width * height`
+    PixelCountMismatch { expected: u64, actual: u64 },
+    /// A scanline doesn't hold exactly `width` pixels
+    RowLengthMismatch { expected: u32, actual: usize },
+    /// The output buffer can't hold the decoded image
+    BufferTooSmall { needed: u64, actual: usize },
+    /// A dimension or the size of the decoded image goes past the configured `DecodeLimits`
+    LimitExceeded {
+        what: &'static str,
+        value: u64,
+        limit: u64,
+    },
+    /// The Netpbm input is malformed or uses a variant we can't read
+    InvalidNetpbm(&'static str),
+    /// The tiled container is malformed
+    InvalidTiled(&'static str),
+    /// The PNG input is malformed or uses a variant we can't read
+    InvalidPng(&'static str),
+    /// The zlib or deflate data is malformed
+    InvalidDeflate(&'static str),
+    /// The BMP input is malformed or uses a variant we can't read
+    InvalidBmp(&'static str),
+    /// The region at `x`, `y` of `width` by `height` pixels doesn't fit in the image
+    RegionOutOfBounds {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    /// There are no pixels to encode
+    EmptyImage,
+    /// 8-bit and 16-bit pixels were mixed in the same image
+    MixedPixelDepth,
+    /// The underlying reader or writer failed
+    #[cfg(feature = "std")]
+    Io(io::ErrorKind),
+}
+
+impl fmt::Display for QoiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QoiError::InvalidMagic(magic) => write!(f, "invalid magic bytes {:?}", magic),
+            QoiError::TruncatedHeader(len) => {
+                write!(f, "truncated header: got {} of 14 bytes", len)
+            }
+            QoiError::InvalidChannels(chanels) => {
+                write!(f, "invalid channel count {} (expected 3 or 4)", chanels)
+            }
+            QoiError::InvalidColorspace(colorspace) => {
+                write!(f, "invalid colorspace {} (expected 0 or 1)", colorspace)
+            }
+            QoiError::TruncatedChunk { offset } => {
+                write!(f, "truncated chunk at byte offset {}", offset)
+            }
+            QoiError::MissingEndMarker => write!(f, "missing end marker"),
+            QoiError::TrailingData { offset } => {
+                write!(
+                    f,
+                    "trailing data after the end marker at byte offset {}",
+                    offset
+                )
+            }
+            QoiError::PixelCountMismatch { expected, actual } => write!(
+                f,
+                "pixel count mismatch: expected {} pixels, got {}",
+                expected, actual
+            ),
+            QoiError::RowLengthMismatch { expected, actual } => write!(
+                f,
+                "row length mismatch: expected {} pixels, got {}",
+                expected, actual
+            ),
+            QoiError::BufferTooSmall { needed, actual } => write!(
+                f,
+                "buffer too small: {} bytes needed, {} available",
+                needed, actual
+            ),
+            QoiError::LimitExceeded { what, value, limit } => {
+                write!(f, "{} of {} exceeds the limit of {}", what, value, limit)
+            }
+            QoiError::InvalidNetpbm(reason) => write!(f, "invalid netpbm input: {}", reason),
+            QoiError::InvalidTiled(reason) => write!(f, "invalid tiled file: {}", reason),
+            QoiError::InvalidPng(reason) => write!(f, "invalid png input: {}", reason),
+            QoiError::InvalidDeflate(reason) => write!(f, "invalid deflate data: {}", reason),
+            QoiError::InvalidBmp(reason) => write!(f, "invalid bmp input: {}", reason),
+            QoiError::RegionOutOfBounds {
+                x,
+                y,
+                width,
+                height,
+            } => write!(
+                f,
+                "the {}x{} region at ({}, {}) is out of the image bounds",
+                width, height, x, y
+            ),
+            QoiError::EmptyImage => write!(f, "the image contains no pixels"),
+            QoiError::MixedPixelDepth => {
+                write!(f, "the image mixes 8-bit and 16-bit pixels")
+            }
+            #[cfg(feature = "std")]
+            QoiError::Io(kind) => write!(f, "i/o error: {}", kind),
+        }
+    }
+}
+
+impl core::error::Error for QoiError {}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for QoiError {
+    fn from(err: io::Error) -> Self {
+        QoiError::Io(err.kind())
+    }
+}
@@ -0,0 +1,180 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::qoi::decoder::{DecodeMode, decode_with_limits};
+use crate::qoi::encoder::encode_with;
+use crate::qoi::error::QoiError;
+use crate::qoi::limits::DecodeLimits;
+use crate::qoi::options::EncoderOptions;
+use crate::qoi::types::{Channels, ColorSpace, Pixel, QoiHeader};
+
+/// An owned image: the pixels in row-major order along with the metadata of its QOI header.
+///
+/// The fields are private so the pixel count always matches `width * height`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QoiImage {
+    pixels: Vec<Pixel>,
+    width: u32,
+    height: u32,
+    chanels: Channels,
+    colorspace: ColorSpace,
+}
+
+impl QoiImage {
+    /// A `width` by `height` image where every pixel is transparent black.
+    pub fn new(width: u32, height: u32, chanels: Channels, colorspace: ColorSpace) -> Self {
+        Self {
+            pixels: vec![Pixel::default(); width as usize * height as usize],
+            width,
+            height,
+            chanels,
+            colorspace,
+        }
+    }
+
+    /// Wraps `pixels`, which must hold exactly `width * height` pixels.
+    pub fn from_pixels(
+        pixels: Vec<Pixel>,
+        width: u32,
+        height: u32,
+        chanels: Channels,
+        colorspace: ColorSpace,
+    ) -> Result<Self, QoiError> {
+        let expected = width as u64 * height as u64;
+        if pixels.len() as u64 != expected {
+            return Err(QoiError::PixelCountMismatch {
+                expected,
+                actual: pixels.len() as u64,
+            });
+        }
+        Ok(Self {
+            pixels,
+            width,
+            height,
+            chanels,
+            colorspace,
+        })
+    }
+
+    pub fn decode(bytestream: &[u8]) -> Result<Self, QoiError> {
+        Self::decode_with_limits(bytestream, &DecodeLimits::default())
+    }
+
+    pub fn decode_with_limits(bytestream: &[u8], limits: &DecodeLimits) -> Result<Self, QoiError> {
+        let header = QoiHeader::parse(bytestream)?;
+        let decoded = decode_with_limits(
+            bytestream,
+            &mut [Pixel::default(); 64],
+            limits,
+            DecodeMode::Strict,
+        )?;
+        Self::from_pixels(
+            decoded.0,
+            header.width(),
+            header.height(),
+            Channels::try_from(header.chanels())?,
+            ColorSpace::try_from(header.colorspace())?,
+        )
+    }
+
+    /// Encodes the image with its own chanels and colorspace in the header, with
+    /// [`Channels::Rgb`] the alpha of every pixel is dropped.
+    pub fn encode(&self) -> Result<Vec<u8>, QoiError> {
+        let options = EncoderOptions::new()
+            .chanels(self.chanels)
+            .colorspace(self.colorspace);
+        encode_with(&self.pixels, self.width, self.height, &options)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn chanels(&self) -> Channels {
+        self.chanels
+    }
+
+    pub fn colorspace(&self) -> ColorSpace {
+        self.colorspace
+    }
+
+    pub fn set_chanels(&mut self, chanels: Channels) {
+        self.chanels = chanels
+    }
+
+    pub fn set_colorspace(&mut self, colorspace: ColorSpace) {
+        self.colorspace = colorspace
+    }
+
+    pub fn header(&self) -> QoiHeader {
+        QoiHeader::new(
+            self.width,
+            self.height,
+            self.chanels as u8,
+            self.colorspace as u8,
+        )
+    }
+
+    #[inline(always)]
+    fn offset(&self, x: u32, y: u32) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(y as usize * self.width as usize + x as usize)
+        } else {
+            None
+        }
+    }
+
+    /// The pixel at column `x` of row `y`, `None` outside of the image.
+    #[inline(always)]
+    pub fn get_pixel(&self, x: u32, y: u32) -> Option<Pixel> {
+        self.offset(x, y).map(|i| self.pixels[i])
+    }
+
+    /// Overwrites the pixel at column `x` of row `y`.
+    ///
+    /// # Panics
+    /// If `(x, y)` is outside of the image.
+    #[inline(always)]
+    pub fn put_pixel(&mut self, x: u32, y: u32, pixel: Pixel) {
+        let i = self.offset(x, y).unwrap_or_else(|| {
+            panic!(
+                "pixel ({}, {}) is outside of the {}x{} image",
+                x, y, self.width, self.height
+            )
+        });
+        self.pixels[i] = pixel;
+    }
+
+    /// Row `y`, `None` past the last row.
+    pub fn row(&self, y: u32) -> Option<&[Pixel]> {
+        let start = self.offset(0, y)?;
+        Some(&self.pixels[start..start + self.width as usize])
+    }
+
+    pub fn row_mut(&mut self, y: u32) -> Option<&mut [Pixel]> {
+        let start = self.offset(0, y)?;
+        let width = self.width as usize;
+        Some(&mut self.pixels[start..start + width])
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[Pixel]> {
+        // a zero width means there are no pixels, the max only keeps chunks_exact happy
+        self.pixels.chunks_exact(self.width.max(1) as usize)
+    }
+
+    pub fn pixels(&self) -> &[Pixel] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [Pixel] {
+        &mut self.pixels
+    }
+
+    pub fn into_pixels(self) -> Vec<Pixel> {
+        self.pixels
+    }
+}
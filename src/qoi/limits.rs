@@ -0,0 +1,78 @@
+use crate::qoi::error::QoiError;
+
+/// Upper bounds a decoder checks against the dimensions found in an untrusted header, before
+/// allocating anything for the pixels.
+///
+/// The defaults accept anything the reference `qoi.h` accepts (at most 400 million pixels).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeLimits {
+    max_width: u32,
+    max_height: u32,
+    max_pixels: u64,
+    max_alloc: u64,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_width: u32::MAX,
+            max_height: u32::MAX,
+            max_pixels: 400_000_000,
+            max_alloc: 400_000_000 * 8,
+        }
+    }
+}
+
+impl DecodeLimits {
+    /// No limit at all, only for trusted inputs.
+    pub fn unlimited() -> Self {
+        Self {
+            max_width: u32::MAX,
+            max_height: u32::MAX,
+            max_pixels: u64::MAX,
+            max_alloc: u64::MAX,
+        }
+    }
+
+    pub fn max_width(mut self, max_width: u32) -> Self {
+        self.max_width = max_width;
+        self
+    }
+
+    pub fn max_height(mut self, max_height: u32) -> Self {
+        self.max_height = max_height;
+        self
+    }
+
+    pub fn max_pixels(mut self, max_pixels: u64) -> Self {
+        self.max_pixels = max_pixels;
+        self
+    }
+
+    /// Largest buffer, in bytes, a decoder may allocate for the decoded pixels.
+    pub fn max_alloc(mut self, max_alloc: u64) -> Self {
+        self.max_alloc = max_alloc;
+        self
+    }
+
+    /// Checks a `width` by `height` image stored with `bytes_per_pixel` bytes per pixel.
+    pub fn check(&self, width: u32, height: u32, bytes_per_pixel: usize) -> Result<(), QoiError> {
+        let pixels = width as u64 * height as u64;
+        let checks = [
+            ("width", width as u64, self.max_width as u64),
+            ("height", height as u64, self.max_height as u64),
+            ("pixel count", pixels, self.max_pixels),
+            (
+                "allocation",
+                pixels.saturating_mul(bytes_per_pixel as u64),
+                self.max_alloc,
+            ),
+        ];
+        for (what, value, limit) in checks {
+            if value > limit {
+                return Err(QoiError::LimitExceeded { what, value, limit });
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,9 +1,33 @@
+pub mod bmp;
+#[cfg(feature = "std")]
 pub mod cli;
 pub mod decoder;
 pub mod encoder;
+pub mod error;
+pub mod image;
+pub mod limits;
+pub mod netpbm;
+pub mod options;
+pub mod png;
+pub mod simd;
+pub mod tiled;
 pub mod types;
 pub mod types16;
+pub mod zlib;
 
+#[cfg(feature = "std")]
 pub use cli::cli;
-pub use decoder::{decode, decode_to_p6_8_bit};
-pub use encoder::encode;
+pub use decoder::{
+    DecodeMode, Decoder, QoiPixels, QoiRows, decode, decode_16, decode_into, decode_region,
+    decode_to_bytes, decode_to_p6_8_bit, decode_to_p6_16_bit, decode_with_limits,
+};
+#[cfg(feature = "std")]
+pub use decoder::{QoiDecoder, probe, probe_file};
+#[cfg(feature = "std")]
+pub use encoder::QoiEncoder;
+pub use encoder::{Encoder, encode, encode_chanels, encode_with};
+pub use error::QoiError;
+pub use image::QoiImage;
+pub use limits::DecodeLimits;
+pub use options::{EncoderOptions, Preset};
+pub use tiled::{TiledImage, encode_tiled};
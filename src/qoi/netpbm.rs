@@ -0,0 +1,427 @@
+//! Reader for the Netpbm formats: PBM (`P1`, `P4`), PGM (`P2`, `P5`), PPM (`P3`, `P6`) and
+//! PAM (`P7`), and a PAM writer so alpha can make it out of a QOI image.
+//!
+//! The header is read as the Netpbm spec describes it: tokens separated by any whitespace, `#`
+//! comments running to the end of the line anywhere between them, and a single whitespace
+//! between the last token and the samples of the binary variants. Anything after the first
+//! image is ignored.
+//!
+//! PAM headers are one `KEYWORD value` per line up to `ENDHDR`, the `TUPLTYPE`s RGB, RGB_ALPHA,
+//! GRAYSCALE, GRAYSCALE_ALPHA and BLACKANDWHITE are understood, any other one is read from its
+//! depth alone.
+
+use alloc::vec::Vec;
+
+use crate::qoi::error::QoiError;
+use crate::qoi::limits::DecodeLimits;
+use crate::qoi::types::{Channels, DynamicPixel, Pixel};
+use crate::qoi::types16::Pixel16;
+
+/// A Netpbm image as read from the file, samples keep the range `0..=maxval` of the source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Netpbm {
+    width: u32,
+    height: u32,
+    depth: u8,
+    maxval: u16,
+    samples: Vec<u16>,
+}
+
+// whitespace as the Netpbm spec has it, which unlike `is_ascii_whitespace` includes \v
+#[inline(always)]
+fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\n' | b'\x0B' | b'\x0C' | b'\r')
+}
+
+// skips whitespace and comments, a comment runs up to the next \n or \r
+fn skip_separators(bytestream: &[u8], i: &mut usize) {
+    while let Some(&byte) = bytestream.get(*i) {
+        if is_whitespace(byte) {
+            *i += 1;
+        } else if byte == b'#' {
+            while bytestream
+                .get(*i)
+                .is_some_and(|&b| b != b'\n' && b != b'\r')
+            {
+                *i += 1;
+            }
+        } else {
+            break;
+        }
+    }
+}
+
+/// Reads a decimal number, after any whitespace and comments before it.
+fn read_number(bytestream: &[u8], i: &mut usize) -> Result<u32, QoiError> {
+    skip_separators(bytestream, i);
+    let start = *i;
+    let mut value: u32 = 0;
+    while let Some(digit) = bytestream.get(*i).filter(|b| b.is_ascii_digit()) {
+        value = value
+            .checked_mul(10)
+            .and_then(|value| value.checked_add((digit - b'0') as u32))
+            .ok_or(QoiError::InvalidNetpbm("number too large"))?;
+        *i += 1;
+    }
+    if *i == start {
+        return Err(QoiError::InvalidNetpbm("expected a number"));
+    }
+    Ok(value)
+}
+
+pub fn read(bytestream: &[u8]) -> Result<Netpbm, QoiError> {
+    read_with_limits(bytestream, &DecodeLimits::default())
+}
+
+/// Same as [`read`], with the dimensions checked against `limits` before anything gets
+/// allocated.
+pub fn read_with_limits(bytestream: &[u8], limits: &DecodeLimits) -> Result<Netpbm, QoiError> {
+    let kind = match bytestream {
+        [b'P', b'7', ..] => return read_pam(bytestream, limits),
+        [b'P', kind @ b'1'..=b'6', ..] => kind - b'0',
+        _ => return Err(QoiError::InvalidNetpbm("not a Netpbm file")),
+    };
+    let mut i = 2;
+    let width = read_number(bytestream, &mut i)?;
+    let height = read_number(bytestream, &mut i)?;
+    // bitmaps have no maxval, 1 is white there and black everywhere else
+    let maxval = if kind == 1 || kind == 4 {
+        1
+    } else {
+        read_number(bytestream, &mut i)?
+    };
+    if !(1..=65535).contains(&maxval) {
+        return Err(QoiError::InvalidNetpbm("maxval out of 1..=65535"));
+    }
+    let maxval = maxval as u16;
+    let depth: u8 = if kind == 3 || kind == 6 { 3 } else { 1 };
+    limits.check(width, height, size_of::<DynamicPixel>())?;
+    let count = sample_count(width, height, depth as u32)?;
+
+    let samples = match kind {
+        1..=3 => read_plain(bytestream, i, count, kind == 1)?,
+        4 => read_raw_bits(bytestream, i, width, height)?,
+        _ => read_raw(bytestream, i, count, maxval)?,
+    };
+    Netpbm::new(width, height, depth, maxval, samples)
+}
+
+// the next word of a PAM header line, everything up to a whitespace
+fn read_word<'a>(bytestream: &'a [u8], i: &mut usize) -> &'a [u8] {
+    let start = *i;
+    while bytestream.get(*i).is_some_and(|&b| !is_whitespace(b)) {
+        *i += 1;
+    }
+    &bytestream[start..*i]
+}
+
+fn read_pam(bytestream: &[u8], limits: &DecodeLimits) -> Result<Netpbm, QoiError> {
+    let (mut width, mut height, mut depth, mut maxval) = (None, None, None, None);
+    let mut tupltype: &[u8] = &[];
+    let mut i = 2;
+    loop {
+        skip_separators(bytestream, &mut i);
+        match read_word(bytestream, &mut i) {
+            b"ENDHDR" => break,
+            b"WIDTH" => width = Some(read_number(bytestream, &mut i)?),
+            b"HEIGHT" => height = Some(read_number(bytestream, &mut i)?),
+            b"DEPTH" => depth = Some(read_number(bytestream, &mut i)?),
+            b"MAXVAL" => maxval = Some(read_number(bytestream, &mut i)?),
+            b"TUPLTYPE" => {
+                while bytestream.get(i).is_some_and(|&b| b == b' ' || b == b'\t') {
+                    i += 1;
+                }
+                tupltype = read_word(bytestream, &mut i);
+            }
+            b"" => return Err(QoiError::InvalidNetpbm("PAM header without ENDHDR")),
+            _ => return Err(QoiError::InvalidNetpbm("unknown PAM header line")),
+        }
+    }
+    let (Some(width), Some(height), Some(depth), Some(maxval)) = (width, height, depth, maxval)
+    else {
+        return Err(QoiError::InvalidNetpbm(
+            "PAM header without WIDTH, HEIGHT, DEPTH or MAXVAL",
+        ));
+    };
+    if !(1..=4).contains(&depth) {
+        return Err(QoiError::InvalidNetpbm("PAM depth out of 1..=4"));
+    }
+    if !(1..=65535).contains(&maxval) {
+        return Err(QoiError::InvalidNetpbm("maxval out of 1..=65535"));
+    }
+    let expected = match tupltype {
+        b"GRAYSCALE" | b"BLACKANDWHITE" => Some(1),
+        b"GRAYSCALE_ALPHA" => Some(2),
+        b"RGB" => Some(3),
+        b"RGB_ALPHA" => Some(4),
+        _ => None,
+    };
+    if expected.is_some_and(|expected| expected != depth) {
+        return Err(QoiError::InvalidNetpbm(
+            "PAM depth doesn't match the TUPLTYPE",
+        ));
+    }
+    limits.check(width, height, size_of::<DynamicPixel>())?;
+    let count = sample_count(width, height, depth)?;
+    let samples = read_raw(bytestream, i, count, maxval as u16)?;
+    Netpbm::new(width, height, depth as u8, maxval as u16, samples)
+}
+
+// `width * height * depth`, only a header past any sane limits overflows it
+fn sample_count(width: u32, height: u32, depth: u32) -> Result<u64, QoiError> {
+    (width as u64)
+        .checked_mul(height as u64)
+        .and_then(|pixels| pixels.checked_mul(depth as u64))
+        .ok_or(QoiError::InvalidNetpbm("image too large"))
+}
+
+// the samples of P1, P2 and P3, a PBM sample is a single digit that needs no separator
+fn read_plain(
+    bytestream: &[u8],
+    mut i: usize,
+    count: u64,
+    bits: bool,
+) -> Result<Vec<u16>, QoiError> {
+    // every sample takes at least one byte, which bounds the allocation by the input
+    if count > (bytestream.len() - i.min(bytestream.len())) as u64 {
+        return Err(QoiError::InvalidNetpbm("truncated pixel data"));
+    }
+    let mut samples = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        if bits {
+            skip_separators(bytestream, &mut i);
+            let sample = match bytestream.get(i) {
+                Some(b'0') => 1,
+                Some(b'1') => 0,
+                Some(_) => return Err(QoiError::InvalidNetpbm("expected a 0 or a 1")),
+                None => return Err(QoiError::InvalidNetpbm("truncated pixel data")),
+            };
+            samples.push(sample);
+            i += 1;
+        } else {
+            let sample =
+                read_number(bytestream, &mut i).map_err(|err| match bytestream.get(i) {
+                    None => QoiError::InvalidNetpbm("truncated pixel data"),
+                    Some(_) => err,
+                })?;
+            let sample = u16::try_from(sample)
+                .map_err(|_| QoiError::InvalidNetpbm("sample above maxval"))?;
+            samples.push(sample);
+        }
+    }
+    Ok(samples)
+}
+
+// the samples of P4, rows are packed 8 pixels to a byte, the first one in the highest bit
+fn read_raw_bits(
+    bytestream: &[u8],
+    i: usize,
+    width: u32,
+    height: u32,
+) -> Result<Vec<u16>, QoiError> {
+    let row_len = (width as usize).div_ceil(8);
+    let raster = raster(bytestream, i, row_len as u64 * height as u64)?;
+    let mut samples = Vec::with_capacity(width as usize * height as usize);
+    if row_len == 0 {
+        return Ok(samples);
+    }
+    for row in raster.chunks_exact(row_len) {
+        for x in 0..width as usize {
+            let bit = (row[x / 8] >> (7 - x % 8)) & 1;
+            samples.push(1 - bit as u16);
+        }
+    }
+    Ok(samples)
+}
+
+// the samples of P5 and P6, 2 bytes each, most significant first, above a maxval of 255
+fn read_raw(bytestream: &[u8], i: usize, count: u64, maxval: u16) -> Result<Vec<u16>, QoiError> {
+    if maxval <= 255 {
+        let raster = raster(bytestream, i, count)?;
+        Ok(raster.iter().map(|&sample| sample as u16).collect())
+    } else {
+        let len = count
+            .checked_mul(2)
+            .ok_or(QoiError::InvalidNetpbm("image too large"))?;
+        let raster = raster(bytestream, i, len)?;
+        Ok(raster
+            .chunks_exact(2)
+            .map(|sample| u16::from_be_bytes([sample[0], sample[1]]))
+            .collect())
+    }
+}
+
+// the `len` bytes of a binary raster, after the single whitespace that ends the header
+fn raster(bytestream: &[u8], i: usize, len: u64) -> Result<&[u8], QoiError> {
+    if !bytestream.get(i).copied().is_some_and(is_whitespace) {
+        return Err(QoiError::InvalidNetpbm(
+            "expected a whitespace before the pixel data",
+        ));
+    }
+    let start = i + 1;
+    if ((bytestream.len() - start) as u64) < len {
+        return Err(QoiError::InvalidNetpbm("truncated pixel data"));
+    }
+    Ok(&bytestream[start..start + len as usize])
+}
+
+// rescales `sample` from 0..=maxval to 0..=max, rounding to the nearest
+#[inline(always)]
+pub(crate) fn rescale(sample: u16, maxval: u16, max: u16) -> u16 {
+    if maxval == max {
+        sample
+    } else {
+        ((sample as u32 * max as u32 + maxval as u32 / 2) / maxval as u32) as u16
+    }
+}
+
+/// Writes `pixels` as a P6 with a maxval of 255, the alpha is dropped.
+pub fn write_ppm(pixels: &[Pixel], width: u32, height: u32) -> Vec<u8> {
+    let mut output = alloc::format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    output.reserve(pixels.len() * 3);
+    for pixel in pixels {
+        let (r, g, b, _) = pixel.extract();
+        output.extend_from_slice(&[r, g, b]);
+    }
+    output
+}
+
+/// Same as [`write_ppm`] for 16-bit pixels, with a maxval of 65535.
+pub fn write_ppm_16(pixels: &[Pixel16], width: u32, height: u32) -> Vec<u8> {
+    let mut output = alloc::format!("P6\n{} {}\n65535\n", width, height).into_bytes();
+    output.reserve(pixels.len() * 6);
+    for pixel in pixels {
+        let (r, g, b, _) = pixel.extract();
+        for sample in [r, g, b] {
+            output.extend_from_slice(&sample.to_be_bytes());
+        }
+    }
+    output
+}
+
+/// Writes `pixels` as a PAM with a maxval of 255, RGB_ALPHA with [`Channels::Rgba`] and RGB
+/// otherwise.
+pub fn write_pam(pixels: &[Pixel], width: u32, height: u32, chanels: Channels) -> Vec<u8> {
+    let depth = chanels as usize;
+    let mut output = pam_header(width, height, chanels, 255);
+    output.reserve(pixels.len() * depth);
+    for pixel in pixels {
+        let (r, g, b, a) = pixel.extract();
+        output.extend_from_slice(&[r, g, b, a][..depth]);
+    }
+    output
+}
+
+/// Same as [`write_pam`] for 16-bit pixels, with a maxval of 65535.
+pub fn write_pam_16(pixels: &[Pixel16], width: u32, height: u32, chanels: Channels) -> Vec<u8> {
+    let depth = chanels as usize;
+    let mut output = pam_header(width, height, chanels, 65535);
+    output.reserve(pixels.len() * depth * 2);
+    for pixel in pixels {
+        let (r, g, b, a) = pixel.extract();
+        for sample in &[r, g, b, a][..depth] {
+            output.extend_from_slice(&sample.to_be_bytes());
+        }
+    }
+    output
+}
+
+fn pam_header(width: u32, height: u32, chanels: Channels, maxval: u16) -> Vec<u8> {
+    let tupltype = match chanels {
+        Channels::Rgb => "RGB",
+        Channels::Rgba => "RGB_ALPHA",
+    };
+    alloc::format!(
+        "P7\nWIDTH {}\nHEIGHT {}\nDEPTH {}\nMAXVAL {}\nTUPLTYPE {}\nENDHDR\n",
+        width,
+        height,
+        chanels as u8,
+        maxval,
+        tupltype
+    )
+    .into_bytes()
+}
+
+impl Netpbm {
+    fn new(
+        width: u32,
+        height: u32,
+        depth: u8,
+        maxval: u16,
+        samples: Vec<u16>,
+    ) -> Result<Self, QoiError> {
+        if samples.iter().any(|&sample| sample > maxval) {
+            return Err(QoiError::InvalidNetpbm("sample above maxval"));
+        }
+        Ok(Self {
+            width,
+            height,
+            depth,
+            maxval,
+            samples,
+        })
+    }
+
+    #[inline(always)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    #[inline(always)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    /// Samples per pixel, 1 for PBM and PGM, 3 for PPM and 1 to 4 for PAM
+    #[inline(always)]
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+    #[inline(always)]
+    pub fn maxval(&self) -> u16 {
+        self.maxval
+    }
+    /// Whether the last sample of every pixel is an alpha, only PAM has one.
+    #[inline(always)]
+    pub fn has_alpha(&self) -> bool {
+        self.depth == 2 || self.depth == 4
+    }
+    /// The samples as stored in the file, row-major with `depth` samples per pixel.
+    #[inline(always)]
+    pub fn samples(&self) -> &[u16] {
+        &self.samples
+    }
+
+    /// The samples of every pixel as r, g, b, a still in `0..=maxval`, gray is spread over the
+    /// three colors and the alpha is `maxval` unless the image has one.
+    pub fn rgba(&self) -> impl Iterator<Item = [u16; 4]> + '_ {
+        let maxval = self.maxval;
+        self.samples
+            .chunks_exact(self.depth as usize)
+            .map(move |pixel| match *pixel {
+                [gray] => [gray, gray, gray, maxval],
+                [gray, a] => [gray, gray, gray, a],
+                [r, g, b] => [r, g, b, maxval],
+                [r, g, b, a] => [r, g, b, a],
+                _ => unreachable!(),
+            })
+    }
+
+    /// The pixels rescaled to 16 bits, whatever the maxval.
+    pub fn to_pixels16(&self) -> Vec<Pixel16> {
+        self.rgba()
+            .map(|[r, g, b, a]| {
+                let scale = |sample| rescale(sample, self.maxval, u16::MAX);
+                Pixel16::new(scale(r), scale(g), scale(b), scale(a))
+            })
+            .collect()
+    }
+
+    /// The pixels rescaled to 8 bits.
+    pub fn to_pixels(&self) -> Vec<Pixel> {
+        self.rgba()
+            .map(|[r, g, b, a]| {
+                let scale = |sample| rescale(sample, self.maxval, 255) as u8;
+                Pixel::new(scale(r), scale(g), scale(b), scale(a))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,82 @@
+use crate::qoi::types::{Channels, ColorSpace};
+
+/// Trades encoding speed for output size, every preset produces a valid QOI stream.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Preset {
+    /// Every op is tried, the output is byte for byte the one of the reference `qoi.h`
+    #[default]
+    Size,
+    /// The color index is neither kept up to date nor looked up, so no QoiOpIndex is ever written
+    Speed,
+}
+
+/// How [`crate::qoi::encoder::encode_with`] writes an image.
+///
+/// The default picks the chanels from the pixels (RGBA only if some pixel isn't opaque), writes
+/// sRGB into the header and encodes like the reference `qoi.h`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EncoderOptions {
+    pub(crate) chanels: Option<Channels>,
+    pub(crate) colorspace: ColorSpace,
+    pub(crate) keep_alpha: bool,
+    pub(crate) preset: Preset,
+    pub(crate) threads: usize,
+    pub(crate) simd: bool,
+}
+
+impl Default for EncoderOptions {
+    fn default() -> Self {
+        Self {
+            chanels: None,
+            colorspace: ColorSpace::Srgb,
+            keep_alpha: true,
+            preset: Preset::Size,
+            threads: 1,
+            simd: true,
+        }
+    }
+}
+
+impl EncoderOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forces the chanels of the header, with [`Channels::Rgb`] the alpha of every pixel is
+    /// dropped like the reference encoder does.
+    pub fn chanels(mut self, chanels: Channels) -> Self {
+        self.chanels = Some(chanels);
+        self
+    }
+
+    pub fn colorspace(mut self, colorspace: ColorSpace) -> Self {
+        self.colorspace = colorspace;
+        self
+    }
+
+    /// With `false` every pixel is encoded as opaque, whatever its alpha.
+    pub fn keep_alpha(mut self, keep_alpha: bool) -> Self {
+        self.keep_alpha = keep_alpha;
+        self
+    }
+
+    pub fn preset(mut self, preset: Preset) -> Self {
+        self.preset = preset;
+        self
+    }
+
+    /// Encodes 8-bit images with up to `threads` threads, each one on a band of whole rows. The bands are
+    /// stitched into a single stream any decoder reads, a little bigger than the one of a
+    /// single thread. Without the `std` feature the image is always encoded on one thread.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// With `false` the encoder sticks to the scalar code even where [`crate::qoi::simd`] has a
+    /// faster path, the output is the same either way.
+    pub fn simd(mut self, simd: bool) -> Self {
+        self.simd = simd;
+        self
+    }
+}
@@ -0,0 +1,518 @@
+//! PNG reader and writer, on top of [`crate::qoi::zlib`].
+//!
+//! Every color type and bit depth of the spec is read, interlaced or not, with `PLTE` and
+//! `tRNS`. Gray and palette images below 8 bits come out as 8-bit samples, 16-bit images keep
+//! their 16 bits. Ancillary chunks other than `tRNS` are skipped, an unknown critical chunk is
+//! an error.
+//!
+//! The writer produces 8 or 16-bit RGB and RGBA, not interlaced, each row with the filter that
+//! gives the smallest sum of absolute differences.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::qoi::error::QoiError;
+use crate::qoi::limits::DecodeLimits;
+use crate::qoi::netpbm::rescale;
+use crate::qoi::types::{Channels, DynamicPixel, Pixel};
+use crate::qoi::types16::Pixel16;
+use crate::qoi::zlib;
+
+/// The 8 bytes every PNG file starts with.
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+// the Adam7 passes as (x, y, step x, step y)
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+const NOT_INTERLACED: [(usize, usize, usize, usize); 1] = [(0, 0, 1, 1)];
+
+/// A decoded PNG image, samples are 8-bit unless the file is 16-bit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Png {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+    depth: u8,
+    samples: Vec<u16>,
+}
+
+// the next chunk as (type, data), with its CRC checked
+fn read_chunk<'a>(bytes: &'a [u8], i: &mut usize) -> Result<([u8; 4], &'a [u8]), QoiError> {
+    let Some(head) = bytes.get(*i..*i + 8) else {
+        return Err(QoiError::InvalidPng("truncated chunk"));
+    };
+    let len = u32::from_be_bytes(head[0..4].try_into().unwrap());
+    if len > i32::MAX as u32 {
+        return Err(QoiError::InvalidPng("chunk length above 2^31 - 1"));
+    }
+    let start = *i + 8;
+    let Some(crc) = start
+        .checked_add(len as usize)
+        .and_then(|end| bytes.get(end..end + 4))
+    else {
+        return Err(QoiError::InvalidPng("truncated chunk"));
+    };
+    // NOTE: the CRC covers the chunk type and data, not the length
+    let crc = u32::from_be_bytes(crc.try_into().unwrap());
+    if zlib::crc32(&bytes[*i + 4..start + len as usize]) != crc {
+        return Err(QoiError::InvalidPng("chunk CRC mismatch"));
+    }
+    *i = start + len as usize + 4;
+    Ok((
+        head[4..8].try_into().unwrap(),
+        &bytes[start..start + len as usize],
+    ))
+}
+
+pub fn read(bytes: &[u8]) -> Result<Png, QoiError> {
+    read_with_limits(bytes, &DecodeLimits::default())
+}
+
+/// Same as [`read`], with the dimensions checked against `limits` before anything gets
+/// allocated.
+pub fn read_with_limits(bytes: &[u8], limits: &DecodeLimits) -> Result<Png, QoiError> {
+    if !bytes.starts_with(&SIGNATURE) {
+        return Err(QoiError::InvalidPng("not a PNG file"));
+    }
+    let mut i = SIGNATURE.len();
+    let (kind, header) = read_chunk(bytes, &mut i)?;
+    if &kind != b"IHDR" || header.len() != 13 {
+        return Err(QoiError::InvalidPng("the file doesn't start with IHDR"));
+    }
+    let width = u32::from_be_bytes(header[0..4].try_into().unwrap());
+    let height = u32::from_be_bytes(header[4..8].try_into().unwrap());
+    let [bit_depth, color_type, compression, filter, interlace] = header[8..13] else {
+        unreachable!()
+    };
+    if width == 0 || height == 0 || width > i32::MAX as u32 || height > i32::MAX as u32 {
+        return Err(QoiError::InvalidPng("width or height out of 1..2^31"));
+    }
+    let valid_depth = match color_type {
+        0 => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+        3 => matches!(bit_depth, 1 | 2 | 4 | 8),
+        2 | 4 | 6 => matches!(bit_depth, 8 | 16),
+        _ => return Err(QoiError::InvalidPng("unknown color type")),
+    };
+    if !valid_depth {
+        return Err(QoiError::InvalidPng(
+            "bit depth not allowed for the color type",
+        ));
+    }
+    if compression != 0 || filter != 0 || interlace > 1 {
+        return Err(QoiError::InvalidPng(
+            "unknown compression, filter or interlace method",
+        ));
+    }
+    limits.check(width, height, size_of::<DynamicPixel>())?;
+
+    // palette entries as r, g, b, a, the alpha only comes with tRNS
+    let mut palette: Vec<[u16; 4]> = Vec::new();
+    let mut transparent: Option<[u16; 3]> = None;
+    let mut has_trns = false;
+    let mut idat = Vec::new();
+    loop {
+        let (kind, data) = read_chunk(bytes, &mut i)?;
+        match &kind {
+            b"IEND" => break,
+            b"IDAT" => idat.extend_from_slice(data),
+            b"IHDR" => return Err(QoiError::InvalidPng("more than one IHDR")),
+            b"PLTE" => {
+                if color_type == 0 || color_type == 4 {
+                    return Err(QoiError::InvalidPng("PLTE in a gray image"));
+                }
+                if !idat.is_empty() || !palette.is_empty() {
+                    return Err(QoiError::InvalidPng("PLTE after IDAT or repeated"));
+                }
+                if data.is_empty() || data.len() % 3 != 0 || data.len() > 256 * 3 {
+                    return Err(QoiError::InvalidPng("PLTE is not 1 to 256 colors"));
+                }
+                palette = data
+                    .chunks_exact(3)
+                    .map(|rgb| [rgb[0] as u16, rgb[1] as u16, rgb[2] as u16, 255])
+                    .collect();
+            }
+            b"tRNS" => {
+                if has_trns || !idat.is_empty() {
+                    return Err(QoiError::InvalidPng("tRNS after IDAT or repeated"));
+                }
+                has_trns = true;
+                let word = |at: usize| u16::from_be_bytes([data[at], data[at + 1]]);
+                match (color_type, data.len()) {
+                    (3, _) if palette.is_empty() => {
+                        return Err(QoiError::InvalidPng("tRNS before PLTE"));
+                    }
+                    (3, len) if len <= palette.len() => {
+                        for (entry, &alpha) in palette.iter_mut().zip(data) {
+                            entry[3] = alpha as u16;
+                        }
+                    }
+                    (0, 2) => transparent = Some([word(0), 0, 0]),
+                    (2, 6) => transparent = Some([word(0), word(2), word(4)]),
+                    (4 | 6, _) => {
+                        return Err(QoiError::InvalidPng("tRNS in an image with alpha"));
+                    }
+                    _ => return Err(QoiError::InvalidPng("tRNS of the wrong length")),
+                }
+            }
+            // bit 5 of the first letter is clear on the chunks a decoder can't skip
+            _ if kind[0] & 0x20 == 0 => {
+                return Err(QoiError::InvalidPng("unknown critical chunk"));
+            }
+            _ => {}
+        }
+    }
+    if color_type == 3 && palette.is_empty() {
+        return Err(QoiError::InvalidPng("palette image without PLTE"));
+    }
+
+    let channels = match color_type {
+        0 | 3 => 1,
+        2 => 3,
+        4 => 2,
+        _ => 4,
+    };
+    let depth = match color_type {
+        0 if transparent.is_some() => 2,
+        2 if transparent.is_some() => 4,
+        3 if has_trns => 4,
+        3 => 3,
+        _ => channels,
+    };
+    let bits_per_pixel = channels * bit_depth as usize;
+    // filters work on whole bytes, a pixel under 8 bits is compared with the previous byte
+    let filter_step = bits_per_pixel.div_ceil(8);
+    let passes: &[(usize, usize, usize, usize)] = if interlace == 1 {
+        &ADAM7
+    } else {
+        &NOT_INTERLACED
+    };
+    let pass_size = |&(x, y, step_x, step_y): &(usize, usize, usize, usize)| {
+        let size = |start: usize, step: usize, end: u32| {
+            (end as usize).saturating_sub(start).div_ceil(step)
+        };
+        (size(x, step_x, width), size(y, step_y, height))
+    };
+    let stride = |pass_width: usize| (pass_width as u64 * bits_per_pixel as u64).div_ceil(8);
+    let raw_len: u64 = passes
+        .iter()
+        .map(|pass| match pass_size(pass) {
+            (0, _) | (_, 0) => 0,
+            (w, h) => h as u64 * (1 + stride(w)),
+        })
+        .sum();
+    // NOTE: deflate can't grow data more than 1032 times, a file too short for its header is
+    //       turned down before the image gets allocated
+    if raw_len > (idat.len() as u64).saturating_mul(1032) {
+        return Err(QoiError::InvalidPng("not enough image data"));
+    }
+    let mut raw = Vec::with_capacity(raw_len as usize);
+    zlib::decompress_into(&idat, &mut raw, raw_len as usize)?;
+    if raw.len() as u64 != raw_len {
+        return Err(QoiError::InvalidPng("not enough image data"));
+    }
+
+    let max: u16 = if bit_depth == 16 { u16::MAX } else { 255 };
+    let sample_max = ((1u32 << bit_depth) - 1) as u16;
+    let sample = |row: &[u8], index: usize| -> u16 {
+        match bit_depth {
+            16 => u16::from_be_bytes([row[2 * index], row[2 * index + 1]]),
+            8 => row[index] as u16,
+            _ => {
+                let per_byte = 8 / bit_depth as usize;
+                let shift = 8 - bit_depth as usize * (index % per_byte + 1);
+                ((row[index / per_byte] >> shift) & ((1 << bit_depth) - 1)) as u16
+            }
+        }
+    };
+    let mut samples = vec![0u16; width as usize * height as usize * depth];
+    let mut offset = 0;
+    for pass in passes {
+        let (pass_width, pass_height) = pass_size(pass);
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+        let &(start_x, start_y, step_x, step_y) = pass;
+        let stride = stride(pass_width) as usize;
+        for py in 0..pass_height {
+            let at = offset + py * (stride + 1);
+            let (before, rest) = raw.split_at_mut(at + 1);
+            let row = &mut rest[..stride];
+            let previous = (py > 0).then(|| &before[at - stride..at]);
+            unfilter(before[at], row, previous, filter_step)?;
+
+            let y = start_y + py * step_y;
+            for px in 0..pass_width {
+                let x = start_x + px * step_x;
+                let pixel = (y * width as usize + x) * depth;
+                let out = &mut samples[pixel..pixel + depth];
+                match color_type {
+                    3 => {
+                        let Some(entry) = palette.get(sample(row, px) as usize) else {
+                            return Err(QoiError::InvalidPng("palette index out of range"));
+                        };
+                        out.copy_from_slice(&entry[..depth]);
+                    }
+                    0 | 2 => {
+                        let mut color = [0u16; 3];
+                        for (c, value) in color[..channels].iter_mut().enumerate() {
+                            *value = sample(row, px * channels + c);
+                        }
+                        let clear = transparent.is_some_and(|t| t[..channels] == color[..channels]);
+                        for (out, &value) in out.iter_mut().zip(&color[..channels]) {
+                            // gray under 8 bits is stretched over 0..=255
+                            *out = rescale(value, sample_max, max);
+                        }
+                        if depth > channels {
+                            out[channels] = if clear { 0 } else { max };
+                        }
+                    }
+                    _ => {
+                        for (c, out) in out.iter_mut().enumerate() {
+                            *out = sample(row, px * channels + c);
+                        }
+                    }
+                }
+            }
+        }
+        offset += pass_height * (stride + 1);
+    }
+
+    Ok(Png {
+        width,
+        height,
+        bit_depth,
+        color_type,
+        interlaced: interlace == 1,
+        depth: depth as u8,
+        samples,
+    })
+}
+
+// what filter `filter` predicts a byte from, its left, above and upper left neighbours
+#[inline(always)]
+fn predict(filter: u8, left: u8, above: u8, upper_left: u8) -> u8 {
+    match filter {
+        1 => left,
+        2 => above,
+        3 => ((left as u16 + above as u16) / 2) as u8,
+        4 => {
+            let estimate = left as i16 + above as i16 - upper_left as i16;
+            let (to_left, to_above, to_upper_left) = (
+                (estimate - left as i16).abs(),
+                (estimate - above as i16).abs(),
+                (estimate - upper_left as i16).abs(),
+            );
+            if to_left <= to_above && to_left <= to_upper_left {
+                left
+            } else if to_above <= to_upper_left {
+                above
+            } else {
+                upper_left
+            }
+        }
+        _ => 0,
+    }
+}
+
+// undoes `filter` on `row` in place, `previous` is the row above it once unfiltered
+fn unfilter(
+    filter: u8,
+    row: &mut [u8],
+    previous: Option<&[u8]>,
+    step: usize,
+) -> Result<(), QoiError> {
+    if filter > 4 {
+        return Err(QoiError::InvalidPng("unknown filter type"));
+    }
+    if filter == 0 {
+        return Ok(());
+    }
+    for i in 0..row.len() {
+        let left = if i >= step { row[i - step] } else { 0 };
+        let (above, upper_left) = match previous {
+            Some(previous) => (previous[i], if i >= step { previous[i - step] } else { 0 }),
+            None => (0, 0),
+        };
+        row[i] = row[i].wrapping_add(predict(filter, left, above, upper_left));
+    }
+    Ok(())
+}
+
+/// Writes `pixels` as an 8-bit PNG, RGBA with [`Channels::Rgba`] and RGB otherwise.
+pub fn write_png(pixels: &[Pixel], width: u32, height: u32, chanels: Channels) -> Vec<u8> {
+    let depth = chanels as usize;
+    let mut raw = Vec::with_capacity(pixels.len() * depth);
+    for pixel in pixels {
+        let (r, g, b, a) = pixel.extract();
+        raw.extend_from_slice(&[r, g, b, a][..depth]);
+    }
+    write(&raw, width, height, chanels, 8)
+}
+
+/// Same as [`write_png`] for 16-bit pixels, written as a 16-bit PNG.
+pub fn write_png_16(pixels: &[Pixel16], width: u32, height: u32, chanels: Channels) -> Vec<u8> {
+    let depth = chanels as usize;
+    let mut raw = Vec::with_capacity(pixels.len() * depth * 2);
+    for pixel in pixels {
+        let (r, g, b, a) = pixel.extract();
+        for sample in &[r, g, b, a][..depth] {
+            raw.extend_from_slice(&sample.to_be_bytes());
+        }
+    }
+    write(&raw, width, height, chanels, 16)
+}
+
+fn write(raw: &[u8], width: u32, height: u32, chanels: Channels, bit_depth: u8) -> Vec<u8> {
+    let step = chanels as usize * bit_depth as usize / 8;
+    let stride = width as usize * step;
+    let mut filtered = Vec::with_capacity(raw.len() + height as usize);
+    let mut candidate = vec![0u8; stride];
+    let mut best = vec![0u8; stride];
+    if stride > 0 {
+        for (y, row) in raw.chunks_exact(stride).enumerate() {
+            let previous = (y > 0).then(|| &raw[(y - 1) * stride..y * stride]);
+            let mut best_score = u64::MAX;
+            let mut best_filter = 0;
+            for filter in 0..5 {
+                for i in 0..stride {
+                    let left = if i >= step { row[i - step] } else { 0 };
+                    let (above, upper_left) = match previous {
+                        Some(previous) => {
+                            (previous[i], if i >= step { previous[i - step] } else { 0 })
+                        }
+                        None => (0, 0),
+                    };
+                    candidate[i] = row[i].wrapping_sub(predict(filter, left, above, upper_left));
+                }
+                // the bytes read as signed, the closer to 0 the better they compress
+                let score = candidate
+                    .iter()
+                    .map(|&b| (b as i8).unsigned_abs() as u64)
+                    .sum();
+                if score < best_score {
+                    best_score = score;
+                    best_filter = filter;
+                    core::mem::swap(&mut best, &mut candidate);
+                }
+            }
+            filtered.push(best_filter);
+            filtered.extend_from_slice(&best);
+        }
+    }
+
+    let color_type = match chanels {
+        Channels::Rgb => 2,
+        Channels::Rgba => 6,
+    };
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+
+    let compressed = zlib::compress(&filtered);
+    let mut output = Vec::with_capacity(compressed.len() + 64);
+    output.extend_from_slice(&SIGNATURE);
+    write_chunk(&mut output, b"IHDR", &header);
+    write_chunk(&mut output, b"IDAT", &compressed);
+    write_chunk(&mut output, b"IEND", &[]);
+    output
+}
+
+fn write_chunk(output: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    output.extend_from_slice(kind);
+    output.extend_from_slice(data);
+    let crc = zlib::crc32_update(zlib::crc32(kind), data);
+    output.extend_from_slice(&crc.to_be_bytes());
+}
+
+impl Png {
+    #[inline(always)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    #[inline(always)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    /// Bits per sample in the file, 1, 2, 4, 8 or 16
+    #[inline(always)]
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+    /// 0 gray, 2 RGB, 3 palette, 4 gray and alpha, 6 RGBA
+    #[inline(always)]
+    pub fn color_type(&self) -> u8 {
+        self.color_type
+    }
+    #[inline(always)]
+    pub fn interlaced(&self) -> bool {
+        self.interlaced
+    }
+    /// Samples per decoded pixel: 1 gray, 2 gray and alpha, 3 RGB or 4 RGBA. Palettes are
+    /// expanded and `tRNS` adds an alpha.
+    #[inline(always)]
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+    /// 65535 for 16-bit files, 255 for the others
+    #[inline(always)]
+    pub fn maxval(&self) -> u16 {
+        if self.bit_depth == 16 { u16::MAX } else { 255 }
+    }
+    #[inline(always)]
+    pub fn has_alpha(&self) -> bool {
+        self.depth == 2 || self.depth == 4
+    }
+    /// The decoded samples, row-major with `depth` samples per pixel.
+    #[inline(always)]
+    pub fn samples(&self) -> &[u16] {
+        &self.samples
+    }
+
+    /// The samples of every pixel as r, g, b, a in `0..=maxval`, gray is spread over the three
+    /// colors and the alpha is `maxval` unless the image has one.
+    pub fn rgba(&self) -> impl Iterator<Item = [u16; 4]> + '_ {
+        let maxval = self.maxval();
+        self.samples
+            .chunks_exact(self.depth as usize)
+            .map(move |pixel| match *pixel {
+                [gray] => [gray, gray, gray, maxval],
+                [gray, a] => [gray, gray, gray, a],
+                [r, g, b] => [r, g, b, maxval],
+                [r, g, b, a] => [r, g, b, a],
+                _ => unreachable!(),
+            })
+    }
+
+    /// The pixels rescaled to 16 bits.
+    pub fn to_pixels16(&self) -> Vec<Pixel16> {
+        let maxval = self.maxval();
+        self.rgba()
+            .map(|[r, g, b, a]| {
+                let scale = |sample| rescale(sample, maxval, u16::MAX);
+                Pixel16::new(scale(r), scale(g), scale(b), scale(a))
+            })
+            .collect()
+    }
+
+    /// The pixels rescaled to 8 bits.
+    pub fn to_pixels(&self) -> Vec<Pixel> {
+        let maxval = self.maxval();
+        self.rgba()
+            .map(|[r, g, b, a]| {
+                let scale = |sample| rescale(sample, maxval, 255) as u8;
+                Pixel::new(scale(r), scale(g), scale(b), scale(a))
+            })
+            .collect()
+    }
+}
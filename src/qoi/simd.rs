@@ -0,0 +1,204 @@
+//! Block-wise helpers for the encoder: run-length scanning and the classification of each
+//! pixel against the one before it.
+//!
+//! On x86_64 with the `std` feature AVX2 is detected at runtime and 8 pixels are handled at a
+//! time, everywhere else the scalar versions are used. Both give exactly the same results, the
+//! encoder output doesn't depend on which one ran.
+
+use crate::qoi::types::Pixel;
+
+/// The pixel is the same as the previous one
+pub const EQUAL: u8 = 1;
+/// The alpha didn't change, a QoiOpRGB is enough when nothing smaller fits
+pub const ALPHA_EQUAL: u8 = 2;
+/// The difference fits a QoiOpDiff
+pub const DIFF: u8 = 4;
+/// The difference fits a QoiOpLuma
+pub const LUMA: u8 = 8;
+
+// the pixel as it sits in memory, r in the lowest byte
+#[inline(always)]
+fn to_u32(pixel: Pixel, opaque: bool) -> u32 {
+    let (r, g, b, a) = pixel.extract();
+    let value = u32::from_le_bytes([r, g, b, a]);
+    if opaque { value | 0xFF00_0000 } else { value }
+}
+
+/// Classifies `pixel` against `prev` as a combination of [`EQUAL`], [`ALPHA_EQUAL`], [`DIFF`]
+/// and [`LUMA`]. With `opaque` the alpha of both is taken as 255.
+#[inline(always)]
+pub fn classify_pixel(pixel: Pixel, prev: Pixel, opaque: bool) -> u8 {
+    let now = to_u32(pixel, opaque).to_le_bytes();
+    let before = to_u32(prev, opaque).to_le_bytes();
+    if now[3] != before[3] {
+        return 0;
+    }
+    let dr = now[0].wrapping_sub(before[0]);
+    let dg = now[1].wrapping_sub(before[1]);
+    let db = now[2].wrapping_sub(before[2]);
+    let mut class = ALPHA_EQUAL;
+    if dr == 0 && dg == 0 && db == 0 {
+        class |= EQUAL;
+    }
+    // with their bias added the differences must fit in their fields
+    if (dr.wrapping_add(2) | dg.wrapping_add(2) | db.wrapping_add(2)) < 4 {
+        class |= DIFF;
+    }
+    let dr_dg = dr.wrapping_sub(dg).wrapping_add(8);
+    let db_dg = db.wrapping_sub(dg).wrapping_add(8);
+    if dg.wrapping_add(32) < 64 && (dr_dg | db_dg) < 16 {
+        class |= LUMA;
+    }
+    class
+}
+
+/// Scalar version of [`classify`].
+pub fn classify_scalar(pixels: &[Pixel], prev: Pixel, opaque: bool, classes: &mut [u8]) {
+    let mut prev = prev;
+    for (pixel, class) in pixels.iter().zip(classes.iter_mut()) {
+        *class = classify_pixel(*pixel, prev, opaque);
+        prev = *pixel;
+    }
+}
+
+/// Classifies every pixel against the one before it, the first one against `prev`. `classes`
+/// must be at least as long as `pixels`.
+pub fn classify(pixels: &[Pixel], prev: Pixel, opaque: bool, classes: &mut [u8]) {
+    assert!(classes.len() >= pixels.len());
+    #[cfg(all(feature = "std", target_arch = "x86_64"))]
+    if std::is_x86_feature_detected!("avx2") {
+        // SAFETY: AVX2 is available, checked just above
+        unsafe { avx2::classify(pixels, prev, opaque, classes) };
+        return;
+    }
+    classify_scalar(pixels, prev, opaque, classes)
+}
+
+/// Scalar version of [`run_length`].
+pub fn run_length_scalar(pixels: &[Pixel], prev: Pixel, opaque: bool) -> usize {
+    let prev = to_u32(prev, opaque);
+    pixels
+        .iter()
+        .position(|pixel| to_u32(*pixel, opaque) != prev)
+        .unwrap_or(pixels.len())
+}
+
+/// Number of pixels at the start of `pixels` that are the same as `prev`.
+pub fn run_length(pixels: &[Pixel], prev: Pixel, opaque: bool) -> usize {
+    #[cfg(all(feature = "std", target_arch = "x86_64"))]
+    if std::is_x86_feature_detected!("avx2") {
+        // SAFETY: AVX2 is available, checked just above
+        return unsafe { avx2::run_length(pixels, prev, opaque) };
+    }
+    run_length_scalar(pixels, prev, opaque)
+}
+
+#[cfg(all(feature = "std", target_arch = "x86_64"))]
+mod avx2 {
+    use core::arch::x86_64::*;
+
+    use super::{ALPHA_EQUAL, DIFF, EQUAL, LUMA, classify_pixel, to_u32};
+    use crate::qoi::types::Pixel;
+
+    const LANES: usize = 8;
+
+    // loads the first 8 pixels, `Pixel` is `repr(C)` so they are 8 little-endian u32 with r in
+    // the lowest byte
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    fn load(pixels: &[Pixel], alpha: __m256i) -> __m256i {
+        assert!(pixels.len() >= LANES);
+        // SAFETY: 8 pixels of 4 bytes are readable, loadu has no alignment requirement
+        let lanes = unsafe { _mm256_loadu_si256(pixels.as_ptr().cast()) };
+        _mm256_or_si256(lanes, alpha)
+    }
+
+    // one bit per pixel, set when all 4 bytes of its lane are set
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    fn mask(lanes: __m256i) -> u32 {
+        _mm256_movemask_ps(_mm256_castsi256_ps(lanes)) as u32
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) fn classify(pixels: &[Pixel], prev: Pixel, opaque: bool, classes: &mut [u8]) {
+        let alpha = _mm256_set1_epi32(if opaque { 0xFF00_0000u32 as i32 } else { 0 });
+        let zero = _mm256_setzero_si256();
+        let alpha_bits = _mm256_set1_epi32(0xFF00_0000u32 as i32);
+        let diff_bias = _mm256_set1_epi32(0x0002_0202);
+        let diff_bits = _mm256_set1_epi32(0x00FC_FCFC);
+        let low = _mm256_set1_epi32(0x0000_00FF);
+        let high = _mm256_set1_epi32(0x00FF_0000);
+        let luma_bias = _mm256_set1_epi32(0x0008_2008);
+        let luma_bits = _mm256_set1_epi32(0x00F0_C0F0);
+        // the low byte of the 4 lanes of each 128-bit half to the front of that half
+        let gather = _mm256_setr_epi8(
+            0, 4, 8, 12, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, 0, 4, 8, 12, -1, -1, -1,
+            -1, -1, -1, -1, -1, -1, -1, -1, -1,
+        );
+
+        if pixels.is_empty() {
+            return;
+        }
+        classes[0] = classify_pixel(pixels[0], prev, opaque);
+        // each pixel is compared with the one right before it in memory
+        let mut i = 1;
+        while i + LANES <= pixels.len() {
+            let now = load(&pixels[i..], alpha);
+            let before = load(&pixels[i - 1..], alpha);
+            let diff = _mm256_sub_epi8(now, before);
+
+            let equal = _mm256_cmpeq_epi32(now, before);
+            let alpha_equal = _mm256_cmpeq_epi32(_mm256_and_si256(diff, alpha_bits), zero);
+            let small = _mm256_and_si256(_mm256_add_epi8(diff, diff_bias), diff_bits);
+            let small = _mm256_cmpeq_epi32(small, zero);
+            // dg goes under dr (byte 0) and db (byte 2) so a single subtraction gives dr - dg,
+            // dg and db - dg
+            let green = _mm256_or_si256(
+                _mm256_and_si256(_mm256_srli_epi32(diff, 8), low),
+                _mm256_and_si256(_mm256_slli_epi32(diff, 8), high),
+            );
+            let luma = _mm256_add_epi8(_mm256_sub_epi8(diff, green), luma_bias);
+            let luma = _mm256_cmpeq_epi32(_mm256_and_si256(luma, luma_bits), zero);
+
+            // the class ends up in the low byte of each lane, a change of alpha rules out every
+            // op but QoiOpRGBA
+            let class = _mm256_or_si256(
+                _mm256_or_si256(
+                    _mm256_and_si256(equal, _mm256_set1_epi32(EQUAL as i32)),
+                    _mm256_and_si256(small, _mm256_set1_epi32(DIFF as i32)),
+                ),
+                _mm256_or_si256(
+                    _mm256_and_si256(luma, _mm256_set1_epi32(LUMA as i32)),
+                    _mm256_set1_epi32(ALPHA_EQUAL as i32),
+                ),
+            );
+            let class = _mm256_shuffle_epi8(_mm256_and_si256(class, alpha_equal), gather);
+            let low_half = _mm256_extract_epi32::<0>(class) as u32;
+            let high_half = _mm256_extract_epi32::<4>(class) as u32;
+            classes[i..i + 4].copy_from_slice(&low_half.to_le_bytes());
+            classes[i + 4..i + LANES].copy_from_slice(&high_half.to_le_bytes());
+            i += LANES;
+        }
+        while i < pixels.len() {
+            classes[i] = classify_pixel(pixels[i], pixels[i - 1], opaque);
+            i += 1;
+        }
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) fn run_length(pixels: &[Pixel], prev: Pixel, opaque: bool) -> usize {
+        let alpha = _mm256_set1_epi32(if opaque { 0xFF00_0000u32 as i32 } else { 0 });
+        let wanted = _mm256_set1_epi32(to_u32(prev, opaque) as i32);
+        let mut i = 0;
+        while i + LANES <= pixels.len() {
+            let now = load(&pixels[i..], alpha);
+            let equal = mask(_mm256_cmpeq_epi32(now, wanted));
+            if equal != 0xFF {
+                return i + equal.trailing_ones() as usize;
+            }
+            i += LANES;
+        }
+        i + super::run_length_scalar(&pixels[i..], prev, opaque)
+    }
+}
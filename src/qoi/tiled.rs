@@ -0,0 +1,311 @@
+//! A container of independently encoded QOI tiles, so part of a large image can be decoded
+//! without going through the rest of it.
+//!
+//! Layout, every number big-endian:
+//!
+//! | offset | size | field                                                       |
+//! |--------|------|-------------------------------------------------------------|
+//! | 0      | 4    | magic `qoit`                                                |
+//! | 4      | 4    | width                                                       |
+//! | 8      | 4    | height                                                      |
+//! | 12     | 1    | chanels                                                     |
+//! | 13     | 1    | colorspace                                                  |
+//! | 14     | 4    | tile size, tiles are square except on the right and bottom  |
+//! | 18     | 8*n  | offset table, one u64 per tile in row-major order, then the |
+//! |        |      | end of the last tile, all from the start of the file        |
+//!
+//! Each tile is a complete QOI stream with its own header, index and `prev`, of the tile's own
+//! width and height.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::qoi::decoder::{Decoder, check_plausible};
+use crate::qoi::encoder::{Encoder, chanels_for};
+use crate::qoi::error::QoiError;
+use crate::qoi::limits::DecodeLimits;
+use crate::qoi::options::EncoderOptions;
+use crate::qoi::types::{Channels, ColorSpace, Pixel};
+
+const MAGIC: [u8; 4] = *b"qoit";
+const HEADER_SIZE: usize = 18;
+
+/// Encodes `image` as tiles of `tile_size` by `tile_size` pixels, see [`crate::qoi::tiled`].
+///
+/// The chanels are picked once for the whole image, every tile gets the same.
+pub fn encode_tiled(
+    image: &[Pixel],
+    width: u32,
+    height: u32,
+    tile_size: u32,
+    options: &EncoderOptions,
+) -> Result<Vec<u8>, QoiError> {
+    let expected = width as u64 * height as u64;
+    if image.len() as u64 != expected {
+        return Err(QoiError::PixelCountMismatch {
+            expected,
+            actual: image.len() as u64,
+        });
+    }
+    if tile_size == 0 {
+        return Err(QoiError::InvalidTiled("the tile size is 0"));
+    }
+    let chanels = chanels_for(image, options);
+    let mut encoder = Encoder::with_options(options.chanels(chanels));
+    let tiles_x = width.div_ceil(tile_size);
+    let tiles_y = height.div_ceil(tile_size);
+    let tiles = tiles_x as usize * tiles_y as usize;
+
+    let mut output = Vec::with_capacity(HEADER_SIZE + (tiles + 1) * 8 + image.len() * 4);
+    output.extend_from_slice(&MAGIC);
+    output.extend_from_slice(&width.to_be_bytes());
+    output.extend_from_slice(&height.to_be_bytes());
+    output.push(chanels as u8);
+    output.push(options.colorspace as u8);
+    output.extend_from_slice(&tile_size.to_be_bytes());
+    // the table is filled in as the tiles are written
+    let table = output.len();
+    output.resize(table + (tiles + 1) * 8, 0);
+
+    let mut tile = Vec::new();
+    for ty in 0..tiles_y {
+        for tx in 0..tiles_x {
+            let (x, y, w, h) = tile_bounds(width, height, tile_size, tx, ty);
+            tile.clear();
+            for row in y..y + h {
+                let start = row as usize * width as usize + x as usize;
+                tile.extend_from_slice(&image[start..start + w as usize]);
+            }
+            let entry = table + (ty as usize * tiles_x as usize + tx as usize) * 8;
+            let offset = output.len() as u64;
+            output[entry..entry + 8].copy_from_slice(&offset.to_be_bytes());
+            let encoded = encoder.encode(&tile, w, h)?;
+            output.extend_from_slice(encoded);
+        }
+    }
+    let entry = table + tiles * 8;
+    let offset = output.len() as u64;
+    output[entry..entry + 8].copy_from_slice(&offset.to_be_bytes());
+    Ok(output)
+}
+
+// the pixels covered by tile `tx`, `ty` as (x, y, width, height)
+#[inline(always)]
+fn tile_bounds(width: u32, height: u32, tile_size: u32, tx: u32, ty: u32) -> (u32, u32, u32, u32) {
+    let x = tx * tile_size;
+    let y = ty * tile_size;
+    (x, y, tile_size.min(width - x), tile_size.min(height - y))
+}
+
+/// A parsed tiled file, tiles are only decoded when asked for.
+#[derive(Clone, Debug)]
+pub struct TiledImage<'a> {
+    bytes: &'a [u8],
+    width: u32,
+    height: u32,
+    chanels: Channels,
+    colorspace: ColorSpace,
+    tile_size: u32,
+    limits: DecodeLimits,
+}
+
+impl<'a> TiledImage<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, QoiError> {
+        Self::parse_with_limits(bytes, &DecodeLimits::default())
+    }
+
+    /// Reads the header and checks the offset table, `limits` apply to every tile and region
+    /// decoded, not to the whole image which is never held in memory.
+    pub fn parse_with_limits(bytes: &'a [u8], limits: &DecodeLimits) -> Result<Self, QoiError> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(QoiError::InvalidTiled("truncated header"));
+        }
+        if bytes[0..4] != MAGIC {
+            return Err(QoiError::InvalidMagic([
+                bytes[0], bytes[1], bytes[2], bytes[3],
+            ]));
+        }
+        let word = |at: usize| {
+            u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+        };
+        let image = Self {
+            bytes,
+            width: word(4),
+            height: word(8),
+            chanels: Channels::try_from(bytes[12])?,
+            colorspace: ColorSpace::try_from(bytes[13])?,
+            tile_size: word(14),
+            limits: *limits,
+        };
+        if image.tile_size == 0 {
+            return Err(QoiError::InvalidTiled("the tile size is 0"));
+        }
+
+        // NOTE: the table size comes from the header, it has to fit in the file before anything
+        //       is read from it
+        let entries = image.tiles_x() as u64 * image.tiles_y() as u64 + 1;
+        let table_len = entries.saturating_mul(8);
+        if table_len > (bytes.len() - HEADER_SIZE) as u64 {
+            return Err(QoiError::InvalidTiled("truncated offset table"));
+        }
+        let data = HEADER_SIZE as u64 + table_len;
+        let mut previous = data;
+        for i in 0..entries as usize {
+            let offset = image.offset(i);
+            if offset < previous || offset > bytes.len() as u64 {
+                return Err(QoiError::InvalidTiled(
+                    "offset out of order or past the end",
+                ));
+            }
+            previous = offset;
+        }
+        if image.offset(0) != data {
+            return Err(QoiError::InvalidTiled(
+                "the first tile doesn't follow the table",
+            ));
+        }
+        Ok(image)
+    }
+
+    #[inline(always)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    #[inline(always)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    #[inline(always)]
+    pub fn chanels(&self) -> Channels {
+        self.chanels
+    }
+    #[inline(always)]
+    pub fn colorspace(&self) -> ColorSpace {
+        self.colorspace
+    }
+    #[inline(always)]
+    pub fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+    /// Number of tile columns
+    #[inline(always)]
+    pub fn tiles_x(&self) -> u32 {
+        self.width.div_ceil(self.tile_size)
+    }
+    /// Number of tile rows
+    #[inline(always)]
+    pub fn tiles_y(&self) -> u32 {
+        self.height.div_ceil(self.tile_size)
+    }
+
+    // entry `i` of the offset table, checked by `parse_with_limits`
+    #[inline(always)]
+    fn offset(&self, i: usize) -> u64 {
+        let at = HEADER_SIZE + i * 8;
+        u64::from_be_bytes(self.bytes[at..at + 8].try_into().unwrap())
+    }
+
+    /// The pixels tile `tx`, `ty` covers, as (x, y, width, height).
+    pub fn tile_bounds(&self, tx: u32, ty: u32) -> Result<(u32, u32, u32, u32), QoiError> {
+        if tx >= self.tiles_x() || ty >= self.tiles_y() {
+            return Err(QoiError::RegionOutOfBounds {
+                x: tx.saturating_mul(self.tile_size),
+                y: ty.saturating_mul(self.tile_size),
+                width: self.tile_size,
+                height: self.tile_size,
+            });
+        }
+        Ok(tile_bounds(self.width, self.height, self.tile_size, tx, ty))
+    }
+
+    /// The QOI stream of tile `tx`, `ty`, as it is in the file.
+    pub fn tile_bytes(&self, tx: u32, ty: u32) -> Result<&'a [u8], QoiError> {
+        self.tile_bounds(tx, ty)?;
+        let i = ty as usize * self.tiles_x() as usize + tx as usize;
+        Ok(&self.bytes[self.offset(i) as usize..self.offset(i + 1) as usize])
+    }
+
+    /// Decodes tile `tx`, `ty`, the pixels are in row-major order with the tile's own width.
+    pub fn decode_tile(&self, tx: u32, ty: u32) -> Result<Vec<Pixel>, QoiError> {
+        let mut decoder = Decoder::new().with_limits(self.limits);
+        self.decode_tile_with(&mut decoder, tx, ty)
+            .map(|pixels| pixels.to_vec())
+    }
+
+    fn decode_tile_with<'d>(
+        &self,
+        decoder: &'d mut Decoder,
+        tx: u32,
+        ty: u32,
+    ) -> Result<&'d [Pixel], QoiError> {
+        let (_, _, width, height) = self.tile_bounds(tx, ty)?;
+        let (header, pixels) = decoder.decode(self.tile_bytes(tx, ty)?)?;
+        if header.width() != width
+            || header.height() != height
+            || header.chanels() != self.chanels as u8
+            || header.colorspace() != self.colorspace as u8
+        {
+            return Err(QoiError::InvalidTiled(
+                "a tile header doesn't match the file",
+            ));
+        }
+        Ok(pixels)
+    }
+
+    /// Decodes the `width` by `height` region at `x`, `y`, only the tiles it overlaps are
+    /// decoded. The pixels are in row-major order with a row of `width`.
+    pub fn decode_region(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<Pixel>, QoiError> {
+        let inside =
+            |start: u32, len: u32, end: u32| start.checked_add(len).is_some_and(|e| e <= end);
+        if !inside(x, width, self.width) || !inside(y, height, self.height) {
+            return Err(QoiError::RegionOutOfBounds {
+                x,
+                y,
+                width,
+                height,
+            });
+        }
+        self.limits.check(width, height, 4)?;
+        if width == 0 || height == 0 {
+            return Ok(Vec::new());
+        }
+        let tiles_x = x / self.tile_size..=(x + width - 1) / self.tile_size;
+        let tiles_y = y / self.tile_size..=(y + height - 1) / self.tile_size;
+        // NOTE: the region is only allocated once every tile it overlaps is long enough for
+        //       its pixels, a lying header can't make it allocate more than the file backs
+        for ty in tiles_y.clone() {
+            for tx in tiles_x.clone() {
+                let (_, _, tile_width, tile_height) = self.tile_bounds(tx, ty)?;
+                let pixels = tile_width as u64 * tile_height as u64;
+                check_plausible(pixels, self.tile_bytes(tx, ty)?.len())?;
+            }
+        }
+        let mut region = vec![Pixel::default(); width as usize * height as usize];
+        let mut decoder = Decoder::new().with_limits(self.limits);
+        for ty in tiles_y {
+            for tx in tiles_x.clone() {
+                let (tile_x, tile_y, tile_width, tile_height) = self.tile_bounds(tx, ty)?;
+                let pixels = self.decode_tile_with(&mut decoder, tx, ty)?;
+                // the part of the tile inside the region
+                let left = x.max(tile_x);
+                let right = (x + width).min(tile_x + tile_width);
+                let top = y.max(tile_y);
+                let bottom = (y + height).min(tile_y + tile_height);
+                for row in top..bottom {
+                    let from =
+                        (row - tile_y) as usize * tile_width as usize + (left - tile_x) as usize;
+                    let to = (row - y) as usize * width as usize + (left - x) as usize;
+                    let len = (right - left) as usize;
+                    region[to..to + len].copy_from_slice(&pixels[from..from + len]);
+                }
+            }
+        }
+        Ok(region)
+    }
+}
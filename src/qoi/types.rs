@@ -1,14 +1,19 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::qoi::error::QoiError;
 use crate::qoi::types16::Pixel16;
 
 pub struct Range<T> {
-    lower_limit: T,
-    upper_limit: T,
+    pub(crate) lower_limit: T,
+    pub(crate) upper_limit: T,
 }
 
 impl<T> Range<T>
 where
     T: PartialOrd,
 {
+    #[allow(clippy::result_unit_err)]
     pub fn new(lower: T, upper: T) -> Result<Self, ()> {
         if lower < upper {
             Ok(Self {
@@ -21,7 +26,10 @@ where
     }
 }
 
-#[derive(PartialOrd, PartialEq, Clone, Copy, Default)]
+// NOTE: repr(C) keeps the bytes in r, g, b, a order, crate::qoi::simd loads pixels straight from
+//       memory
+#[derive(PartialOrd, PartialEq, Clone, Copy, Default, Debug)]
+#[repr(C)]
 pub struct Pixel {
     r: u8,
     g: u8,
@@ -138,6 +146,67 @@ impl PixelDiff {
     }
 }
 
+/// The chanels field of the header, it is informative only and doesn't change how chunks are
+/// decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channels {
+    Rgb = 3,
+    Rgba = 4,
+}
+
+impl TryFrom<u8> for Channels {
+    type Error = QoiError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            3 => Ok(Channels::Rgb),
+            4 => Ok(Channels::Rgba),
+            _ => Err(QoiError::InvalidChannels(value)),
+        }
+    }
+}
+
+/// The colorspace field of the header, informative only as well.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// sRGB with linear alpha
+    Srgb = 0,
+    /// all chanels linear
+    Linear = 1,
+}
+
+impl TryFrom<u8> for ColorSpace {
+    type Error = QoiError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ColorSpace::Srgb),
+            1 => Ok(ColorSpace::Linear),
+            _ => Err(QoiError::InvalidColorspace(value)),
+        }
+    }
+}
+
+/// How decoded pixels are laid out in a caller-provided byte buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelLayout {
+    /// 3 bytes per pixel, alpha dropped
+    Rgb8,
+    /// 4 bytes per pixel
+    Rgba8,
+}
+
+impl PixelLayout {
+    #[inline(always)]
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            PixelLayout::Rgb8 => 3,
+            PixelLayout::Rgba8 => 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct QoiHeader {
     magic_0: u8,
     magic_1: u8,
@@ -163,6 +232,55 @@ impl QoiHeader {
             colorspace,
         }
     }
+    /// Parses and validates the 14-byte header at the start of `bytestream`, the rest of the
+    /// stream isn't looked at.
+    pub fn parse(bytestream: &[u8]) -> Result<Self, QoiError> {
+        if bytestream.len() < 14 {
+            return Err(QoiError::TruncatedHeader(bytestream.len()));
+        }
+        if bytestream[0..4] != [0x71, 0x6F, 0x69, 0x66] {
+            return Err(QoiError::InvalidMagic([
+                bytestream[0],
+                bytestream[1],
+                bytestream[2],
+                bytestream[3],
+            ]));
+        }
+        let width =
+            u32::from_be_bytes([bytestream[4], bytestream[5], bytestream[6], bytestream[7]]);
+        let height =
+            u32::from_be_bytes([bytestream[8], bytestream[9], bytestream[10], bytestream[11]]);
+        let chanels = bytestream[12];
+        let colorspace = bytestream[13];
+        if !(3..=4).contains(&chanels) {
+            return Err(QoiError::InvalidChannels(chanels));
+        }
+        if colorspace > 1 {
+            return Err(QoiError::InvalidColorspace(colorspace));
+        }
+        Ok(Self::new(width, height, chanels, colorspace))
+    }
+    #[inline(always)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    #[inline(always)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    #[inline(always)]
+    pub fn chanels(&self) -> u8 {
+        self.chanels
+    }
+    #[inline(always)]
+    pub fn colorspace(&self) -> u8 {
+        self.colorspace
+    }
+    /// Number of pixels described by the header, `width * height`.
+    #[inline(always)]
+    pub fn pixel_count(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
     // PERFORMANCE: so many unnecessary memory allocations to vectors for every single QoiOp
     //              Dont use unless absolutely neccessary
     #[deprecated]
@@ -213,7 +331,7 @@ impl QoiOpRGB {
     #[inline(always)]
     pub fn new(r: u8, g: u8, b: u8) -> Self {
         Self {
-            tag: 0b11111100,
+            tag: 0b11111110,
             r,
             g,
             b,
@@ -267,6 +385,7 @@ impl QoiOpRGBA {
         output.push(self.r);
         output.push(self.g);
         output.push(self.b);
+        output.push(self.a);
         output.to_vec()
     }
     pub fn append_self(&self, bytestream: &mut Vec<u8>) {
@@ -274,6 +393,7 @@ impl QoiOpRGBA {
         bytestream.push(self.r);
         bytestream.push(self.g);
         bytestream.push(self.b);
+        bytestream.push(self.a);
     }
 }
 
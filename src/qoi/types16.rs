@@ -1,6 +1,33 @@
+//! QOI16, the 16-bit per channel extension of QOI used for `max_col_val > 255` images.
+//!
+//! A QOI16 stream is laid out like a QOI one, with every sample widened to 16 bits:
+//!
+//! ```text
+//! header      "qo16" | width: u32 BE | height: u32 BE | chanels: u8 | colorspace: u8
+//! chunks      see below, samples are u16 BE
+//! end marker  [0, 0, 0, 0, 0, 0, 0, 1]
+//! ```
+//!
+//! | chunk             | layout                                                        |
+//! |-------------------|---------------------------------------------------------------|
+//! | `QOI16_OP_RGB`    | `0xFE` r g b                                                  |
+//! | `QOI16_OP_RGBA`   | `0xFF` r g b a                                                |
+//! | `QOI16_OP_INDEX`  | `0b00` index (6 bits)                                         |
+//! | `QOI16_OP_DIFF`   | `0b01` dr dg db (2 bits each, -2..=1, bias 2)                 |
+//! | `QOI16_OP_LUMA`   | `0b10` dg (10 bits, -512..=511, bias 512)                     |
+//! |                   | dr - dg (6 bits, -32..=31, bias 32) db - dg (6 bits, bias 32) |
+//! | `QOI16_OP_RUN`    | `0b11` run - 1 (6 bits, 1..=62)                               |
+//!
+//! Differences wrap around like in QOI, only modulo 65536. The index hash is
+//! `(r * 3 + g * 5 + b * 7 + a * 11) % 64` computed without overflow. `QOI16_OP_LUMA` is one
+//! byte longer than its QOI counterpart so it still covers the deltas found in 16-bit scans.
+//! Samples always span the full `0..=65535` range, whatever the `max_col_val` of the source.
+use alloc::vec::Vec;
+
+use crate::qoi::error::QoiError;
 use crate::qoi::types::Range;
 
-#[derive(PartialOrd, PartialEq, Clone, Copy, Default)]
+#[derive(PartialOrd, PartialEq, Clone, Copy, Default, Debug)]
 pub struct Pixel16 {
     r: u16,
     g: u16,
@@ -8,252 +35,217 @@ pub struct Pixel16 {
     a: u16,
 }
 impl Pixel16 {
+    #[inline(always)]
     pub fn new(r: u16, g: u16, b: u16, a: u16) -> Self {
         Self { r, g, b, a }
     }
+    #[inline(always)]
     pub fn extract(&self) -> (u16, u16, u16, u16) {
         (self.r, self.g, self.b, self.a)
     }
+    #[inline(always)]
     pub fn hash(&self) -> u8 {
-        ((self.r * 3 + self.g * 5 + self.b * 6 + self.a * 11) % 64) as u8
+        ((self.r as u32 * 3 + self.g as u32 * 5 + self.b as u32 * 7 + self.a as u32 * 11) & 63)
+            as u8
+    }
+    #[inline(always)]
+    pub fn append_self(&self, bytestream: &mut Vec<u8>) {
+        bytestream.extend_from_slice(&self.r.to_be_bytes());
+        bytestream.extend_from_slice(&self.g.to_be_bytes());
+        bytestream.extend_from_slice(&self.b.to_be_bytes());
+        bytestream.extend_from_slice(&self.a.to_be_bytes());
     }
 }
 
 #[derive(PartialOrd, PartialEq)]
 pub struct PixelDiff16 {
-    r: i8,
-    g: i8,
-    b: i8,
-    a: i8,
+    r: i16,
+    g: i16,
+    b: i16,
+    a: i16,
 }
 
 impl PixelDiff16 {
+    #[inline(always)]
     pub fn new(p1: &Pixel16, p2: &Pixel16) -> Self {
         Self {
-            r: (p1.r - p2.r) as i8,
-            g: (p1.g - p2.g) as i8,
-            b: (p1.b - p2.b) as i8,
-            a: (p1.a - p2.a) as i8,
+            r: p1.r.wrapping_sub(p2.r) as i16,
+            g: p1.g.wrapping_sub(p2.g) as i16,
+            b: p1.b.wrapping_sub(p2.b) as i16,
+            a: p1.a.wrapping_sub(p2.a) as i16,
         }
     }
-    pub fn new2(r: i8, g: i8, b: i8, a: i8) -> Self {
+    #[inline(always)]
+    pub fn new2(r: i16, g: i16, b: i16, a: i16) -> Self {
         Self { r, g, b, a }
     }
-    pub fn new_diff(p1: &Pixel16, p2: &Pixel16) -> Option<Self> {
-        if p1.a == p2.a {
-            None
-        } else {
-            Some(Self {
-                g: (p1.g - p2.g).try_into().expect(
-                    "Error Converting in gd, PixelDiff::new_diff(&Pixel, &Pixel) -> PixelDiff",
-                ),
-                r: ((p1.r - p2.r) / (p1.g - p2.g)).try_into().expect(
-                    "Error Convering in dr, PixelDiff::new_diff(&Pixel, &Pixel) -> PixelDiff",
-                ),
-                b: ((p1.b - p2.b) / (p1.g - p2.g)).try_into().expect(
-                    "Error Convering in db, PixelDiff::new_diff(&Pixel, &Pixel) -> PixelDiff",
-                ),
-                a: 0,
-            })
+    /// The differences used by `QOI16_OP_LUMA`: green as is, red and blue relative to green.
+    #[inline(always)]
+    pub fn new_diff(p1: &Pixel16, p2: &Pixel16) -> Self {
+        let diff = Self::new(p1, p2);
+        Self {
+            g: diff.g,
+            r: diff.r.wrapping_sub(diff.g),
+            b: diff.b.wrapping_sub(diff.g),
+            a: diff.a,
         }
     }
+    #[inline(always)]
     pub fn belongs(&self, range: Range<PixelDiff16>) -> bool {
-        // self.r >= range.lower_limit.r
-        //     && self.g >= range.lower_limit.g
-        //     && self.b >= range.lower_limit.b
-        //     && self.a >= range.lower_limit.a
-        //     && self.r <= range.upper_limit.r
-        //     && self.g <= range.upper_limit.g
-        //     && self.b <= range.upper_limit.b
-        //     && self.a <= range.upper_limit.a
-        todo!()
+        self.r >= range.lower_limit.r
+            && self.g >= range.lower_limit.g
+            && self.b >= range.lower_limit.b
+            && self.a >= range.lower_limit.a
+            && self.r <= range.upper_limit.r
+            && self.g <= range.upper_limit.g
+            && self.b <= range.upper_limit.b
+            && self.a <= range.upper_limit.a
     }
-    pub fn extract(&self) -> (i8, i8, i8, i8) {
+    #[inline(always)]
+    pub fn extract(&self) -> (i16, i16, i16, i16) {
         (self.r, self.g, self.b, self.a)
     }
+    #[inline(always)]
+    pub fn is_alpha_zero(&self) -> bool {
+        self.a == 0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QoiHeader16 {
+    width: u32,
+    height: u32,
+    chanels: u8,
+    colorspace: u8,
+}
+
+impl QoiHeader16 {
+    pub const MAGIC: [u8; 4] = *b"qo16";
+
+    #[inline(always)]
+    pub fn new(width: u32, height: u32, chanels: u8, colorspace: u8) -> Self {
+        Self {
+            width,
+            height,
+            chanels,
+            colorspace,
+        }
+    }
+    /// Parses and validates the 14-byte header at the start of `bytestream`.
+    pub fn parse(bytestream: &[u8]) -> Result<Self, QoiError> {
+        if bytestream.len() < 14 {
+            return Err(QoiError::TruncatedHeader(bytestream.len()));
+        }
+        if bytestream[0..4] != Self::MAGIC {
+            return Err(QoiError::InvalidMagic([
+                bytestream[0],
+                bytestream[1],
+                bytestream[2],
+                bytestream[3],
+            ]));
+        }
+        let width =
+            u32::from_be_bytes([bytestream[4], bytestream[5], bytestream[6], bytestream[7]]);
+        let height =
+            u32::from_be_bytes([bytestream[8], bytestream[9], bytestream[10], bytestream[11]]);
+        let chanels = bytestream[12];
+        let colorspace = bytestream[13];
+        if !(3..=4).contains(&chanels) {
+            return Err(QoiError::InvalidChannels(chanels));
+        }
+        if colorspace > 1 {
+            return Err(QoiError::InvalidColorspace(colorspace));
+        }
+        Ok(Self::new(width, height, chanels, colorspace))
+    }
+    #[inline(always)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    #[inline(always)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    #[inline(always)]
+    pub fn chanels(&self) -> u8 {
+        self.chanels
+    }
+    #[inline(always)]
+    pub fn colorspace(&self) -> u8 {
+        self.colorspace
+    }
+    #[inline(always)]
+    pub fn pixel_count(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+    pub fn append_self(&self, bytestream: &mut Vec<u8>) {
+        bytestream.extend_from_slice(&Self::MAGIC);
+        bytestream.extend_from_slice(&self.width.to_be_bytes());
+        bytestream.extend_from_slice(&self.height.to_be_bytes());
+        bytestream.push(self.chanels);
+        bytestream.push(self.colorspace);
+    }
+}
+
+pub struct QoiOpRGB16 {
+    r: u16,
+    g: u16,
+    b: u16,
+}
+
+impl QoiOpRGB16 {
+    #[inline(always)]
+    pub fn new(r: u16, g: u16, b: u16) -> Self {
+        Self { r, g, b }
+    }
+    pub fn append_self(&self, bytestream: &mut Vec<u8>) {
+        bytestream.push(0b11111110);
+        bytestream.extend_from_slice(&self.r.to_be_bytes());
+        bytestream.extend_from_slice(&self.g.to_be_bytes());
+        bytestream.extend_from_slice(&self.b.to_be_bytes());
+    }
+}
+
+pub struct QoiOpRGBA16 {
+    pixel: Pixel16,
+}
+
+impl QoiOpRGBA16 {
+    #[inline(always)]
+    pub fn new(r: u16, g: u16, b: u16, a: u16) -> Self {
+        Self {
+            pixel: Pixel16::new(r, g, b, a),
+        }
+    }
+    pub fn append_self(&self, bytestream: &mut Vec<u8>) {
+        bytestream.push(0b11111111);
+        self.pixel.append_self(bytestream);
+    }
+}
+
+pub struct QoiOpLuma16 {
+    tag_diffg: u8,
+    diffg_dr_dg: u8,
+    dr_dg_db_dg: u8,
+}
+
+impl QoiOpLuma16 {
+    pub fn new(diff_green: i16, dr_dg: i16, db_dg: i16) -> Self {
+        assert!((-512..=511).contains(&diff_green));
+        assert!((-32..=31).contains(&dr_dg));
+        assert!((-32..=31).contains(&db_dg));
+        let diff_green = (diff_green + 512) as u16;
+        let dr_dg = (dr_dg + 32) as u8;
+        let db_dg = (db_dg + 32) as u8;
+        Self {
+            tag_diffg: 0b10000000 | (diff_green >> 4) as u8,
+            diffg_dr_dg: ((diff_green as u8 & 0b00001111) << 4) | (dr_dg >> 2),
+            dr_dg_db_dg: ((dr_dg & 0b00000011) << 6) | db_dg,
+        }
+    }
+    pub fn append_self(&self, bytestream: &mut Vec<u8>) {
+        bytestream.push(self.tag_diffg);
+        bytestream.push(self.diffg_dr_dg);
+        bytestream.push(self.dr_dg_db_dg);
+    }
 }
-//
-// pub struct QoiHeader {
-//     magic_0: u8,
-//     magic_1: u8,
-//     magic_2: u8,
-//     magic_3: u8,
-//     width: u32,
-//     height: u32,
-//     chanels: u8,
-//     colorspace: u8,
-// }
-//
-// impl QoiHeader {
-//     fn new(width: u32, height: u32, chanels: u8, colorspace: u8) -> Self {
-//         Self {
-//             magic_0: 113,
-//             magic_1: 111,
-//             magic_2: 105,
-//             magic_3: 102,
-//             width,
-//             height,
-//             chanels,
-//             colorspace,
-//         }
-//     }
-//     fn as_bytes(&self) -> Vec<u8> {
-//         let output: &mut Vec<u8> = &mut Vec::with_capacity(112);
-//         output.push(self.magic_0);
-//         output.push(self.magic_1);
-//         output.push(self.magic_2);
-//         output.push(self.magic_3);
-//         output.push((self.width >> 24) as u8 & 0b11111111);
-//         output.push((self.width >> 16) as u8 & 0b11111111);
-//         output.push((self.width >> 8) as u8 & 0b11111111);
-//         output.push((self.width) as u8 & 0b11111111);
-//         output.push((self.height >> 24) as u8 & 0b11111111);
-//         output.push((self.height >> 16) as u8 & 0b11111111);
-//         output.push((self.height >> 8) as u8 & 0b11111111);
-//         output.push((self.height) as u8 & 0b11111111);
-//         output.push(self.chanels);
-//         output.push(self.colorspace);
-//         output.to_vec()
-//     }
-// }
-//
-// pub struct QoiOpRGB {
-//     tag: u8,
-//     r: u8,
-//     g: u8,
-//     b: u8,
-// }
-//
-// impl QoiOpRGB {
-//     pub fn new(r: u8, g: u8, b: u8) -> Self {
-//         Self {
-//             tag: 0b11111100,
-//             r,
-//             g,
-//             b,
-//         }
-//     }
-//
-//     pub fn as_bytes(&self) -> Vec<u8> {
-//         let output: &mut Vec<u8> = &mut Vec::with_capacity(4);
-//         output.push(self.tag);
-//         output.push(self.r);
-//         output.push(self.g);
-//         output.push(self.b);
-//         output.to_vec()
-//     }
-// }
-//
-// pub struct QoiOpRGBA {
-//     tag: u8,
-//     r: u8,
-//     g: u8,
-//     b: u8,
-//     a: u8,
-// }
-//
-// impl QoiOpRGBA {
-//     pub fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
-//         Self {
-//             tag: 0b11111111,
-//             r,
-//             g,
-//             b,
-//             a,
-//         }
-//     }
-//     pub fn as_bytes(&self) -> Vec<u8> {
-//         let output: &mut Vec<u8> = &mut Vec::with_capacity(5);
-//         output.push(self.tag);
-//         output.push(self.r);
-//         output.push(self.g);
-//         output.push(self.b);
-//         output.to_vec()
-//     }
-// }
-//
-// pub struct QoiOpIndex {
-//     tag_index: u8,
-// }
-//
-// impl QoiOpIndex {
-//     pub fn new(index: u8) -> Self {
-//         assert!(0 <= index && index <= 64);
-//         Self {
-//             tag_index: index & 0b00111111,
-//         }
-//     }
-//     pub fn as_bytes(&self) -> Vec<u8> {
-//         let output: &mut Vec<u8> = &mut Vec::with_capacity(1);
-//         output.push(self.tag_index);
-//         output.to_vec()
-//     }
-// }
-//
-// pub struct QoiOpDiff {
-//     tad_dr_dg_db: u8,
-// }
-//
-// impl QoiOpDiff {
-//     pub fn new(dr: i8, dg: i8, db: i8) -> Self {
-//         assert!(-2 <= dr && dr <= 1);
-//         assert!(-2 <= db && db <= 1);
-//         assert!(-2 <= dg && dg <= 1);
-//
-//         Self {
-//             tad_dr_dg_db: 0b01000000
-//                 | ((((dr + 2) as u8) << 4) & 0b00110000)
-//                 | ((((dg + 2) as u8) << 2) & 0b00001100)
-//                 | (((db + 2) as u8) & 0b00000011),
-//         }
-//     }
-//
-//     pub fn as_bytes(&self) -> Vec<u8> {
-//         let output: &mut Vec<u8> = &mut Vec::with_capacity(1);
-//         output.push(self.tad_dr_dg_db);
-//         output.to_vec()
-//     }
-// }
-//
-// pub struct QoiOpLuma {
-//     tag_diffg: u8,
-//     dr_dg_db_dg: u8,
-// }
-//
-// impl QoiOpLuma {
-//     pub fn new(diff_green: i8, dr_dg: i8, db_dg: i8) -> Self {
-//         assert!(-32 <= diff_green && diff_green <= 31);
-//         assert!(-8 <= dr_dg && dr_dg <= 7);
-//         assert!(-8 <= db_dg && db_dg <= 7);
-//         Self {
-//             tag_diffg: 0b10000000 | ((diff_green + 32) as u8 & 0b00111111),
-//             dr_dg_db_dg: (((((dr_dg + 8) as u8) << 4) & 0b11110000)
-//                 | ((db_dg + 8) as u8 & 0b00001111)),
-//         }
-//     }
-//     pub fn as_bytes(&self) -> Vec<u8> {
-//         let output: &mut Vec<u8> = &mut Vec::with_capacity(2);
-//         output.push(self.tag_diffg);
-//         output.push(self.dr_dg_db_dg);
-//         output.to_vec()
-//     }
-// }
-//
-// pub struct QoiOpRun {
-//     tag_run: u8,
-// }
-//
-// impl QoiOpRun {
-//     pub fn new(run: u8) -> Self {
-//         assert!(1 <= run && run <= 62);
-//         Self {
-//             tag_run: 0b11000000 | ((run - 1) & 0b00111111),
-//         }
-//     }
-//     pub fn as_bytes(&self) -> Vec<u8> {
-//         let output: &mut Vec<u8> = &mut Vec::with_capacity(1);
-//         output.push(self.tag_run);
-//         output.to_vec()
-//     }
-// }
-// TODO!: implement the Qoi for 16-bit valued images
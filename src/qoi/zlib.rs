@@ -0,0 +1,906 @@
+//! zlib (RFC 1950) and deflate (RFC 1951), both ways, with the CRC-32 of PNG chunks, so PNG
+//! needs nothing outside the crate.
+//!
+//! The decompressor handles stored, fixed and dynamic blocks. The compressor finds matches on
+//! hash chains with one step of lazy matching, and writes each block as whichever of stored,
+//! fixed or dynamic Huffman comes out smallest.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::qoi::error::QoiError;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut crc = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            crc = if crc & 1 != 0 {
+                0xEDB8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            k += 1;
+        }
+        table[n] = crc;
+        n += 1;
+    }
+    table
+}
+
+/// The CRC-32 of `bytes`, as PNG and gzip have it.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    crc32_update(0, bytes)
+}
+
+/// Carries on the CRC-32 `crc` of what came before over `bytes`.
+pub fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in bytes {
+        crc = CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// The Adler-32 checksum that ends a zlib stream.
+pub fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // NOTE: 5552 is the most bytes that can be summed before `b` can overflow
+    for chunk in bytes.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// the order the code length code lengths are sent in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+const END_OF_BLOCK: usize = 256;
+
+// the code lengths of the fixed Huffman blocks
+fn fixed_lengths() -> ([u8; 288], [u8; 30]) {
+    let mut literals = [8u8; 288];
+    literals[144..256].fill(9);
+    literals[256..280].fill(7);
+    (literals, [5u8; 30])
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    // the next byte to go into `buffer`, past the end once the input runs out and zeros are
+    // shifted in instead
+    pos: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            pos: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    #[inline(always)]
+    fn refill(&mut self) {
+        while self.count <= 56 {
+            let byte = self.bytes.get(self.pos).copied().unwrap_or(0);
+            self.buffer |= (byte as u64) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+    }
+
+    #[inline(always)]
+    fn peek(&mut self, bits: u32) -> u32 {
+        if self.count < bits {
+            self.refill();
+        }
+        (self.buffer & ((1u64 << bits) - 1)) as u32
+    }
+
+    #[inline(always)]
+    fn consume(&mut self, bits: u32) {
+        self.buffer >>= bits;
+        self.count -= bits;
+    }
+
+    #[inline(always)]
+    fn bits(&mut self, bits: u32) -> u32 {
+        let value = self.peek(bits);
+        self.consume(bits);
+        value
+    }
+
+    // bytes actually read so far, rounded up to the byte
+    #[inline(always)]
+    fn consumed(&self) -> usize {
+        self.pos - (self.count / 8) as usize
+    }
+
+    // whether more bits were used than the input holds
+    #[inline(always)]
+    fn overrun(&self) -> bool {
+        self.pos > self.bytes.len() && self.consumed() > self.bytes.len()
+    }
+
+    fn align(&mut self) {
+        self.consume(self.count % 8);
+    }
+}
+
+const FAST_BITS: u32 = 10;
+
+// a canonical Huffman code, codes up to `FAST_BITS` long are found in one lookup and the longer
+// ones a bit at a time through `counts` and `symbols`
+struct Huffman {
+    // (length << 9) | symbol, 0 when the code is longer than FAST_BITS
+    fast: [u16; 1 << FAST_BITS],
+    counts: [u16; 16],
+    symbols: [u16; 288],
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, QoiError> {
+        let mut huffman = Huffman {
+            fast: [0; 1 << FAST_BITS],
+            counts: [0; 16],
+            symbols: [0; 288],
+        };
+        for &length in lengths {
+            huffman.counts[length as usize] += 1;
+        }
+        huffman.counts[0] = 0;
+        // NOTE: incomplete codes are fine, a missing code errors out once it is met
+        let mut left: i32 = 1;
+        for length in 1..16 {
+            left = (left << 1) - huffman.counts[length] as i32;
+            if left < 0 {
+                return Err(QoiError::InvalidDeflate("over-subscribed Huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        let mut codes = [0u32; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + huffman.counts[length];
+            codes[length + 1] = (codes[length] + huffman.counts[length] as u32) << 1;
+        }
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length == 0 {
+                continue;
+            }
+            let length = length as usize;
+            huffman.symbols[offsets[length] as usize] = symbol as u16;
+            offsets[length] += 1;
+            let code = codes[length];
+            codes[length] += 1;
+            if length as u32 <= FAST_BITS {
+                let reversed = code.reverse_bits() >> (32 - length);
+                let entry = ((length as u16) << 9) | symbol as u16;
+                for slot in (reversed as usize..1 << FAST_BITS).step_by(1 << length) {
+                    huffman.fast[slot] = entry;
+                }
+            }
+        }
+        Ok(huffman)
+    }
+
+    #[inline(always)]
+    fn decode(&self, reader: &mut BitReader) -> Result<usize, QoiError> {
+        let window = reader.peek(15);
+        let entry = self.fast[(window & ((1 << FAST_BITS) - 1)) as usize];
+        if entry != 0 {
+            reader.consume((entry >> 9) as u32);
+            return Ok((entry & 0x1FF) as usize);
+        }
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= ((window >> (length - 1)) & 1) as i32;
+            let count = self.counts[length as usize] as i32;
+            if code - first < count {
+                reader.consume(length);
+                return Ok(self.symbols[(index + code - first) as usize] as usize);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(QoiError::InvalidDeflate("invalid Huffman code"))
+    }
+}
+
+/// Decompresses a zlib stream.
+pub fn decompress(bytes: &[u8]) -> Result<Vec<u8>, QoiError> {
+    decompress_with_limit(bytes, usize::MAX)
+}
+
+/// Same as [`decompress`], failing as soon as the output goes past `limit` bytes.
+pub fn decompress_with_limit(bytes: &[u8], limit: usize) -> Result<Vec<u8>, QoiError> {
+    let mut output = Vec::with_capacity(limit.min(bytes.len().saturating_mul(4)));
+    decompress_into(bytes, &mut output, limit)?;
+    Ok(output)
+}
+
+// decompresses onto the end of `output`, which the caller may have reserved already
+pub(crate) fn decompress_into(
+    bytes: &[u8],
+    output: &mut Vec<u8>,
+    limit: usize,
+) -> Result<(), QoiError> {
+    let [cmf, flg, ..] = *bytes else {
+        return Err(QoiError::InvalidDeflate("truncated zlib header"));
+    };
+    if cmf & 0x0F != 8 || cmf >> 4 > 7 {
+        return Err(QoiError::InvalidDeflate("not a deflate stream"));
+    }
+    if !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) {
+        return Err(QoiError::InvalidDeflate("bad zlib header check"));
+    }
+    if flg & 0x20 != 0 {
+        return Err(QoiError::InvalidDeflate(
+            "preset dictionaries are not supported",
+        ));
+    }
+    let start = output.len();
+    let consumed = 2 + inflate_into(&bytes[2..], output, limit)?;
+    let Some(checksum) = bytes.get(consumed..consumed + 4) else {
+        return Err(QoiError::InvalidDeflate("missing Adler-32 checksum"));
+    };
+    if adler32(&output[start..]) != u32::from_be_bytes(checksum.try_into().unwrap()) {
+        return Err(QoiError::InvalidDeflate("Adler-32 checksum mismatch"));
+    }
+    Ok(())
+}
+
+/// Decompresses raw deflate data, without the zlib header and checksum.
+pub fn inflate(bytes: &[u8]) -> Result<Vec<u8>, QoiError> {
+    let mut output = Vec::with_capacity(bytes.len().saturating_mul(4));
+    inflate_into(bytes, &mut output, usize::MAX)?;
+    Ok(output)
+}
+
+// inflates onto the end of `output`, returns the number of bytes of `bytes` used
+fn inflate_into(bytes: &[u8], output: &mut Vec<u8>, limit: usize) -> Result<usize, QoiError> {
+    let start = output.len();
+    let limit = limit.saturating_add(start);
+    let mut reader = BitReader::new(bytes);
+    loop {
+        let last = reader.bits(1) == 1;
+        match reader.bits(2) {
+            0 => {
+                reader.align();
+                let len = reader.bits(16);
+                if reader.bits(16) != !len & 0xFFFF {
+                    return Err(QoiError::InvalidDeflate("stored block length mismatch"));
+                }
+                // NOTE: the bytes already in the bit buffer go back to the input
+                let from = reader.consumed();
+                let Some(stored) = bytes.get(from..from + len as usize) else {
+                    return Err(QoiError::InvalidDeflate("truncated stored block"));
+                };
+                if output.len() + stored.len() > limit {
+                    return Err(QoiError::InvalidDeflate("more data than expected"));
+                }
+                output.extend_from_slice(stored);
+                reader = BitReader {
+                    pos: from + stored.len(),
+                    ..BitReader::new(bytes)
+                };
+            }
+            1 => {
+                let (literals, distances) = fixed_lengths();
+                let literals = Huffman::new(&literals)?;
+                let distances = Huffman::new(&distances)?;
+                inflate_block(&mut reader, output, start, limit, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_header(&mut reader)?;
+                inflate_block(&mut reader, output, start, limit, &literals, &distances)?;
+            }
+            _ => return Err(QoiError::InvalidDeflate("invalid block type")),
+        }
+        if reader.overrun() {
+            return Err(QoiError::InvalidDeflate("truncated deflate data"));
+        }
+        if last {
+            return Ok(reader.consumed());
+        }
+    }
+}
+
+fn read_dynamic_header(reader: &mut BitReader) -> Result<(Huffman, Huffman), QoiError> {
+    let literal_count = reader.bits(5) as usize + 257;
+    let distance_count = reader.bits(5) as usize + 1;
+    let code_length_count = reader.bits(4) as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err(QoiError::InvalidDeflate(
+            "too many length or distance codes",
+        ));
+    }
+    let mut code_lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[symbol] = reader.bits(3) as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths)?;
+
+    let total = literal_count + distance_count;
+    let mut lengths = [0u8; 286 + 30];
+    let mut i = 0;
+    while i < total {
+        let symbol = code_lengths.decode(reader)?;
+        let (length, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                if i == 0 {
+                    return Err(QoiError::InvalidDeflate("repeat with no previous length"));
+                }
+                (lengths[i - 1], 3 + reader.bits(2) as usize)
+            }
+            17 => (0, 3 + reader.bits(3) as usize),
+            _ => (0, 11 + reader.bits(7) as usize),
+        };
+        if i + repeat > total {
+            return Err(QoiError::InvalidDeflate("code lengths run past the end"));
+        }
+        lengths[i..i + repeat].fill(length);
+        i += repeat;
+    }
+    if lengths[END_OF_BLOCK] == 0 {
+        return Err(QoiError::InvalidDeflate("no end of block code"));
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..total])?,
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    start: usize,
+    limit: usize,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), QoiError> {
+    loop {
+        // NOTE: past the end of the input the reader only gives zeros, which can decode to
+        //       anything, so this is checked on every symbol
+        if reader.overrun() {
+            return Err(QoiError::InvalidDeflate("truncated deflate data"));
+        }
+        let symbol = literals.decode(reader)?;
+        if symbol < END_OF_BLOCK {
+            if output.len() == limit {
+                return Err(QoiError::InvalidDeflate("more data than expected"));
+            }
+            output.push(symbol as u8);
+            continue;
+        }
+        if symbol == END_OF_BLOCK {
+            return Ok(());
+        }
+        let symbol = symbol - 257;
+        if symbol >= LENGTH_BASE.len() {
+            return Err(QoiError::InvalidDeflate("invalid length symbol"));
+        }
+        let len = LENGTH_BASE[symbol] as usize + reader.bits(LENGTH_EXTRA[symbol] as u32) as usize;
+        let symbol = distances.decode(reader)?;
+        if symbol >= DIST_BASE.len() {
+            return Err(QoiError::InvalidDeflate("invalid distance symbol"));
+        }
+        let distance = DIST_BASE[symbol] as usize + reader.bits(DIST_EXTRA[symbol] as u32) as usize;
+        if distance > output.len() - start {
+            return Err(QoiError::InvalidDeflate("distance too far back"));
+        }
+        if output.len() + len > limit {
+            return Err(QoiError::InvalidDeflate("more data than expected"));
+        }
+        let from = output.len() - distance;
+        if distance >= len {
+            output.extend_from_within(from..from + len);
+        } else {
+            // the copy overlaps what it writes
+            for k in from..from + len {
+                output.push(output[k]);
+            }
+        }
+    }
+}
+
+/// Compresses `bytes` as a zlib stream.
+pub fn compress(bytes: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(bytes.len() / 2 + 64);
+    // deflate with a 32K window and the default level
+    output.extend_from_slice(&[0x78, 0x9C]);
+    deflate_into(bytes, &mut output);
+    output.extend_from_slice(&adler32(bytes).to_be_bytes());
+    output
+}
+
+/// Compresses `bytes` as raw deflate data, without the zlib header and checksum.
+pub fn deflate(bytes: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(bytes.len() / 2 + 64);
+    deflate_into(bytes, &mut output);
+    output
+}
+
+struct BitWriter<'a> {
+    output: &'a mut Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter<'_> {
+    #[inline(always)]
+    fn write(&mut self, value: u32, bits: u32) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += bits;
+        while self.count >= 8 {
+            self.output.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.output.push(self.buffer as u8);
+            self.buffer = 0;
+            self.count = 0;
+        }
+    }
+}
+
+const WINDOW: usize = 1 << 15;
+const HASH_BITS: u32 = 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64;
+// a match this long is taken without looking for a longer one
+const NICE_MATCH: usize = 128;
+// a 3 byte match further back than this costs more than the literals
+const FAR_MATCH: usize = 4096;
+const BLOCK_TOKENS: usize = 1 << 14;
+const NONE: u32 = u32::MAX;
+
+// a literal below 256, or (length << 16) | distance for a match
+type Token = u32;
+
+struct Matcher<'a> {
+    bytes: &'a [u8],
+    head: Vec<u32>,
+    prev: Vec<u32>,
+}
+
+impl Matcher<'_> {
+    #[inline(always)]
+    fn hash(&self, i: usize) -> usize {
+        let bytes = self.bytes;
+        let key = (bytes[i] as u32) << 16 | (bytes[i + 1] as u32) << 8 | bytes[i + 2] as u32;
+        (key.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+    }
+
+    #[inline(always)]
+    fn insert(&mut self, i: usize) {
+        if i + MIN_MATCH <= self.bytes.len() {
+            let hash = self.hash(i);
+            self.prev[i % WINDOW] = self.head[hash];
+            self.head[hash] = i as u32;
+        }
+    }
+
+    // the longest match for position `i` as (length, distance), before `i` is inserted
+    fn find(&self, i: usize) -> (usize, usize) {
+        let bytes = self.bytes;
+        let max = MAX_MATCH.min(bytes.len() - i);
+        let (mut best, mut distance) = (0, 0);
+        if max < MIN_MATCH {
+            return (0, 0);
+        }
+        let mut candidate = self.head[self.hash(i)];
+        let mut chain = MAX_CHAIN;
+        while candidate != NONE && i - candidate as usize <= WINDOW && chain > 0 {
+            let at = candidate as usize;
+            if bytes[at + best.min(max - 1)] == bytes[i + best.min(max - 1)] {
+                let len = bytes[at..at + max]
+                    .iter()
+                    .zip(&bytes[i..i + max])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best {
+                    best = len;
+                    distance = i - at;
+                    if len >= NICE_MATCH || len == max {
+                        break;
+                    }
+                }
+            }
+            let next = self.prev[at % WINDOW];
+            if next == NONE || next >= candidate {
+                break;
+            }
+            candidate = next;
+            chain -= 1;
+        }
+        if best < MIN_MATCH || (best == MIN_MATCH && distance > FAR_MATCH) {
+            return (0, 0);
+        }
+        (best, distance)
+    }
+}
+
+fn deflate_into(bytes: &[u8], output: &mut Vec<u8>) {
+    let mut writer = BitWriter {
+        output,
+        buffer: 0,
+        count: 0,
+    };
+    let mut matcher = Matcher {
+        bytes,
+        head: vec![NONE; 1 << HASH_BITS],
+        prev: vec![NONE; WINDOW],
+    };
+    let mut tokens: Vec<Token> = Vec::with_capacity(BLOCK_TOKENS + 1);
+    let mut block_start = 0;
+    let mut i = 0;
+    // the match found at `i` by the lazy lookahead of the previous position
+    let mut pending = None;
+    while i < bytes.len() {
+        let (len, distance) = pending.take().unwrap_or_else(|| matcher.find(i));
+        matcher.insert(i);
+        if (MIN_MATCH..NICE_MATCH).contains(&len) && i + 1 < bytes.len() {
+            let next = matcher.find(i + 1);
+            if next.0 > len {
+                tokens.push(bytes[i] as Token);
+                pending = Some(next);
+                i += 1;
+                continue;
+            }
+        }
+        if len >= MIN_MATCH {
+            tokens.push(((len as Token) << 16) | distance as Token);
+            for k in i + 1..i + len {
+                matcher.insert(k);
+            }
+            i += len;
+        } else {
+            tokens.push(bytes[i] as Token);
+            i += 1;
+        }
+        if tokens.len() >= BLOCK_TOKENS {
+            write_block(&mut writer, &tokens, &bytes[block_start..i], false);
+            tokens.clear();
+            block_start = i;
+        }
+    }
+    write_block(&mut writer, &tokens, &bytes[block_start..], true);
+    writer.align();
+}
+
+#[inline(always)]
+fn length_code(len: usize) -> usize {
+    LENGTH_BASE.partition_point(|&base| base as usize <= len) - 1
+}
+
+#[inline(always)]
+fn distance_code(distance: usize) -> usize {
+    DIST_BASE.partition_point(|&base| base as usize <= distance) - 1
+}
+
+// the canonical codes for `lengths`, bit-reversed as deflate sends them
+fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+    let mut counts = [0u32; 16];
+    for &length in lengths {
+        counts[length as usize] += 1;
+    }
+    counts[0] = 0;
+    let mut next = [0u32; 16];
+    for length in 1..15 {
+        next[length + 1] = (next[length] + counts[length]) << 1;
+    }
+    lengths
+        .iter()
+        .map(|&length| {
+            if length == 0 {
+                return 0;
+            }
+            let code = next[length as usize];
+            next[length as usize] += 1;
+            (code.reverse_bits() >> (32 - length as u32)) as u16
+        })
+        .collect()
+}
+
+// code lengths of at most `limit` bits for `frequencies`, by package-merge. At least two
+// symbols always get a code, so every code is complete.
+fn huffman_lengths(frequencies: &[u32], limit: u32) -> Vec<u8> {
+    let mut lengths = vec![0u8; frequencies.len()];
+    let mut leaves: Vec<(u64, usize)> = frequencies
+        .iter()
+        .enumerate()
+        .filter(|(_, frequency)| **frequency > 0)
+        .map(|(symbol, &frequency)| (frequency as u64, symbol))
+        .collect();
+    if leaves.len() < 2 {
+        let used = leaves.first().map_or(0, |leaf| leaf.1);
+        lengths[used] = 1;
+        lengths[if used == 0 { 1 } else { 0 }] = 1;
+        return lengths;
+    }
+    leaves.sort_unstable();
+
+    // every item is a weight and either a leaf or a pair of earlier items
+    enum Node {
+        Leaf(usize),
+        Pair(usize, usize),
+    }
+    let mut nodes: Vec<(u64, Node)> = leaves
+        .iter()
+        .map(|&(weight, symbol)| (weight, Node::Leaf(symbol)))
+        .collect();
+    let leaf_ids: Vec<usize> = (0..leaves.len()).collect();
+    let mut list = leaf_ids.clone();
+    for _ in 1..limit {
+        let mut merged = Vec::with_capacity(leaves.len() * 2);
+        let mut packages = list
+            .chunks_exact(2)
+            .map(|pair| (pair[0], pair[1]))
+            .peekable();
+        let mut leaf = leaf_ids.iter().peekable();
+        loop {
+            let package_weight = packages.peek().map(|&(a, b)| nodes[a].0 + nodes[b].0);
+            match (leaf.peek(), package_weight) {
+                (Some(&&id), Some(weight)) if nodes[id].0 <= weight => {
+                    merged.push(id);
+                    leaf.next();
+                }
+                (_, Some(weight)) => {
+                    let (a, b) = packages.next().unwrap();
+                    nodes.push((weight, Node::Pair(a, b)));
+                    merged.push(nodes.len() - 1);
+                }
+                (Some(&&id), None) => {
+                    merged.push(id);
+                    leaf.next();
+                }
+                (None, None) => break,
+            }
+        }
+        list = merged;
+    }
+    let mut stack: Vec<usize> = list[..2 * leaves.len() - 2].to_vec();
+    while let Some(id) = stack.pop() {
+        match nodes[id].1 {
+            Node::Leaf(symbol) => lengths[symbol] += 1,
+            Node::Pair(a, b) => stack.extend_from_slice(&[a, b]),
+        }
+    }
+    lengths
+}
+
+// the code lengths of both trees run-length encoded with the symbols 16, 17 and 18, as
+// (symbol, extra bits)
+fn encode_code_lengths(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut encoded = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let length = lengths[i];
+        let run = lengths[i..].iter().take_while(|&&l| l == length).count();
+        let mut left = run;
+        if length == 0 {
+            while left >= 11 {
+                let n = left.min(138);
+                encoded.push((18, (n - 11) as u8));
+                left -= n;
+            }
+            if left >= 3 {
+                encoded.push((17, (left - 3) as u8));
+                left = 0;
+            }
+        } else {
+            encoded.push((length, 0));
+            left -= 1;
+            while left >= 3 {
+                let n = left.min(6);
+                encoded.push((16, (n - 3) as u8));
+                left -= n;
+            }
+        }
+        encoded.extend(core::iter::repeat_n((length, 0), left));
+        i += run;
+    }
+    encoded
+}
+
+const CODE_LENGTH_EXTRA: [u32; 3] = [2, 3, 7];
+
+// bits taken by the tokens of a block with these code lengths
+fn data_cost(
+    literal_frequencies: &[u32],
+    distance_frequencies: &[u32],
+    literal_lengths: &[u8],
+    distance_lengths: &[u8],
+) -> u64 {
+    let mut bits = 0u64;
+    for (symbol, &frequency) in literal_frequencies.iter().enumerate() {
+        let extra = if symbol > END_OF_BLOCK {
+            LENGTH_EXTRA[symbol - 257] as u64
+        } else {
+            0
+        };
+        bits += frequency as u64 * (literal_lengths[symbol] as u64 + extra);
+    }
+    for (symbol, &frequency) in distance_frequencies.iter().enumerate() {
+        bits += frequency as u64 * (distance_lengths[symbol] as u64 + DIST_EXTRA[symbol] as u64);
+    }
+    bits
+}
+
+fn write_block(writer: &mut BitWriter, tokens: &[Token], raw: &[u8], last: bool) {
+    let mut literal_frequencies = [0u32; 286];
+    let mut distance_frequencies = [0u32; 30];
+    literal_frequencies[END_OF_BLOCK] = 1;
+    for &token in tokens {
+        if token < 256 {
+            literal_frequencies[token as usize] += 1;
+        } else {
+            literal_frequencies[257 + length_code((token >> 16) as usize)] += 1;
+            distance_frequencies[distance_code((token & 0xFFFF) as usize)] += 1;
+        }
+    }
+
+    let literal_lengths = huffman_lengths(&literal_frequencies, 15);
+    let distance_lengths = huffman_lengths(&distance_frequencies, 15);
+    let literal_count = 257.max(literal_lengths.iter().rposition(|&l| l > 0).unwrap() + 1);
+    let distance_count = distance_lengths.iter().rposition(|&l| l > 0).unwrap() + 1;
+    let mut all_lengths = literal_lengths[..literal_count].to_vec();
+    all_lengths.extend_from_slice(&distance_lengths[..distance_count]);
+    let encoded = encode_code_lengths(&all_lengths);
+    let mut code_length_frequencies = [0u32; 19];
+    for &(symbol, _) in &encoded {
+        code_length_frequencies[symbol as usize] += 1;
+    }
+    let code_length_lengths = huffman_lengths(&code_length_frequencies, 7);
+    let code_length_count = 4.max(
+        CODE_LENGTH_ORDER
+            .iter()
+            .rposition(|&symbol| code_length_lengths[symbol] > 0)
+            .unwrap()
+            + 1,
+    );
+
+    let header_cost = 5 + 5 + 4 + 3 * code_length_count as u64;
+    let lengths_cost: u64 = encoded
+        .iter()
+        .map(|&(symbol, _)| {
+            code_length_lengths[symbol as usize] as u64
+                + symbol
+                    .checked_sub(16)
+                    .map_or(0, |i| CODE_LENGTH_EXTRA[i as usize] as u64)
+        })
+        .sum();
+    let dynamic_cost = header_cost
+        + lengths_cost
+        + data_cost(
+            &literal_frequencies,
+            &distance_frequencies,
+            &literal_lengths,
+            &distance_lengths,
+        );
+    let (fixed_literals, fixed_distances) = fixed_lengths();
+    let fixed_cost = data_cost(
+        &literal_frequencies,
+        &distance_frequencies,
+        &fixed_literals,
+        &fixed_distances,
+    );
+    // every stored block is a header, the padding to the byte and both lengths
+    let stored_cost =
+        (raw.len() as u64).div_ceil(65535).max(1) * (3 + 7 + 32) + raw.len() as u64 * 8;
+
+    if stored_cost <= dynamic_cost.min(fixed_cost) {
+        let mut chunks = raw.chunks(65535).peekable();
+        if chunks.peek().is_none() {
+            write_stored(writer, &[], last);
+        }
+        while let Some(chunk) = chunks.next() {
+            write_stored(writer, chunk, last && chunks.peek().is_none());
+        }
+    } else if fixed_cost <= dynamic_cost {
+        writer.write(last as u32, 1);
+        writer.write(1, 2);
+        write_tokens(writer, tokens, &fixed_literals, &fixed_distances);
+    } else {
+        writer.write(last as u32, 1);
+        writer.write(2, 2);
+        writer.write((literal_count - 257) as u32, 5);
+        writer.write((distance_count - 1) as u32, 5);
+        writer.write((code_length_count - 4) as u32, 4);
+        for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+            writer.write(code_length_lengths[symbol] as u32, 3);
+        }
+        let codes = canonical_codes(&code_length_lengths);
+        for &(symbol, extra) in &encoded {
+            let symbol = symbol as usize;
+            writer.write(codes[symbol] as u32, code_length_lengths[symbol] as u32);
+            if symbol >= 16 {
+                writer.write(extra as u32, CODE_LENGTH_EXTRA[symbol - 16]);
+            }
+        }
+        write_tokens(writer, tokens, &literal_lengths, &distance_lengths);
+    }
+}
+
+fn write_stored(writer: &mut BitWriter, raw: &[u8], last: bool) {
+    writer.write(last as u32, 1);
+    writer.write(0, 2);
+    writer.align();
+    let len = raw.len() as u16;
+    writer.output.extend_from_slice(&len.to_le_bytes());
+    writer.output.extend_from_slice(&(!len).to_le_bytes());
+    writer.output.extend_from_slice(raw);
+}
+
+fn write_tokens(
+    writer: &mut BitWriter,
+    tokens: &[Token],
+    literal_lengths: &[u8],
+    distance_lengths: &[u8],
+) {
+    let literal_codes = canonical_codes(literal_lengths);
+    let distance_codes = canonical_codes(distance_lengths);
+    for &token in tokens {
+        if token < 256 {
+            let symbol = token as usize;
+            writer.write(literal_codes[symbol] as u32, literal_lengths[symbol] as u32);
+            continue;
+        }
+        let (len, distance) = ((token >> 16) as usize, (token & 0xFFFF) as usize);
+        let code = length_code(len);
+        let symbol = 257 + code;
+        writer.write(literal_codes[symbol] as u32, literal_lengths[symbol] as u32);
+        writer.write(
+            (len - LENGTH_BASE[code] as usize) as u32,
+            LENGTH_EXTRA[code] as u32,
+        );
+        let code = distance_code(distance);
+        writer.write(distance_codes[code] as u32, distance_lengths[code] as u32);
+        writer.write(
+            (distance - DIST_BASE[code] as usize) as u32,
+            DIST_EXTRA[code] as u32,
+        );
+    }
+    writer.write(
+        literal_codes[END_OF_BLOCK] as u32,
+        literal_lengths[END_OF_BLOCK] as u32,
+    );
+}
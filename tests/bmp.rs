@@ -0,0 +1,336 @@
+// BMP: hand-built files of every bit depth, palettes, bitfields, both row orders and both RLE
+// compressions, and round trips through the writer.
+use qoi::qoi::QoiError;
+use qoi::qoi::bmp::{read, write_bmp};
+use qoi::qoi::encoder::bytestream_to_pixelstream;
+use qoi::qoi::types::{Channels, DynamicPixel, Pixel};
+
+const BI_RGB: u32 = 0;
+const BI_RLE8: u32 = 1;
+const BI_RLE4: u32 = 2;
+const BI_BITFIELDS: u32 = 3;
+
+// a file with a 40 byte info header, `table` holds the masks or the palette and `data` the rows
+fn build(
+    width: i32,
+    height: i32,
+    bpp: u16,
+    compression: u32,
+    table: &[u8],
+    data: &[u8],
+) -> Vec<u8> {
+    let offset = 14 + 40 + table.len() as u32;
+    let mut bytes = b"BM".to_vec();
+    bytes.extend_from_slice(&(offset + data.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&[0; 4]);
+    bytes.extend_from_slice(&offset.to_le_bytes());
+    bytes.extend_from_slice(&40u32.to_le_bytes());
+    bytes.extend_from_slice(&width.to_le_bytes());
+    bytes.extend_from_slice(&height.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&bpp.to_le_bytes());
+    bytes.extend_from_slice(&compression.to_le_bytes());
+    bytes.extend_from_slice(&[0; 20]);
+    bytes.extend_from_slice(table);
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+// the palette as RGBQUADs, blue first
+fn palette(colors: &[[u8; 3]]) -> Vec<u8> {
+    colors.iter().flat_map(|&[r, g, b]| [b, g, r, 0]).collect()
+}
+
+fn words(values: &[u32]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+fn rgb(r: u8, g: u8, b: u8) -> Pixel {
+    Pixel::new(r, g, b, 255)
+}
+
+const COLORS: [[u8; 3]; 4] = [[0, 0, 0], [255, 0, 0], [0, 255, 0], [0, 0, 255]];
+
+fn color(index: usize) -> Pixel {
+    let [r, g, b] = COLORS[index];
+    rgb(r, g, b)
+}
+
+#[test]
+fn palettes() {
+    // 1-bit, 10 wide so a row spills into a second byte, bottom row first
+    let data = [
+        0b1010_0000,
+        0b1100_0000,
+        0,
+        0,
+        0b0101_0101,
+        0b0100_0000,
+        0,
+        0,
+    ];
+    let bmp = read(&build(10, 2, 1, BI_RGB, &palette(&COLORS[..2]), &data)).unwrap();
+    assert_eq!(
+        (bmp.width(), bmp.height(), bmp.bits_per_pixel()),
+        (10, 2, 1)
+    );
+    let bits = |row: u16| (0..10).map(move |x| color(((row >> (15 - x)) & 1) as usize));
+    let expected: Vec<Pixel> = bits(0b0101_0101_0100_0000)
+        .chain(bits(0b1010_0000_1100_0000))
+        .collect();
+    assert_eq!(bmp.pixels(), &expected[..]);
+
+    // 4-bit, 3 wide, the low nibble of the last byte is padding
+    let data = [0x01, 0x20, 0, 0, 0x32, 0x10, 0, 0];
+    let bmp = read(&build(3, 2, 4, BI_RGB, &palette(&COLORS), &data)).unwrap();
+    let expected: Vec<Pixel> = [3, 2, 1, 0, 1, 2].into_iter().map(color).collect();
+    assert_eq!(bmp.pixels(), &expected[..]);
+
+    // 8-bit with a palette shorter than the bit depth allows, and with a core header of
+    // RGBTRIPLEs
+    let data = [3, 2, 0, 0, 1, 0, 0, 0];
+    let bmp = read(&build(2, 2, 8, BI_RGB, &palette(&COLORS), &data)).unwrap();
+    let expected: Vec<Pixel> = [1, 0, 3, 2].into_iter().map(color).collect();
+    assert_eq!(bmp.pixels(), &expected[..]);
+    assert!(!bmp.has_alpha());
+
+    let mut core = b"BM".to_vec();
+    core.extend_from_slice(&(14 + 12 + 12 + 8u32).to_le_bytes());
+    core.extend_from_slice(&[0; 4]);
+    core.extend_from_slice(&(14 + 12 + 12u32).to_le_bytes());
+    core.extend_from_slice(&12u32.to_le_bytes());
+    for value in [2u16, 2, 1, 8] {
+        core.extend_from_slice(&value.to_le_bytes());
+    }
+    core.extend(COLORS.iter().flat_map(|&[r, g, b]| [b, g, r]));
+    core.extend_from_slice(&data);
+    assert_eq!(read(&core).unwrap().pixels(), &expected[..]);
+}
+
+#[test]
+fn row_order_and_padding() {
+    // 3 pixels of 3 bytes padded to 12, the padding is never read
+    let data = [
+        1, 2, 3, 4, 5, 6, 7, 8, 9, 0xAA, 0xBB, 0xCC, //
+        10, 11, 12, 13, 14, 15, 16, 17, 18, 0xDD, 0xEE, 0xFF,
+    ];
+    let first = [rgb(3, 2, 1), rgb(6, 5, 4), rgb(9, 8, 7)];
+    let second = [rgb(12, 11, 10), rgb(15, 14, 13), rgb(18, 17, 16)];
+
+    let bottom_up = read(&build(3, 2, 24, BI_RGB, &[], &data)).unwrap();
+    assert!(!bottom_up.top_down());
+    assert_eq!(bottom_up.pixels(), &[second, first].concat()[..]);
+
+    let top_down = read(&build(3, -2, 24, BI_RGB, &[], &data)).unwrap();
+    assert!(top_down.top_down());
+    assert_eq!(top_down.height(), 2);
+    assert_eq!(top_down.pixels(), &[first, second].concat()[..]);
+}
+
+#[test]
+fn bitfields() {
+    // 32-bit BI_RGB: the fourth byte is padding, not an alpha
+    let bmp = read(&build(1, 1, 32, BI_RGB, &[], &[1, 2, 3, 4])).unwrap();
+    assert_eq!(bmp.pixels(), &[rgb(3, 2, 1)]);
+    assert!(!bmp.has_alpha());
+
+    // 16-bit BI_RGB is 5-5-5, the fields get rescaled to 8 bits
+    let data = [0xFF, 0x7F, 0x10, 0x42];
+    let bmp = read(&build(2, 1, 16, BI_RGB, &[], &data)).unwrap();
+    assert_eq!(bmp.pixels(), &[rgb(255, 255, 255), rgb(132, 132, 132)]);
+
+    // 16-bit 5-6-5
+    let masks = words(&[0xF800, 0x07E0, 0x001F]);
+    let value = (31u16 << 11 | 32 << 5 | 1).to_le_bytes();
+    let bmp = read(&build(
+        1,
+        1,
+        16,
+        BI_BITFIELDS,
+        &masks,
+        &[value[0], value[1], 0, 0],
+    ))
+    .unwrap();
+    assert_eq!(bmp.pixels(), &[rgb(255, 130, 8)]);
+    assert_eq!(bmp.compression(), BI_BITFIELDS);
+
+    // 32-bit with an alpha mask in a V4 header, the bytes in RGBA order
+    let mut header = build(2, 1, 32, BI_BITFIELDS, &[], &[]);
+    header[10] = 14 + 108;
+    header[14] = 108;
+    header.extend(words(&[0xFF, 0xFF00, 0xFF_0000, 0xFF00_0000]));
+    header.extend_from_slice(b"BGRs");
+    header.extend_from_slice(&[0; 48]);
+    header.extend_from_slice(&[10, 20, 30, 40, 50, 60, 70, 0]);
+    let bmp = read(&header).unwrap();
+    assert!(bmp.has_alpha());
+    assert_eq!(
+        bmp.pixels(),
+        &[Pixel::new(10, 20, 30, 40), Pixel::new(50, 60, 70, 0)]
+    );
+}
+
+#[test]
+fn rle8() {
+    // 4 by 3, bottom row first: a run of 3 then a run of 2 cut at the right edge, an absolute
+    // run of 3 padded to 4 bytes, then a delta up to the last column of the top row
+    let data = [
+        3, 1, 2, 2, 0, 0, //
+        0, 3, 3, 2, 1, 0, //
+        0, 2, 0, 1, 1, 3, 0, 1,
+    ];
+    let bmp = read(&build(4, 3, 8, BI_RLE8, &palette(&COLORS), &data)).unwrap();
+    let expected: Vec<Pixel> = [0, 0, 0, 3, 3, 2, 1, 0, 1, 1, 1, 2]
+        .into_iter()
+        .map(color)
+        .collect();
+    assert_eq!(bmp.pixels(), &expected[..]);
+
+    // the data may end without the end of bitmap marker
+    let bmp = read(&build(2, 2, 8, BI_RLE8, &palette(&COLORS), &[2, 3])).unwrap();
+    let expected: Vec<Pixel> = [0, 0, 3, 3].into_iter().map(color).collect();
+    assert_eq!(bmp.pixels(), &expected[..]);
+
+    // an end of bitmap partway through a row leaves the rest of the image at entry 0
+    let data = [2, 1, 0, 0, 1, 2, 0, 1];
+    let bmp = read(&build(2, 3, 8, BI_RLE8, &palette(&COLORS), &data)).unwrap();
+    let expected: Vec<Pixel> = [0, 0, 2, 0, 1, 1].into_iter().map(color).collect();
+    assert_eq!(bmp.pixels(), &expected[..]);
+
+    // deltas and line ends get through rows without a pixel, those are left at entry 0
+    let data = [0, 2, 1, 2, 1, 2, 0, 0, 2, 3, 0, 1];
+    let bmp = read(&build(2, 4, 8, BI_RLE8, &palette(&COLORS), &data)).unwrap();
+    let expected: Vec<Pixel> = [3, 3, 0, 2, 0, 0, 0, 0].into_iter().map(color).collect();
+    assert_eq!(bmp.pixels(), &expected[..]);
+}
+
+#[test]
+fn rle4() {
+    // 5 by 2, bottom row first: 5 alternating indexes, then an absolute run of 3 nibbles in 2
+    // bytes and a run of 2
+    let data = [
+        5, 0x12, 0, 0, //
+        0, 3, 0x31, 0x20, 2, 0x33, 0, 1,
+    ];
+    let bmp = read(&build(5, 2, 4, BI_RLE4, &palette(&COLORS), &data)).unwrap();
+    let expected: Vec<Pixel> = [3, 1, 2, 3, 3, 1, 2, 1, 2, 1]
+        .into_iter()
+        .map(color)
+        .collect();
+    assert_eq!(bmp.pixels(), &expected[..]);
+}
+
+#[test]
+fn written_files_read_back() {
+    let pixels: Vec<Pixel> = (0..15u8)
+        .map(|i| Pixel::new(i * 17, 255 - i, i * 3, i * 16))
+        .collect();
+    for (chanels, bpp) in [(Channels::Rgb, 24), (Channels::Rgba, 32)] {
+        let bytes = write_bmp(&pixels, 3, 5, chanels);
+        let bmp = read(&bytes).unwrap();
+        assert_eq!(
+            (bmp.width(), bmp.height(), bmp.bits_per_pixel()),
+            (3, 5, bpp)
+        );
+        assert_eq!(bmp.has_alpha(), chanels == Channels::Rgba);
+        let expected: Vec<Pixel> = pixels
+            .iter()
+            .map(|pixel| {
+                let (r, g, b, a) = pixel.extract();
+                Pixel::new(r, g, b, if bmp.has_alpha() { a } else { 255 })
+            })
+            .collect();
+        assert_eq!(bmp.to_pixels(), expected);
+    }
+    // rows of 9 bytes padded to 12
+    assert_eq!(write_bmp(&pixels, 3, 5, Channels::Rgb).len(), 54 + 12 * 5);
+}
+
+#[test]
+fn bmp_input_to_pixelstream() {
+    let pixels = [rgb(1, 2, 3), Pixel::new(4, 5, 6, 7)];
+    let (image, width, height, max_col_val) =
+        bytestream_to_pixelstream(&write_bmp(&pixels, 2, 1, Channels::Rgba)).unwrap();
+    assert_eq!((width, height, max_col_val), (2, 1, 255));
+    let image: Vec<Pixel> = image
+        .iter()
+        .map(|p| match p {
+            DynamicPixel::Pixel(p) => *p,
+            DynamicPixel::Pixel16(_) => panic!("BMP read as 16-bit"),
+        })
+        .collect();
+    assert_eq!(image, pixels);
+}
+
+#[test]
+fn malformed_bmp() {
+    let invalid = |bytes: &[u8], reason| assert_eq!(read(bytes), Err(QoiError::InvalidBmp(reason)));
+    let good = build(
+        2,
+        2,
+        8,
+        BI_RGB,
+        &palette(&COLORS),
+        &[3, 2, 0, 0, 1, 0, 0, 0],
+    );
+    assert!(read(&good).is_ok());
+
+    invalid(b"PK\x03\x04", "not a BMP file");
+    invalid(&good[..20], "truncated header");
+    invalid(&good[..good.len() - 1], "truncated pixel data");
+    invalid(&good[..60], "truncated palette");
+
+    let mut bytes = good.clone();
+    bytes[14] = 20;
+    invalid(&bytes, "unknown header size");
+
+    let mut bytes = good.clone();
+    bytes[70] = 9;
+    invalid(&bytes, "palette index out of range");
+
+    let mut bytes = build(1, 1, 24, BI_RGB, &[], &[0; 4]);
+    bytes[10] = 0xFF;
+    invalid(&bytes, "pixel data offset past the end");
+
+    invalid(&build(2, 2, 8, 9, &[], &[]), "unknown compression");
+    invalid(
+        &build(2, 2, 24, BI_RLE8, &[], &[]),
+        "bit depth not allowed for the compression",
+    );
+    invalid(
+        &build(0, 2, 24, BI_RGB, &[], &[]),
+        "width or height out of range",
+    );
+    invalid(
+        &build(2, -2, 8, BI_RLE8, &palette(&COLORS), &[0, 1]),
+        "RLE images can't be top-down",
+    );
+    invalid(
+        &build(
+            1,
+            1,
+            16,
+            BI_BITFIELDS,
+            &words(&[0xF0F0, 0x0F00, 0x000F]),
+            &[0; 4],
+        ),
+        "non-contiguous bitfield mask",
+    );
+    // more than 4096 pixels per byte of RLE data
+    assert!(read(&build(64, 128, 8, BI_RLE8, &palette(&COLORS), &[0, 1])).is_ok());
+    invalid(
+        &build(64, 129, 8, BI_RLE8, &palette(&COLORS), &[0, 1]),
+        "not enough RLE data for the image",
+    );
+    invalid(
+        &build(2, 2, 8, BI_RLE8, &palette(&COLORS), &[0, 2, 1]),
+        "truncated RLE delta",
+    );
+    invalid(
+        &build(2, 2, 8, BI_RLE8, &palette(&COLORS), &[0, 4, 1, 2]),
+        "truncated RLE literal run",
+    );
+}
@@ -0,0 +1,131 @@
+// decode_to_bytes and decode_into: interleaved bytes laid out as the header's chanels or the
+// caller's layout say, the same pixels as decode.
+use qoi::qoi::decoder::{decode, decode_into, decode_to_bytes};
+use qoi::qoi::encoder::encode_with;
+use qoi::qoi::types::{Channels, Pixel, PixelLayout};
+use qoi::qoi::{EncoderOptions, QoiError};
+
+mod common;
+use common::{END_MARKER, header};
+
+fn pixels(width: u32, height: u32) -> Vec<Pixel> {
+    (0..width * height)
+        .map(|i| match (i / 7) % 3 {
+            0 => Pixel::new(i as u8, 40, 200, 255),
+            1 => Pixel::new(10, 20, 30, (i * 9) as u8),
+            _ => Pixel::new(0, 0, 0, 0),
+        })
+        .collect()
+}
+
+fn flatten(pixels: &[Pixel], bytes_per_pixel: usize) -> Vec<u8> {
+    pixels
+        .iter()
+        .flat_map(|pixel| {
+            let (r, g, b, a) = pixel.extract();
+            [r, g, b, a].into_iter().take(bytes_per_pixel)
+        })
+        .collect()
+}
+
+#[test]
+fn rgba_keeps_the_alpha() {
+    let image = pixels(13, 5);
+    let options = EncoderOptions::new().chanels(Channels::Rgba);
+    let encoded = encode_with(&image, 13, 5, &options).unwrap();
+    let decoded = decode(&encoded, &mut [Pixel::default(); 64]).unwrap().0;
+
+    let (bytes, header) = decode_to_bytes(&encoded).unwrap();
+    assert_eq!(
+        (header.width(), header.height(), header.chanels()),
+        (13, 5, 4)
+    );
+    assert_eq!(bytes.len(), 13 * 5 * 4);
+    assert_eq!(bytes, flatten(&decoded, 4));
+    assert_eq!(bytes, flatten(&image, 4));
+}
+
+#[test]
+fn rgb_drops_the_alpha() {
+    // a 3-chanel header over QOI_OP_RGBA chunks, decode keeps the alpha they carry
+    let mut encoded = header(b"qoif", 2, 1, 3, 0);
+    encoded.extend_from_slice(&[0xFF, 1, 2, 3, 4, 0xFF, 5, 6, 7, 8]);
+    encoded.extend_from_slice(&END_MARKER);
+    let decoded = decode(&encoded, &mut [Pixel::default(); 64]).unwrap().0;
+    assert!(decoded == [Pixel::new(1, 2, 3, 4), Pixel::new(5, 6, 7, 8)]);
+
+    let (bytes, header) = decode_to_bytes(&encoded).unwrap();
+    assert_eq!(header.chanels(), 3);
+    assert_eq!(bytes, [1, 2, 3, 5, 6, 7]);
+    assert_eq!(bytes, flatten(&decoded, 3));
+
+    let image = pixels(13, 5);
+    let options = EncoderOptions::new().chanels(Channels::Rgb);
+    let encoded = encode_with(&image, 13, 5, &options).unwrap();
+    let decoded = decode(&encoded, &mut [Pixel::default(); 64]).unwrap().0;
+    let (bytes, _) = decode_to_bytes(&encoded).unwrap();
+    assert_eq!(bytes.len(), 13 * 5 * 3);
+    assert_eq!(bytes, flatten(&decoded, 3));
+}
+
+#[test]
+fn decode_into_layouts_match_decode() {
+    let image = pixels(13, 5);
+    let encoded = encode_with(&image, 13, 5, &EncoderOptions::new()).unwrap();
+    let decoded = decode(&encoded, &mut [Pixel::default(); 64]).unwrap().0;
+    for (layout, bytes_per_pixel) in [(PixelLayout::Rgb8, 3), (PixelLayout::Rgba8, 4)] {
+        let mut buffer = vec![0; 13 * 5 * bytes_per_pixel];
+        let header = decode_into(&encoded, &mut buffer, layout).unwrap();
+        assert_eq!((header.width(), header.height()), (13, 5));
+        assert_eq!(buffer, flatten(&decoded, bytes_per_pixel));
+    }
+}
+
+#[test]
+fn decode_into_leaves_the_rest_of_the_buffer() {
+    let image = pixels(13, 5);
+    let encoded = encode_with(&image, 13, 5, &EncoderOptions::new()).unwrap();
+    let mut buffer = vec![0xAA; 13 * 5 * 3 + 10];
+    decode_into(&encoded, &mut buffer, PixelLayout::Rgb8).unwrap();
+    assert_eq!(buffer[..13 * 5 * 3], flatten(&image, 3)[..]);
+    assert_eq!(buffer[13 * 5 * 3..], [0xAA; 10]);
+}
+
+#[test]
+fn decode_into_checks_the_buffer() {
+    let image = pixels(13, 5);
+    let encoded = encode_with(&image, 13, 5, &EncoderOptions::new()).unwrap();
+    let mut buffer = vec![0xAA; 13 * 5 * 4 - 1];
+    assert_eq!(
+        decode_into(&encoded, &mut buffer, PixelLayout::Rgba8).unwrap_err(),
+        QoiError::BufferTooSmall {
+            needed: 13 * 5 * 4,
+            actual: 13 * 5 * 4 - 1
+        }
+    );
+    // nothing was written before the check failed
+    assert!(buffer.iter().all(|&byte| byte == 0xAA));
+
+    // width * height * 4 doesn't fit in a u64
+    let mut overflowing = header(b"qoif", 0x8080_8080, u32::MAX, 4, 0);
+    overflowing.extend_from_slice(&[0xFE, 1, 2, 3, 0]);
+    assert_eq!(overflowing.len(), 19);
+    for layout in [PixelLayout::Rgb8, PixelLayout::Rgba8] {
+        assert_eq!(
+            decode_into(&overflowing, &mut buffer, layout).unwrap_err(),
+            QoiError::BufferTooSmall {
+                needed: u64::MAX,
+                actual: buffer.len()
+            }
+        );
+    }
+
+    // a buffer large enough for a header no stream this short can back
+    let mut short = header(b"qoif", 100, 100, 4, 0);
+    short.extend_from_slice(&END_MARKER);
+    let mut buffer = vec![0; 100 * 100 * 4];
+    assert_eq!(
+        decode_into(&short, &mut buffer, PixelLayout::Rgba8).unwrap_err(),
+        QoiError::TruncatedChunk { offset: 22 }
+    );
+}
@@ -0,0 +1,32 @@
+// Fixtures shared by the integration tests, not every test file uses all of them.
+#![allow(dead_code)]
+
+use qoi::qoi::EncoderOptions;
+use qoi::qoi::encoder::encode_with;
+use qoi::qoi::types::Pixel;
+
+pub const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+// the 14 header bytes, laid out by hand so malformed ones can be built as well
+pub fn header(magic: &[u8; 4], width: u32, height: u32, chanels: u8, colorspace: u8) -> Vec<u8> {
+    let mut bytes = magic.to_vec();
+    bytes.extend_from_slice(&width.to_be_bytes());
+    bytes.extend_from_slice(&height.to_be_bytes());
+    bytes.push(chanels);
+    bytes.push(colorspace);
+    bytes
+}
+
+// an RGBA image through the public encoder with the default options, long runs so some of them
+// cross the end of a row, and every other chunk kind in between
+pub fn stream(width: u32, height: u32) -> (Vec<u8>, Vec<Pixel>) {
+    let pixels: Vec<Pixel> = (0..width * height)
+        .map(|i| match (i / 90) % 3 {
+            0 => Pixel::new(1, 2, 3, 255),
+            1 => Pixel::new(i as u8, (i / 3) as u8, 77, 255),
+            _ => Pixel::new(200, 100, (i % 5) as u8, 128),
+        })
+        .collect();
+    let encoded = encode_with(&pixels, width, height, &EncoderOptions::new()).unwrap();
+    (encoded, pixels)
+}
@@ -0,0 +1,249 @@
+// Conformance suite for the QOI specification (https://qoiformat.org/qoi-specification.pdf).
+// The byte vectors below are built by hand from the spec, not from our own encoder.
+use qoi::qoi::decoder::{DecodeMode, decode, decode_with_limits};
+use qoi::qoi::encoder::encode_with;
+use qoi::qoi::types::Pixel;
+use qoi::qoi::{DecodeLimits, EncoderOptions, QoiError};
+
+mod common;
+use common::{END_MARKER, header};
+
+fn stream_chanels(width: u32, height: u32, chanels: u8, chunks: &[u8]) -> Vec<u8> {
+    let mut bytes = header(b"qoif", width, height, chanels, 0);
+    bytes.extend_from_slice(chunks);
+    bytes.extend_from_slice(&END_MARKER);
+    bytes
+}
+
+fn stream(width: u32, height: u32, chunks: &[u8]) -> Vec<u8> {
+    stream_chanels(width, height, 3, chunks)
+}
+
+fn px(r: u8, g: u8, b: u8, a: u8) -> Pixel {
+    Pixel::new(r, g, b, a)
+}
+
+// checks the vector in both directions: our encoder must produce it and our decoder must read it
+fn check(pixels: &[Pixel], width: u32, height: u32, chunks: &[u8]) {
+    // the header only advertises RGBA when some pixel isn't opaque
+    let chanels = if pixels.iter().any(|p| p.extract().3 != 255) {
+        4
+    } else {
+        3
+    };
+    let expected = stream_chanels(width, height, chanels, chunks);
+    let encoded = encode_with(pixels, width, height, &EncoderOptions::default()).unwrap();
+    assert_eq!(
+        encoded, expected,
+        "encoder output differs from the spec vector"
+    );
+    let decoded = decode(&expected, &mut [Pixel::default(); 64]).unwrap();
+    assert!(
+        decoded.0 == pixels,
+        "decoder output differs from the spec vector"
+    );
+    assert_eq!(
+        (decoded.1, decoded.2, decoded.3, decoded.4),
+        (width, height, chanels, 0)
+    );
+}
+
+#[test]
+fn op_rgb() {
+    check(&[px(10, 20, 30, 255)], 1, 1, &[0xFE, 10, 20, 30]);
+}
+
+#[test]
+fn op_rgba() {
+    check(&[px(10, 20, 30, 128)], 1, 1, &[0xFF, 10, 20, 30, 128]);
+}
+
+#[test]
+fn op_index() {
+    // hash(10, 20, 30, 255) == 9
+    check(
+        &[
+            px(10, 20, 30, 255),
+            px(200, 100, 50, 255),
+            px(10, 20, 30, 255),
+        ],
+        3,
+        1,
+        &[0xFE, 10, 20, 30, 0xFE, 200, 100, 50, 0x09],
+    );
+}
+
+#[test]
+fn op_diff() {
+    // dr = 1, dg = -1, db = 0 stored with a bias of 2
+    check(
+        &[px(10, 20, 30, 255), px(11, 19, 30, 255)],
+        2,
+        1,
+        &[0xFE, 10, 20, 30, 0b01_11_01_10],
+    );
+}
+
+#[test]
+fn op_diff_wraps_around() {
+    // 0 - 1 wraps to 255 on every channel
+    check(&[px(255, 255, 255, 255)], 1, 1, &[0b01_01_01_01]);
+}
+
+#[test]
+fn op_luma() {
+    // dg = 10, dr - dg = -5, db - dg = -7, stored with biases of 32 and 8
+    check(
+        &[px(10, 20, 30, 255), px(15, 30, 33, 255)],
+        2,
+        1,
+        &[0xFE, 10, 20, 30, 0b10_101010, 0b0011_0001],
+    );
+}
+
+#[test]
+fn op_run() {
+    // the previous pixel starts as (0, 0, 0, 255)
+    check(&[px(0, 0, 0, 255); 3], 3, 1, &[0b11_000010]);
+}
+
+#[test]
+fn op_run_is_split_at_62() {
+    check(&[px(0, 0, 0, 255); 64], 8, 8, &[0b11_111101, 0b11_000001]);
+}
+
+#[test]
+fn index_starts_zeroed() {
+    // a fully transparent black pixel hashes to 0 and is found in the zeroed index
+    check(&[px(0, 0, 0, 0)], 1, 1, &[0x00]);
+}
+
+#[test]
+fn decoder_indexes_run_pixels() {
+    // hash(0, 0, 0, 255) == 53, the run at the start puts the initial pixel into the index
+    let bytes = stream(3, 1, &[0b11_000000, 0xFE, 1, 2, 3, 53]);
+    let decoded = decode(&bytes, &mut [Pixel::default(); 64]).unwrap();
+    assert!(decoded.0 == [px(0, 0, 0, 255), px(1, 2, 3, 255), px(0, 0, 0, 255)]);
+}
+
+#[test]
+fn decoder_ignores_stale_index() {
+    let mut array = [px(1, 2, 3, 4); 64];
+    let bytes = stream(1, 1, &[0x00]);
+    let decoded = decode(&bytes, &mut array).unwrap();
+    assert!(decoded.0 == [px(0, 0, 0, 0)]);
+}
+
+#[test]
+fn end_marker_inside_a_chunk() {
+    // the QoiOpRGB payload and the index chunks after it spell out the end marker, which must not
+    // cut the image short
+    let bytes = stream(6, 1, &[0xFE, 0, 0, 0, 0x00, 0x00, 0x00, 0x00, 0x01]);
+    let decoded = decode(&bytes, &mut [Pixel::default(); 64]).unwrap();
+    let mut expected = vec![px(0, 0, 0, 0); 6];
+    expected[0] = px(0, 0, 0, 255);
+    assert!(decoded.0 == expected);
+}
+
+#[test]
+fn strict_mode_checks_the_end_of_the_stream() {
+    let limits = DecodeLimits::default();
+    let strict = |bytes: &[u8]| {
+        decode_with_limits(
+            bytes,
+            &mut [Pixel::default(); 64],
+            &limits,
+            DecodeMode::Strict,
+        )
+    };
+    let lenient = |bytes: &[u8]| {
+        decode_with_limits(
+            bytes,
+            &mut [Pixel::default(); 64],
+            &limits,
+            DecodeMode::Lenient,
+        )
+    };
+
+    let mut trailing = stream(1, 1, &[0xFE, 1, 2, 3]);
+    trailing.extend_from_slice(&[0xAB; 3]);
+    assert_eq!(
+        strict(&trailing).unwrap_err(),
+        QoiError::TrailingData { offset: 26 }
+    );
+    assert!(lenient(&trailing).unwrap().0 == [px(1, 2, 3, 255)]);
+
+    let mut missing = header(b"qoif", 1, 1, 3, 0);
+    missing.extend_from_slice(&[0xFE, 1, 2, 3]);
+    assert_eq!(strict(&missing).unwrap_err(), QoiError::MissingEndMarker);
+    assert!(lenient(&missing).unwrap().0 == [px(1, 2, 3, 255)]);
+
+    // a run of 3 on a 2 pixel image
+    let spilling = stream(2, 1, &[0b11_000010]);
+    assert_eq!(
+        strict(&spilling).unwrap_err(),
+        QoiError::PixelCountMismatch {
+            expected: 2,
+            actual: 3
+        }
+    );
+    assert!(lenient(&spilling).unwrap().0 == [px(0, 0, 0, 255); 2]);
+}
+
+// deterministic xorshift so the round trips don't need an rng dependency
+fn noise(state: &mut u32) -> u8 {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+    (*state >> 24) as u8
+}
+
+fn round_trip(pixels: &[Pixel], width: u32, height: u32) {
+    let encoded = encode_with(pixels, width, height, &EncoderOptions::default()).unwrap();
+    let decoded = decode(&encoded, &mut [Pixel::default(); 64]).unwrap();
+    assert_eq!((decoded.1, decoded.2), (width, height));
+    assert!(decoded.0 == pixels, "decode(encode(x)) != x");
+}
+
+#[test]
+fn round_trip_rgb() {
+    let (width, height) = (97, 61);
+    let mut state = 0x1234_5678;
+    let mut pixels = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            // mix smooth gradients, flat runs and noise so every op gets exercised
+            let pixel = match (x / 16 + y / 16) % 3 {
+                0 => px(x as u8, y as u8, (x + y) as u8, 255),
+                1 => px(40, 80, 120, 255),
+                _ => px(noise(&mut state), noise(&mut state), noise(&mut state), 255),
+            };
+            pixels.push(pixel);
+        }
+    }
+    round_trip(&pixels, width, height);
+}
+
+#[test]
+fn round_trip_rgba() {
+    let (width, height) = (64, 48);
+    let mut state = 0xDEAD_BEEF;
+    let mut pixels = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = match (x / 8 + y / 8) % 4 {
+                0 => px(x as u8, y as u8, 7, (x * 4) as u8),
+                1 => px(0, 0, 0, 0),
+                2 => px(200, 10, 30, 128),
+                _ => px(
+                    noise(&mut state),
+                    noise(&mut state),
+                    noise(&mut state),
+                    noise(&mut state),
+                ),
+            };
+            pixels.push(pixel);
+        }
+    }
+    round_trip(&pixels, width, height);
+}
@@ -0,0 +1,64 @@
+// Encoder and Decoder are reused across images: nothing may carry over from one image to the next.
+use qoi::qoi::decoder::{Decoder, decode};
+use qoi::qoi::encoder::{Encoder, encode_, encode_16};
+use qoi::qoi::types::{DynamicPixel, Pixel};
+use qoi::qoi::types16::Pixel16;
+
+fn image(seed: u8, len: usize) -> Vec<Pixel> {
+    (0..len)
+        .map(|i| match (i / 11) % 3 {
+            0 => Pixel::new(seed, i as u8, 3, 255),
+            1 => Pixel::new(seed.wrapping_mul(7), 9, (i * 5) as u8, 255),
+            _ => Pixel::new(1, seed, 2, 255),
+        })
+        .collect()
+}
+
+#[test]
+fn encoder_resets_between_images() {
+    let mut encoder = Encoder::new();
+    for (seed, width, height) in [(1, 30, 20), (2, 7, 7), (1, 30, 20), (200, 64, 3)] {
+        let pixels = image(seed, (width * height) as usize);
+        let fresh = encode_(&pixels, &mut [Pixel::default(); 64], width, height).unwrap();
+        assert_eq!(encoder.encode(&pixels, width, height).unwrap(), &fresh[..]);
+    }
+}
+
+#[test]
+fn decoder_resets_between_images() {
+    let mut decoder = Decoder::new();
+    for (seed, width, height) in [(3, 25, 25), (4, 9, 1), (3, 25, 25)] {
+        let pixels = image(seed, (width * height) as usize);
+        let encoded = encode_(&pixels, &mut [Pixel::default(); 64], width, height).unwrap();
+        let (header, decoded) = decoder.decode(&encoded).unwrap();
+        assert_eq!((header.width(), header.height()), (width, height));
+        assert!(decoded == pixels);
+        assert!(decode(&encoded, &mut [Pixel::default(); 64]).unwrap().0 == pixels);
+    }
+}
+
+#[test]
+fn encoder_handles_both_depths() {
+    let mut encoder = Encoder::new();
+    let pixels16: Vec<Pixel16> = (0..60u16)
+        .map(|i| Pixel16::new(i * 1000, 7, 65535 - i, 65535))
+        .collect();
+    let dynamic: Vec<DynamicPixel> = pixels16.iter().map(|p| DynamicPixel::Pixel16(*p)).collect();
+    let fresh = encode_16(&pixels16, &mut [Pixel16::default(); 64], 6, 10, 65535).unwrap();
+    assert_eq!(
+        encoder.encode_dynamic(&dynamic, 6, 10, 65535).unwrap(),
+        &fresh[..]
+    );
+
+    let pixels = image(5, 60);
+    let dynamic: Vec<DynamicPixel> = pixels.iter().map(|p| DynamicPixel::Pixel(*p)).collect();
+    let fresh = encode_(&pixels, &mut [Pixel::default(); 64], 6, 10).unwrap();
+    assert_eq!(
+        encoder.encode_dynamic(&dynamic, 6, 10, 255).unwrap(),
+        &fresh[..]
+    );
+
+    let mut mixed = dynamic;
+    mixed[30] = DynamicPixel::Pixel16(Pixel16::default());
+    assert!(encoder.encode_dynamic(&mixed, 6, 10, 255).is_err());
+}
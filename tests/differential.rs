@@ -0,0 +1,298 @@
+// Differential tests against the reference qoi.h (tests/reference), compiled with the local C
+// compiler the same way utils/gen_ppm.c is. Set CC to pick a compiler other than `cc`.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+use qoi::qoi::EncoderOptions;
+use qoi::qoi::decoder::decode;
+use qoi::qoi::encoder::{bytestream_to_pixelstream, encode_, encode_chanels, encode_with};
+use qoi::qoi::types::{Channels, Pixel};
+
+fn reference() -> &'static Path {
+    static BINARY: OnceLock<PathBuf> = OnceLock::new();
+    BINARY.get_or_init(|| {
+        let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/reference/qoi_ref.c");
+        let binary = Path::new(env!("CARGO_TARGET_TMPDIR")).join("qoi_ref");
+        let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+        let status = Command::new(&cc)
+            .args(["-O2", "-std=c99", "-o"])
+            .arg(&binary)
+            .arg(&source)
+            .status()
+            .unwrap_or_else(|err| panic!("couldn't run the C compiler `{}`: {}", cc, err));
+        assert!(status.success(), "compiling the reference qoi.h failed");
+        binary
+    })
+}
+
+fn scratch(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join(name)
+}
+
+fn reference_encode(name: &str, pixels: &[Pixel], width: u32, height: u32, chanels: u8) -> Vec<u8> {
+    let raw = scratch(&format!("{}.raw", name));
+    let out = scratch(&format!("{}.ref.qoi", name));
+    let mut bytes = Vec::with_capacity(pixels.len() * chanels as usize);
+    for pixel in pixels {
+        let (r, g, b, a) = pixel.extract();
+        bytes.extend_from_slice(&[r, g, b, a][..chanels as usize]);
+    }
+    fs::write(&raw, bytes).unwrap();
+    let status = Command::new(reference())
+        .arg("encode")
+        .arg(&raw)
+        .args([width.to_string(), height.to_string(), chanels.to_string()])
+        .arg(&out)
+        .status()
+        .unwrap();
+    assert!(status.success(), "reference encoder failed on {}", name);
+    fs::read(out).unwrap()
+}
+
+fn reference_decode(name: &str, encoded: &[u8]) -> Vec<Pixel> {
+    let input = scratch(&format!("{}.in.qoi", name));
+    let out = scratch(&format!("{}.rgba", name));
+    fs::write(&input, encoded).unwrap();
+    let status = Command::new(reference())
+        .arg("decode")
+        .arg(&input)
+        .arg(&out)
+        .status()
+        .unwrap();
+    assert!(status.success(), "reference decoder failed on {}", name);
+    fs::read(out)
+        .unwrap()
+        .chunks_exact(4)
+        .map(|p| Pixel::new(p[0], p[1], p[2], p[3]))
+        .collect()
+}
+
+// splits the chunk area of a stream into (offset, op name) pairs
+fn chunks(stream: &[u8]) -> Vec<(usize, &'static str)> {
+    let mut ops = Vec::new();
+    let mut i = 14;
+    while i < stream.len().saturating_sub(8) {
+        let (name, len) = match stream[i] {
+            0xFE => ("QOI_OP_RGB", 4),
+            0xFF => ("QOI_OP_RGBA", 5),
+            b if b >> 6 == 0 => ("QOI_OP_INDEX", 1),
+            b if b >> 6 == 1 => ("QOI_OP_DIFF", 1),
+            b if b >> 6 == 2 => ("QOI_OP_LUMA", 2),
+            _ => ("QOI_OP_RUN", 1),
+        };
+        ops.push((i, name));
+        i += len;
+    }
+    ops
+}
+
+fn chunk_at(stream: &[u8], offset: usize) -> (usize, &'static str) {
+    if offset < 14 {
+        return (0, "header");
+    }
+    chunks(stream)
+        .into_iter()
+        .take_while(|(start, _)| *start <= offset)
+        .last()
+        .unwrap_or((offset, "end marker"))
+}
+
+fn assert_same_stream(name: &str, ours: &[u8], theirs: &[u8]) {
+    if ours == theirs {
+        return;
+    }
+    let offset = ours
+        .iter()
+        .zip(theirs)
+        .position(|(a, b)| a != b)
+        .unwrap_or(ours.len().min(theirs.len()));
+    let (our_start, our_op) = chunk_at(ours, offset);
+    let (their_start, their_op) = chunk_at(theirs, offset);
+    panic!(
+        "{}: streams differ at byte {} (ours: {} at offset {}, qoi.h: {} at offset {}; lengths {} vs {})",
+        name,
+        offset,
+        our_op,
+        our_start,
+        their_op,
+        their_start,
+        ours.len(),
+        theirs.len()
+    );
+}
+
+fn assert_same_pixels(name: &str, ours: &[Pixel], theirs: &[Pixel]) {
+    assert_eq!(ours.len(), theirs.len(), "{}: pixel counts differ", name);
+    if let Some(i) = ours.iter().zip(theirs).position(|(a, b)| a != b) {
+        panic!(
+            "{}: pixel {} differs (ours: {:?}, qoi.h: {:?})",
+            name,
+            i,
+            ours[i].extract(),
+            theirs[i].extract()
+        );
+    }
+}
+
+fn check(name: &str, pixels: &[Pixel], width: u32, height: u32) {
+    let ours = encode_(pixels, &mut [Pixel::default(); 64], width, height).unwrap();
+    let theirs = reference_encode(name, pixels, width, height, 3);
+    assert_same_stream(name, &ours, &theirs);
+
+    let decoded = decode(&theirs, &mut [Pixel::default(); 64]).unwrap();
+    assert_same_pixels(name, &decoded.0, &reference_decode(name, &theirs));
+}
+
+fn check_rgba(name: &str, pixels: &[Pixel], width: u32, height: u32) {
+    let ours = encode_chanels(pixels, &mut [Pixel::default(); 64], width, height, 4).unwrap();
+    let theirs = reference_encode(name, pixels, width, height, 4);
+    assert_same_stream(name, &ours, &theirs);
+
+    let decoded = decode(&theirs, &mut [Pixel::default(); 64]).unwrap();
+    assert_same_pixels(name, &decoded.0, &reference_decode(name, &theirs));
+    assert_same_pixels(name, &decoded.0, pixels);
+}
+
+fn synthetic(width: u32, height: u32, seed: u32) -> Vec<Pixel> {
+    let mut state = seed;
+    let mut noise = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        (state >> 24) as u8
+    };
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = match (x / 13 + y / 7) % 5 {
+                0 => Pixel::new(x as u8, y as u8, (x ^ y) as u8, 255),
+                1 => Pixel::new(0, 0, 0, 255),
+                2 => Pixel::new(noise(), noise(), noise(), 255),
+                3 => Pixel::new(
+                    (x * 3) as u8,
+                    (x * 3 + 5) as u8,
+                    (x * 3).wrapping_sub(7) as u8,
+                    255,
+                ),
+                _ => Pixel::new(120, 60, 30, 255),
+            };
+            pixels.push(pixel);
+        }
+    }
+    pixels
+}
+
+#[test]
+fn synthetic_images_match_reference() {
+    for (width, height, seed) in [(1, 1, 1), (7, 3, 2), (64, 64, 3), (301, 123, 4)] {
+        let name = format!("synthetic_{}x{}", width, height);
+        check(&name, &synthetic(width, height, seed), width, height);
+    }
+}
+
+#[test]
+fn long_runs_match_reference() {
+    let mut pixels = vec![Pixel::new(0, 0, 0, 255); 500];
+    pixels.extend(vec![Pixel::new(9, 9, 9, 255); 124]);
+    check("long_runs", &pixels, 624, 1);
+}
+
+#[test]
+fn corpus_matches_reference() {
+    for entry in fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("test")).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|ext| ext != "ppm") {
+            continue;
+        }
+        let (image, width, height, _) =
+            bytestream_to_pixelstream(&fs::read(&path).unwrap()).unwrap();
+        let pixels: Vec<Pixel> = image.iter().map(|p| p.as_pixel().unwrap()).collect();
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        check(&name, &pixels, width, height);
+    }
+}
+
+#[test]
+fn rgba_images_match_reference() {
+    for (width, height, seed) in [(1, 1, 5), (33, 17, 6), (128, 96, 7)] {
+        let name = format!("rgba_{}x{}", width, height);
+        // fade the alpha in and out along the rows so QOI_OP_RGBA gets exercised
+        let pixels: Vec<Pixel> = synthetic(width, height, seed)
+            .into_iter()
+            .enumerate()
+            .map(|(i, pixel)| {
+                let (r, g, b, _) = pixel.extract();
+                let a = match (i / 29) % 3 {
+                    0 => 255,
+                    1 => 0,
+                    _ => (i * 7) as u8,
+                };
+                Pixel::new(r, g, b, a)
+            })
+            .collect();
+        check_rgba(&name, &pixels, width, height);
+    }
+}
+
+#[test]
+fn rgb_encoding_drops_alpha() {
+    let pixels = vec![Pixel::new(10, 20, 30, 0), Pixel::new(10, 20, 30, 128)];
+    let opaque = vec![Pixel::new(10, 20, 30, 255); 2];
+    let ours = encode_chanels(&pixels, &mut [Pixel::default(); 64], 2, 1, 3).unwrap();
+    assert_same_stream(
+        "drops_alpha",
+        &ours,
+        &reference_encode("drops_alpha", &opaque, 2, 1, 3),
+    );
+}
+
+#[test]
+fn threaded_streams_decode_with_reference() {
+    // the bands of the 301x123 image are longer than the lookback of the index
+    for (width, height, seed) in [(5, 2, 8), (64, 64, 9), (301, 123, 10), (1000, 40, 11)] {
+        let pixels = synthetic(width, height, seed);
+        for threads in [2, 3, 8, 200] {
+            let name = format!("threads_{}_{}x{}", threads, width, height);
+            let options = EncoderOptions::new().threads(threads);
+            let ours = encode_with(&pixels, width, height, &options).unwrap();
+            assert_same_pixels(&name, &reference_decode(&name, &ours), &pixels);
+            let decoded = decode(&ours, &mut [Pixel::default(); 64]).unwrap();
+            assert_same_pixels(&name, &decoded.0, &pixels);
+
+            // dropping the alpha goes through the same masking in every band
+            let rgba: Vec<Pixel> = pixels
+                .iter()
+                .enumerate()
+                .map(|(i, pixel)| {
+                    let (r, g, b, _) = pixel.extract();
+                    Pixel::new(r, g, b, if i % 37 < 20 { 255 } else { (i * 3) as u8 })
+                })
+                .collect();
+            for chanels in [Channels::Rgb, Channels::Rgba] {
+                let name = format!("{}_{}", name, chanels as u8);
+                let ours = encode_with(&rgba, width, height, &options.chanels(chanels)).unwrap();
+                let expected = if chanels == Channels::Rgb {
+                    &pixels
+                } else {
+                    &rgba
+                };
+                assert_same_pixels(&name, &reference_decode(&name, &ours), expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn threaded_empty_images() {
+    for (width, height) in [(0, 2), (0, 1000), (2, 0), (0, 0)] {
+        let single = encode_with(&[], width, height, &EncoderOptions::new()).unwrap();
+        for threads in [2, 8] {
+            let options = EncoderOptions::new().threads(threads);
+            assert_eq!(encode_with(&[], width, height, &options).unwrap(), single);
+        }
+        let decoded = decode(&single, &mut [Pixel::default(); 64]).unwrap();
+        assert!(decoded.0.is_empty());
+    }
+}
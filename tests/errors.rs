@@ -0,0 +1,45 @@
+// Every malformed stream ends in its own QoiError variant, never in a panic.
+use qoi::qoi::QoiError;
+use qoi::qoi::decoder::decode;
+use qoi::qoi::types::Pixel;
+
+mod common;
+use common::{END_MARKER, header};
+
+fn decode_err(bytes: &[u8]) -> QoiError {
+    decode(bytes, &mut [Pixel::default(); 64]).unwrap_err()
+}
+
+#[test]
+fn bad_magic() {
+    let mut bytes = header(b"qoix", 1, 1, 3, 0);
+    bytes.extend_from_slice(&[0xFE, 1, 2, 3]);
+    bytes.extend_from_slice(&END_MARKER);
+    assert_eq!(decode_err(&bytes), QoiError::InvalidMagic(*b"qoix"));
+}
+
+#[test]
+fn truncated_header() {
+    let bytes = header(b"qoif", 1, 1, 3, 0);
+    assert_eq!(decode_err(&bytes[..10]), QoiError::TruncatedHeader(10));
+    assert_eq!(decode_err(&[]), QoiError::TruncatedHeader(0));
+}
+
+#[test]
+fn invalid_channels_and_colorspace() {
+    let mut bytes = header(b"qoif", 1, 1, 5, 0);
+    bytes.extend_from_slice(&END_MARKER);
+    assert_eq!(decode_err(&bytes), QoiError::InvalidChannels(5));
+
+    let mut bytes = header(b"qoif", 1, 1, 4, 2);
+    bytes.extend_from_slice(&END_MARKER);
+    assert_eq!(decode_err(&bytes), QoiError::InvalidColorspace(2));
+}
+
+#[test]
+fn truncated_chunk() {
+    // a QOI_OP_RGB chunk with only 2 of its 3 color bytes
+    let mut bytes = header(b"qoif", 1, 1, 3, 0);
+    bytes.extend_from_slice(&[0xFE, 1, 2]);
+    assert_eq!(decode_err(&bytes), QoiError::TruncatedChunk { offset: 14 });
+}
@@ -0,0 +1,119 @@
+// QoiHeader::parse, probe and probe_file: the fields as encoded, every malformed header rejected
+// with its own error, and nothing read past the 14 header bytes.
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use qoi::qoi::encoder::encode_with;
+use qoi::qoi::types::{Channels, ColorSpace, Pixel, QoiHeader};
+use qoi::qoi::{EncoderOptions, QoiError, probe, probe_file};
+
+mod common;
+use common::header;
+
+#[test]
+fn getters_return_the_encoded_fields() {
+    let pixels = vec![Pixel::new(9, 8, 7, 255); 300 * 2];
+    let options = EncoderOptions::new()
+        .chanels(Channels::Rgba)
+        .colorspace(ColorSpace::Linear);
+    let encoded = encode_with(&pixels, 300, 2, &options).unwrap();
+
+    let parsed = QoiHeader::parse(&encoded).unwrap();
+    assert_eq!(
+        (
+            parsed.width(),
+            parsed.height(),
+            parsed.chanels(),
+            parsed.colorspace()
+        ),
+        (300, 2, 4, 1)
+    );
+    assert_eq!(parsed.pixel_count(), 600);
+    assert_eq!(probe(Cursor::new(&encoded)).unwrap(), parsed);
+
+    let parsed = QoiHeader::parse(&header(b"qoif", 70_000, 1, 3, 0)).unwrap();
+    assert_eq!(
+        (
+            parsed.width(),
+            parsed.height(),
+            parsed.chanels(),
+            parsed.colorspace()
+        ),
+        (70_000, 1, 3, 0)
+    );
+}
+
+#[test]
+fn malformed_headers() {
+    let parse = |bytes: &[u8]| QoiHeader::parse(bytes).unwrap_err();
+    assert_eq!(
+        parse(&header(b"qoif", 1, 1, 3, 0)[..13]),
+        QoiError::TruncatedHeader(13)
+    );
+    assert_eq!(
+        parse(&header(b"QOIF", 1, 1, 3, 0)),
+        QoiError::InvalidMagic(*b"QOIF")
+    );
+    assert_eq!(
+        parse(&header(b"qoif", 1, 1, 2, 0)),
+        QoiError::InvalidChannels(2)
+    );
+    assert_eq!(
+        parse(&header(b"qoif", 1, 1, 4, 7)),
+        QoiError::InvalidColorspace(7)
+    );
+}
+
+// hands out a few bytes per call, like a socket
+struct Trickle<R>(R);
+
+impl<R: Read> Read for Trickle<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(3);
+        self.0.read(&mut buf[..len])
+    }
+}
+
+#[test]
+fn probe_short_readers() {
+    let bytes = header(b"qoif", 5, 6, 3, 0);
+    assert_eq!(
+        probe(Trickle(Cursor::new(&bytes[..9]))).unwrap_err(),
+        QoiError::TruncatedHeader(9)
+    );
+    assert_eq!(
+        probe(Cursor::new(&[][..])).unwrap_err(),
+        QoiError::TruncatedHeader(0)
+    );
+    let parsed = probe(Trickle(Cursor::new(&bytes))).unwrap();
+    assert_eq!((parsed.width(), parsed.height()), (5, 6));
+}
+
+#[test]
+fn probe_reads_only_the_header() {
+    // the header is followed by bytes no decoder would take
+    let mut bytes = header(b"qoif", 640, 480, 4, 1);
+    bytes.extend_from_slice(&[0xFF; 4096]);
+
+    let mut reader = Cursor::new(&bytes);
+    probe(&mut reader).unwrap();
+    assert_eq!(reader.position(), 14);
+
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("probe_file.qoi");
+    fs::write(&path, &bytes).unwrap();
+    let parsed = probe_file(&path).unwrap();
+    assert_eq!(
+        (
+            parsed.width(),
+            parsed.height(),
+            parsed.chanels(),
+            parsed.colorspace()
+        ),
+        (640, 480, 4, 1)
+    );
+    assert!(matches!(
+        probe_file(path.with_extension("missing")),
+        Err(QoiError::Io(_))
+    ));
+}
@@ -0,0 +1,335 @@
+// Randomized hostile inputs: whatever the bytes, decoding must end in Ok or Err, never in a panic,
+// an out-of-bounds index or an allocation sized from an unchecked header.
+use std::io::Cursor;
+
+use qoi::qoi::bmp::{self, write_bmp};
+use qoi::qoi::decoder::{
+    QoiDecoder, decode, decode_16, decode_16_with_limits, decode_into, decode_to_bytes,
+    decode_with_limits,
+};
+use qoi::qoi::encoder::{
+    bytestream_to_pixelstream, bytestream_to_pixelstream_with_limits, encode_with,
+};
+use qoi::qoi::png::{self, write_png};
+use qoi::qoi::types::{Channels, Pixel, PixelLayout};
+use qoi::qoi::types16::Pixel16;
+use qoi::qoi::{
+    DecodeLimits, DecodeMode, EncoderOptions, QoiError, QoiImage, QoiPixels, QoiRows, TiledImage,
+    decode_region, encode_tiled, zlib,
+};
+
+mod common;
+use common::header;
+
+struct XorShift(u32);
+
+impl XorShift {
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.next() as usize % n.max(1)
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| (self.next() >> 24) as u8).collect()
+    }
+}
+
+fn valid_stream(rng: &mut XorShift) -> Vec<u8> {
+    let (width, height) = (1 + rng.below(40) as u32, 1 + rng.below(40) as u32);
+    let pixels: Vec<Pixel> = (0..width * height)
+        .map(|i| match (i / 9) % 3 {
+            0 => Pixel::new(i as u8, 3, 200, 255),
+            1 => Pixel::new(7, 7, 7, 255),
+            _ => {
+                let [r, g, b, a] = rng.next().to_be_bytes();
+                Pixel::new(r, g, b, a)
+            }
+        })
+        .collect();
+    encode_with(&pixels, width, height, &EncoderOptions::new()).unwrap()
+}
+
+fn mutate(rng: &mut XorShift, mut bytes: Vec<u8>) -> Vec<u8> {
+    match rng.below(4) {
+        0 => bytes.truncate(rng.below(bytes.len())),
+        1 => {
+            for _ in 0..1 + rng.below(8) {
+                let i = rng.below(bytes.len());
+                bytes[i] = (rng.next() >> 24) as u8;
+            }
+        }
+        2 => {
+            // rewrite the dimensions, mostly to something far too large
+            let i = 4 + rng.below(8);
+            bytes[i] = (rng.next() >> 24) as u8;
+        }
+        _ => {
+            let i = rng.below(bytes.len());
+            let len = rng.below(64);
+            let extra = rng.bytes(len);
+            bytes.splice(i..i, extra);
+        }
+    }
+    bytes
+}
+
+// every entry point that takes encoded bytes, the results are irrelevant as long as nothing panics
+fn decode_everything(bytes: &[u8]) {
+    let _ = decode(bytes, &mut [Pixel::default(); 64]);
+    let _ = decode_16(bytes, &mut [Pixel16::default(); 64]);
+    let _ = decode_to_bytes(bytes);
+    let _ = decode_into(bytes, &mut [0; 4096], PixelLayout::Rgba8);
+    let _ = QoiImage::decode(bytes);
+    let _ = bytestream_to_pixelstream(bytes);
+    let _ = png::read(bytes);
+    let _ = bmp::read(bytes);
+    let _ = zlib::decompress(bytes);
+    let _ = zlib::inflate(bytes);
+    let _ = decode_region(bytes, 1, 2, 3, 4);
+    if let Ok(pixels) = QoiPixels::new(bytes) {
+        pixels.take_while(Result::is_ok).for_each(drop);
+    }
+    if let Ok(rows) = QoiRows::new(bytes) {
+        rows.take_while(Result::is_ok).for_each(drop);
+    }
+    if let Ok(tiled) = TiledImage::parse(bytes) {
+        let _ = tiled.decode_tile(0, 0);
+        let _ = tiled.decode_region(0, 0, tiled.width(), tiled.height());
+    }
+    if let Ok(decoder) = QoiDecoder::new(Cursor::new(bytes)) {
+        for pixel in decoder {
+            if pixel.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[test]
+fn random_bytes_never_panic() {
+    let mut rng = XorShift(0x1234_5678);
+    for _ in 0..2000 {
+        // a good share of the inputs gets past the magic check
+        let prefix: &[u8] = [
+            &b"qoif"[..],
+            b"qo16",
+            b"qoit",
+            b"P1\n",
+            b"P2\n",
+            b"P3\n",
+            b"P4\n",
+            b"P5\n",
+            b"P6\n",
+            b"P7\nWIDTH 3\nHEIGHT 2\nDEPTH 4\nMAXVAL 255\n",
+            &png::SIGNATURE,
+            &bmp::MAGIC,
+            b"\x78\x9c",
+            b"",
+        ][rng.below(14)];
+        let len = rng.below(300);
+        let mut bytes = prefix.to_vec();
+        bytes.extend(rng.bytes(len));
+        decode_everything(&bytes);
+    }
+}
+
+#[test]
+fn mutated_streams_never_panic() {
+    let mut rng = XorShift(0xDEAD_BEEF);
+    for _ in 0..1000 {
+        let stream = valid_stream(&mut rng);
+        let mutated = mutate(&mut rng, stream);
+        decode_everything(&mutated);
+    }
+}
+
+#[test]
+fn mutated_tiled_files_never_panic() {
+    let mut rng = XorShift(0x0BAD_F00D);
+    for _ in 0..500 {
+        let stream = valid_stream(&mut rng);
+        let (pixels, width, height, ..) = decode(&stream, &mut [Pixel::default(); 64]).unwrap();
+        let tile_size = 1 + rng.below(16) as u32;
+        let tiled = encode_tiled(&pixels, width, height, tile_size, &EncoderOptions::new());
+        let mutated = mutate(&mut rng, tiled.unwrap());
+        decode_everything(&mutated);
+    }
+}
+
+// the CRC of every chunk recomputed, so a mutated PNG gets past the CRC check and into the rest
+// of the reader
+fn fix_crcs(bytes: &mut [u8]) {
+    let mut i = png::SIGNATURE.len();
+    while i + 12 <= bytes.len() {
+        let len = u32::from_be_bytes(bytes[i..i + 4].try_into().unwrap()) as usize;
+        let Some(end) = (i + 8)
+            .checked_add(len)
+            .filter(|end| end + 4 <= bytes.len())
+        else {
+            break;
+        };
+        let crc = zlib::crc32(&bytes[i + 4..end]);
+        bytes[end..end + 4].copy_from_slice(&crc.to_be_bytes());
+        i = end + 4;
+    }
+}
+
+#[test]
+fn mutated_png_files_never_panic() {
+    let mut rng = XorShift(0x5EED_1234);
+    for _ in 0..500 {
+        let stream = valid_stream(&mut rng);
+        let (pixels, width, height, ..) = decode(&stream, &mut [Pixel::default(); 64]).unwrap();
+        let chanels = [Channels::Rgb, Channels::Rgba][rng.below(2)];
+        let mut mutated = mutate(&mut rng, write_png(&pixels, width, height, chanels));
+        if rng.below(4) != 0 {
+            fix_crcs(&mut mutated);
+        }
+        decode_everything(&mutated);
+
+        let raw: Vec<u8> = pixels
+            .iter()
+            .flat_map(|pixel| {
+                let (r, g, b, a) = pixel.extract();
+                [r, g, b, a]
+            })
+            .collect();
+        let mutated = mutate(&mut rng, zlib::compress(&raw));
+        decode_everything(&mutated);
+    }
+}
+
+#[test]
+fn mutated_bmp_files_never_panic() {
+    let mut rng = XorShift(0xB17_3A9);
+    for _ in 0..500 {
+        let stream = valid_stream(&mut rng);
+        let (pixels, width, height, ..) = decode(&stream, &mut [Pixel::default(); 64]).unwrap();
+        let chanels = [Channels::Rgb, Channels::Rgba][rng.below(2)];
+        let mut mutated = mutate(&mut rng, write_bmp(&pixels, width, height, chanels));
+        // a share of the files claims one of the RLE compressions over the same data
+        if rng.below(3) == 0 && mutated.len() >= 34 {
+            mutated[28..30].copy_from_slice(&[8, 0]);
+            mutated[30..34].copy_from_slice(&[1 + rng.below(2) as u8, 0, 0, 0]);
+        }
+        decode_everything(&mutated);
+    }
+}
+
+#[test]
+fn huge_headers_are_rejected_before_allocating() {
+    let mut bytes = header(b"qoif", u32::MAX, u32::MAX, 4, 0);
+    bytes.extend_from_slice(&[0xFD; 16]);
+    bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+    assert!(decode(&bytes, &mut [Pixel::default(); 64]).is_err());
+    assert!(decode_to_bytes(&bytes).is_err());
+    assert!(QoiImage::decode(&bytes).is_err());
+
+    bytes[..4].copy_from_slice(b"qo16");
+    assert!(decode_16(&bytes, &mut [Pixel16::default(); 64]).is_err());
+
+    let ppm = b"P6\n4294967295 4294967295\n255\n".to_vec();
+    assert!(bytestream_to_pixelstream(&ppm).is_err());
+
+    // 15000 by 15000 is within the limits, but a few bytes of IDAT can't hold it
+    let mut png = png::SIGNATURE.to_vec();
+    let mut header = 15_000u32.to_be_bytes().repeat(2);
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    for (kind, data) in [
+        (b"IHDR", header),
+        (b"IDAT", zlib::compress(&[0; 3])),
+        (b"IEND", vec![]),
+    ] {
+        png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        let start = png.len();
+        png.extend_from_slice(kind);
+        png.extend_from_slice(&data);
+        let crc = zlib::crc32(&png[start..]);
+        png.extend_from_slice(&crc.to_be_bytes());
+    }
+    assert_eq!(
+        png::read(&png),
+        Err(QoiError::InvalidPng("not enough image data"))
+    );
+
+    // the same for a BMP with no pixel data after its header
+    let mut bmp = write_bmp(&[Pixel::default()], 1, 1, Channels::Rgb);
+    bmp.truncate(54);
+    bmp[18..26].copy_from_slice(&[15_000u32.to_le_bytes(), 15_000u32.to_le_bytes()].concat());
+    assert_eq!(
+        bmp::read(&bmp),
+        Err(QoiError::InvalidBmp("truncated pixel data"))
+    );
+
+    // and as RLE8 or RLE4, where a few hundred bytes of deltas or line ends would skip through
+    // every row
+    for (bits_per_pixel, compression) in [(8u16, 1u32), (4, 2)] {
+        for data in [[0, 2, 255, 255].repeat(60), [0, 0].repeat(15_000)] {
+            let mut rle = bmp.clone();
+            rle[28..30].copy_from_slice(&bits_per_pixel.to_le_bytes());
+            rle[30..34].copy_from_slice(&compression.to_le_bytes());
+            rle.extend(data);
+            assert_eq!(
+                bmp::read(&rle),
+                Err(QoiError::InvalidBmp("not enough RLE data for the image"))
+            );
+        }
+    }
+}
+
+#[test]
+fn limits_are_enforced() {
+    let limits = DecodeLimits::default().max_width(64).max_pixels(1000);
+    let exceeded = |result: Result<(), QoiError>| {
+        assert!(
+            matches!(result, Err(QoiError::LimitExceeded { .. })),
+            "{:?}",
+            result
+        )
+    };
+
+    let mut stream = header(b"qoif", 65, 1, 4, 0);
+    stream.extend_from_slice(&[0xFD, 0xC1, 0, 0, 0, 0, 0, 0, 0, 1]);
+    exceeded(
+        decode_with_limits(
+            &stream,
+            &mut [Pixel::default(); 64],
+            &limits,
+            DecodeMode::Strict,
+        )
+        .map(drop),
+    );
+    exceeded(QoiImage::decode_with_limits(&stream, &limits).map(drop));
+    exceeded(QoiDecoder::with_limits(Cursor::new(&stream), &limits).map(drop));
+
+    let mut stream = header(b"qo16", 50, 50, 4, 0);
+    stream.extend_from_slice(&[0xFD; 41]);
+    stream.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+    exceeded(
+        decode_16_with_limits(
+            &stream,
+            &mut [Pixel16::default(); 64],
+            &limits,
+            DecodeMode::Lenient,
+        )
+        .map(drop),
+    );
+
+    let mut ppm = b"P6\n10 200\n255\n".to_vec();
+    ppm.extend_from_slice(&[0; 6000]);
+    exceeded(bytestream_to_pixelstream_with_limits(&ppm, &limits).map(drop));
+
+    let limits = DecodeLimits::default().max_alloc(100);
+    exceeded(bytestream_to_pixelstream_with_limits(&ppm, &limits).map(drop));
+
+    // within the limits the same images decode fine
+    let (pixels, ..) =
+        bytestream_to_pixelstream_with_limits(&ppm, &DecodeLimits::unlimited()).unwrap();
+    assert_eq!(pixels.len(), 2000);
+}
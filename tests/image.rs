@@ -0,0 +1,73 @@
+// QoiImage: pixel access in bounds and out, rows, and the metadata carried into the header.
+use qoi::qoi::types::{Channels, ColorSpace, Pixel, QoiHeader};
+use qoi::qoi::{QoiError, QoiImage};
+
+fn gradient(width: u32, height: u32) -> Vec<Pixel> {
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| Pixel::new(x as u8, y as u8, 9, 255)))
+        .collect()
+}
+
+#[test]
+fn from_pixels_checks_the_count() {
+    assert_eq!(
+        QoiImage::from_pixels(gradient(4, 3), 4, 4, Channels::Rgb, ColorSpace::Srgb).unwrap_err(),
+        QoiError::PixelCountMismatch {
+            expected: 16,
+            actual: 12
+        }
+    );
+    let image =
+        QoiImage::from_pixels(gradient(4, 3), 4, 3, Channels::Rgb, ColorSpace::Srgb).unwrap();
+    assert!(image.pixels() == &gradient(4, 3)[..]);
+}
+
+#[test]
+fn pixel_access() {
+    let mut image = QoiImage::new(4, 3, Channels::Rgba, ColorSpace::Srgb);
+    assert_eq!(image.get_pixel(3, 2), Some(Pixel::default()));
+    assert_eq!(image.get_pixel(4, 0), None);
+    assert_eq!(image.get_pixel(0, 3), None);
+    assert_eq!(image.get_pixel(u32::MAX, u32::MAX), None);
+
+    image.put_pixel(1, 2, Pixel::new(10, 20, 30, 40));
+    assert_eq!(image.get_pixel(1, 2), Some(Pixel::new(10, 20, 30, 40)));
+    assert_eq!(image.pixels()[2 * 4 + 1], Pixel::new(10, 20, 30, 40));
+    assert_eq!(image.get_pixel(2, 1), Some(Pixel::default()));
+}
+
+#[test]
+fn rows() {
+    let pixels = gradient(4, 3);
+    let mut image =
+        QoiImage::from_pixels(pixels.clone(), 4, 3, Channels::Rgb, ColorSpace::Srgb).unwrap();
+    assert!(image.row(1).unwrap() == &pixels[4..8]);
+    assert!(image.row(3).is_none());
+    let rows: Vec<&[Pixel]> = image.rows().collect();
+    assert_eq!(rows.len(), 3);
+    assert!(rows.concat() == pixels);
+
+    image.row_mut(2).unwrap()[0] = Pixel::new(1, 1, 1, 1);
+    assert_eq!(image.get_pixel(0, 2), Some(Pixel::new(1, 1, 1, 1)));
+
+    let empty = QoiImage::new(0, 5, Channels::Rgb, ColorSpace::Srgb);
+    assert_eq!(empty.rows().count(), 0);
+}
+
+#[test]
+fn encode_writes_the_metadata() {
+    let pixels = gradient(6, 2);
+    for chanels in [Channels::Rgb, Channels::Rgba] {
+        for colorspace in [ColorSpace::Srgb, ColorSpace::Linear] {
+            let image = QoiImage::from_pixels(pixels.clone(), 6, 2, chanels, colorspace).unwrap();
+            let encoded = image.encode().unwrap();
+            let header = QoiHeader::parse(&encoded).unwrap();
+            assert_eq!(header, image.header());
+            assert_eq!(
+                (header.chanels(), header.colorspace()),
+                (chanels as u8, colorspace as u8)
+            );
+            assert_eq!(QoiImage::decode(&encoded).unwrap(), image);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+// QoiPixels and QoiRows must produce exactly what decode does, and stop at the first error.
+use qoi::qoi::decoder::decode;
+use qoi::qoi::types::Pixel;
+use qoi::qoi::{DecodeMode, QoiError, QoiPixels, QoiRows};
+
+mod common;
+use common::{END_MARKER, header, stream};
+
+#[test]
+fn pixels_match_decode() {
+    let (encoded, pixels) = stream(37, 23);
+    let iterated: Vec<Pixel> = QoiPixels::new(&encoded)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert!(iterated == pixels);
+    assert!(decode(&encoded, &mut [Pixel::default(); 64]).unwrap().0 == iterated);
+
+    // the bounding box of the half transparent pixels, without keeping the image around
+    let (mut left, mut right) = (u32::MAX, 0);
+    for (i, pixel) in QoiPixels::new(&encoded).unwrap().enumerate() {
+        if pixel.unwrap().extract().3 != 255 {
+            left = left.min(i as u32 % 37);
+            right = right.max(i as u32 % 37);
+        }
+    }
+    assert_eq!((left, right), (0, 36));
+}
+
+#[test]
+fn rows_match_decode() {
+    let (encoded, pixels) = stream(37, 23);
+    let mut rows = QoiRows::new(&encoded).unwrap();
+    let mut y = 0;
+    let mut buffer = None;
+    while let Some(row) = rows.next_row() {
+        let row = row.unwrap();
+        assert!(row == &pixels[y * 37..(y + 1) * 37]);
+        // every row is decoded into the same buffer
+        assert_eq!(*buffer.get_or_insert(row.as_ptr()), row.as_ptr());
+        y += 1;
+    }
+    assert_eq!(y, 23);
+    assert!(rows.next_row().is_none());
+
+    let collected: Vec<Vec<Pixel>> = QoiRows::new(&encoded)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert!(collected.concat() == pixels);
+}
+
+#[test]
+fn errors_end_the_iteration() {
+    let (encoded, _) = stream(37, 23);
+    let truncated = &encoded[..encoded.len() - 40];
+    let items: Vec<_> = QoiPixels::new(truncated).unwrap().collect();
+    assert!(matches!(
+        items.last(),
+        Some(Err(QoiError::TruncatedChunk { .. }))
+    ));
+    assert_eq!(items.iter().filter(|item| item.is_err()).count(), 1);
+
+    let mut rows = QoiRows::new(truncated).unwrap();
+    let error = loop {
+        match rows.next_row() {
+            Some(Ok(_)) => continue,
+            Some(Err(err)) => break err,
+            None => panic!("a truncated stream decoded to the end"),
+        }
+    };
+    assert!(matches!(error, QoiError::TruncatedChunk { .. }));
+    assert!(rows.next_row().is_none());
+
+    let mut trailing = encoded.clone();
+    trailing.push(0);
+    let last = QoiRows::new(&trailing).unwrap().last().unwrap();
+    assert!(matches!(last, Err(QoiError::TrailingData { .. })));
+    let lenient = QoiRows::new(&trailing)
+        .unwrap()
+        .with_mode(DecodeMode::Lenient);
+    assert!(lenient.into_iter().all(|row| row.is_ok()));
+}
+
+#[test]
+fn empty_images_check_the_end_marker() {
+    for (width, height) in [(0, 5), (5, 0)] {
+        let header = header(b"qoif", width, height, 4, 0);
+        let good = [&header[..], &END_MARKER].concat();
+        let bad = [&header[..], &[0, 0, 0, 0, 0, 0, 0, 2]].concat();
+        let expected = decode(&bad, &mut [Pixel::default(); 64]).unwrap_err();
+
+        assert_eq!(QoiPixels::new(&good).unwrap().count(), 0);
+        let items: Vec<_> = QoiPixels::new(&bad).unwrap().collect();
+        assert_eq!(items, [Err(expected.clone())]);
+
+        let mut rows = QoiRows::new(&good).unwrap();
+        assert!(rows.next_row().is_none());
+        let mut rows = QoiRows::new(&bad).unwrap();
+        assert_eq!(rows.next_row(), Some(Err(expected.clone())));
+        assert!(rows.next_row().is_none());
+        let lenient = QoiRows::new(&bad).unwrap().with_mode(DecodeMode::Lenient);
+        assert_eq!(lenient.count(), 0);
+    }
+}
@@ -0,0 +1,261 @@
+// Netpbm input: every variant of the same image must give the same pixels.
+use qoi::qoi::{DecodeLimits, QoiError};
+use qoi::qoi::decoder::decode;
+use qoi::qoi::encoder::{Encoder, bytestream_to_pixelstream};
+use qoi::qoi::netpbm::{read, read_with_limits, write_pam, write_pam_16};
+use qoi::qoi::types::{Channels, Pixel};
+use qoi::qoi::types16::Pixel16;
+
+const WIDTH: usize = 11;
+const HEIGHT: usize = 3;
+
+fn gray(x: usize, y: usize) -> u8 {
+    ((x * 23 + y * 41) % 256) as u8
+}
+
+fn rgb(x: usize, y: usize) -> [u8; 3] {
+    [gray(x, y), (x * 20) as u8, 255 - (y * 60) as u8]
+}
+
+fn ascii(magic: &str, maxval: Option<u32>, samples: impl Iterator<Item = u32>) -> Vec<u8> {
+    // comments, tabs and \r between the tokens of the header
+    let mut text = format!(
+        "{}\r\n# made by hand\n{}\t# width\n {}",
+        magic, WIDTH, HEIGHT
+    );
+    if let Some(maxval) = maxval {
+        text += &format!("\n#maxval next\n{}", maxval);
+    }
+    text.push('\n');
+    for (i, sample) in samples.enumerate() {
+        text += &format!("{}{}", sample, if i % 7 == 6 { "\n" } else { " " });
+    }
+    text.into_bytes()
+}
+
+fn binary(magic: &str, maxval: u32, samples: &[u8]) -> Vec<u8> {
+    let mut bytes = format!("{}\n#c\n{} {} #c\n{}\n", magic, WIDTH, HEIGHT, maxval).into_bytes();
+    bytes.extend_from_slice(samples);
+    bytes
+}
+
+fn pixels(bytes: &[u8]) -> Vec<Pixel> {
+    read(bytes).unwrap().to_pixels()
+}
+
+fn expected(f: impl Fn(usize, usize) -> [u8; 3]) -> Vec<Pixel> {
+    (0..HEIGHT)
+        .flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let [r, g, b] = f(x, y);
+            Pixel::new(r, g, b, 255)
+        })
+        .collect()
+}
+
+fn coords() -> impl Iterator<Item = (usize, usize)> {
+    (0..HEIGHT).flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+}
+
+#[test]
+fn ppm_variants() {
+    let p3 = ascii(
+        "P3",
+        Some(255),
+        coords().flat_map(|(x, y)| rgb(x, y).map(u32::from)),
+    );
+    let raw: Vec<u8> = coords().flat_map(|(x, y)| rgb(x, y)).collect();
+    let p6 = binary("P6", 255, &raw);
+    assert!(pixels(&p3) == expected(rgb));
+    assert!(pixels(&p6) == expected(rgb));
+
+    // 16-bit samples are rescaled to 8 bits
+    let wide: Vec<u8> = raw
+        .iter()
+        .flat_map(|&sample| (sample as u16 * 257).to_be_bytes())
+        .collect();
+    assert!(pixels(&binary("P6", 65535, &wide)) == expected(rgb));
+}
+
+#[test]
+fn pgm_variants_are_spread_over_rgb() {
+    let gray3 = |x, y| [gray(x, y); 3];
+    let p2 = ascii("P2", Some(255), coords().map(|(x, y)| gray(x, y) as u32));
+    let raw: Vec<u8> = coords().map(|(x, y)| gray(x, y)).collect();
+    assert!(pixels(&p2) == expected(gray3));
+    assert!(pixels(&binary("P5", 255, &raw)) == expected(gray3));
+
+    // a maxval of 15 scales 15 to 255 and 1 to 17
+    let p2 = ascii("P2", Some(15), coords().map(|(x, _)| (x % 16) as u32));
+    let scaled = read(&p2).unwrap();
+    assert_eq!(scaled.maxval(), 15);
+    assert!(scaled.to_pixels() == expected(|x, _| [(x % 16) as u8 * 17; 3]));
+}
+
+#[test]
+fn pbm_variants() {
+    let bit = |x: usize, y: usize| (x + y).is_multiple_of(3);
+    let bw = |x, y| if bit(x, y) { [0; 3] } else { [255; 3] };
+    let p1 = ascii("P1", None, coords().map(|(x, y)| bit(x, y) as u32));
+    assert!(pixels(&p1) == expected(bw));
+
+    // plain PBM samples don't need separators
+    let mut packed = format!("P1 {} {}\n", WIDTH, HEIGHT).into_bytes();
+    packed.extend(coords().map(|(x, y)| if bit(x, y) { b'1' } else { b'0' }));
+    assert!(pixels(&packed) == expected(bw));
+
+    // rows are padded to whole bytes
+    let mut p4 = format!("P4\n{} {}\n", WIDTH, HEIGHT).into_bytes();
+    for y in 0..HEIGHT {
+        let mut row = [0u8; WIDTH.div_ceil(8)];
+        for x in (0..WIDTH).filter(|&x| bit(x, y)) {
+            row[x / 8] |= 0x80 >> (x % 8);
+        }
+        p4.extend_from_slice(&row);
+    }
+    assert!(pixels(&p4) == expected(bw));
+}
+
+#[test]
+fn bytestream_to_pixelstream_reads_every_variant() {
+    let p2 = ascii("P2", Some(255), coords().map(|(x, y)| gray(x, y) as u32));
+    let (image, width, height, _) = bytestream_to_pixelstream(&p2).unwrap();
+    assert_eq!((width, height), (WIDTH as u32, HEIGHT as u32));
+    assert_eq!(image[1].as_pixel().unwrap(), Pixel::new(23, 23, 23, 255));
+}
+
+#[test]
+fn malformed_input_is_rejected() {
+    let invalid = |bytes: &[u8]| {
+        let result = read(bytes);
+        assert!(
+            matches!(result, Err(QoiError::InvalidNetpbm(_))),
+            "{:?}: {:?}",
+            String::from_utf8_lossy(bytes),
+            result
+        );
+    };
+    invalid(b"P7\n1 1\n255\n");
+    invalid(b"qoif");
+    invalid(b"P6\n1 1\n0\n\0\0\0");
+    invalid(b"P6\n1 1\n65536\n");
+    invalid(b"P6\n1 1\n255\n\0\0");
+    invalid(b"P6\n1 1\n255\0\0\0");
+    invalid(b"P6\n1 1\n15\n\0\0\x10");
+    invalid(b"P2\n2 1\n15\n3 16\n");
+    invalid(b"P2\n2 1\n15\n3");
+    invalid(b"P3\n1 1 255 1 x 1");
+    invalid(b"P1\n2 1\n1 2");
+    invalid(b"P5\n99999999999 1\n255\n");
+    // the file is too short for the samples before anything is allocated
+    invalid(b"P3\n10000 10000\n255\n");
+}
+
+fn pam(depth: u8, maxval: u32, tupltype: &str, samples: &[u8]) -> Vec<u8> {
+    let mut bytes = format!(
+        "P7\n# a comment\nWIDTH {}\nHEIGHT {}\nDEPTH {}\nMAXVAL {}\nTUPLTYPE {}\nENDHDR\n",
+        WIDTH, HEIGHT, depth, maxval, tupltype
+    )
+    .into_bytes();
+    bytes.extend_from_slice(samples);
+    bytes
+}
+
+fn alpha(x: usize, y: usize) -> u8 {
+    (x * 25 + y) as u8
+}
+
+#[test]
+fn pam_tuple_types() {
+    let rgba: Vec<Pixel> = coords()
+        .map(|(x, y)| {
+            let [r, g, b] = rgb(x, y);
+            Pixel::new(r, g, b, alpha(x, y))
+        })
+        .collect();
+    let raw: Vec<u8> = coords()
+        .flat_map(|(x, y)| {
+            let [r, g, b] = rgb(x, y);
+            [r, g, b, alpha(x, y)]
+        })
+        .collect();
+    let image = read(&pam(4, 255, "RGB_ALPHA", &raw)).unwrap();
+    assert!(image.has_alpha());
+    assert!(image.to_pixels() == rgba);
+
+    let raw: Vec<u8> = coords().flat_map(|(x, y)| rgb(x, y)).collect();
+    assert!(pixels(&pam(3, 255, "RGB", &raw)) == expected(rgb));
+
+    let raw: Vec<u8> = coords().map(|(x, y)| gray(x, y)).collect();
+    assert!(pixels(&pam(1, 255, "GRAYSCALE", &raw)) == expected(|x, y| [gray(x, y); 3]));
+
+    // 16-bit gray and alpha
+    let raw: Vec<u8> = coords()
+        .flat_map(|(x, y)| [gray(x, y) as u16 * 257, alpha(x, y) as u16 * 257])
+        .flat_map(u16::to_be_bytes)
+        .collect();
+    let gray_alpha: Vec<Pixel> = coords()
+        .map(|(x, y)| Pixel::new(gray(x, y), gray(x, y), gray(x, y), alpha(x, y)))
+        .collect();
+    assert!(pixels(&pam(2, 65535, "GRAYSCALE_ALPHA", &raw)) == gray_alpha);
+}
+
+#[test]
+fn pam_round_trip() {
+    let pixels: Vec<Pixel> = coords()
+        .map(|(x, y)| {
+            let [r, g, b] = rgb(x, y);
+            Pixel::new(r, g, b, alpha(x, y))
+        })
+        .collect();
+    let written = write_pam(&pixels, WIDTH as u32, HEIGHT as u32, Channels::Rgba);
+    assert!(read(&written).unwrap().to_pixels() == pixels);
+
+    // through QOI and back, the alpha survives
+    let (image, width, height, max) = bytestream_to_pixelstream(&written).unwrap();
+    let mut encoder = Encoder::new();
+    let encoded = encoder.encode_dynamic(&image, width, height, max).unwrap();
+    assert_eq!(encoded[12], 4);
+    let decoded = decode(encoded, &mut [Pixel::default(); 64]).unwrap();
+    assert!(decoded.0 == pixels);
+
+    let wide: Vec<Pixel16> = pixels
+        .iter()
+        .map(|pixel| {
+            let (r, g, b, _) = pixel.extract();
+            Pixel16::new(r as u16 * 257, g as u16 * 257, b as u16 * 257, u16::MAX)
+        })
+        .collect();
+    let written = write_pam_16(&wide, WIDTH as u32, HEIGHT as u32, Channels::Rgb);
+    let image = read(&written).unwrap();
+    assert_eq!((image.depth(), image.maxval()), (3, 65535));
+    assert!(image.to_pixels() == expected(rgb));
+}
+
+#[test]
+fn malformed_pam_is_rejected() {
+    let invalid = |bytes: &[u8]| assert!(matches!(read(bytes), Err(QoiError::InvalidNetpbm(_))));
+    invalid(&pam(3, 255, "RGB_ALPHA", &[0; WIDTH * HEIGHT * 4]));
+    invalid(&pam(5, 255, "FIVE", &[0; WIDTH * HEIGHT * 5]));
+    invalid(&pam(3, 0, "RGB", &[0; WIDTH * HEIGHT * 3]));
+    invalid(&pam(3, 255, "RGB", &[0; WIDTH * HEIGHT * 3 - 1]));
+    invalid(b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 1\nENDHDR\n\0");
+    invalid(b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 1\nMAXVAL 255\n");
+    invalid(b"P7\nWIDTH 1\nCOLORS 1\nENDHDR\n");
+}
+
+#[test]
+fn unlimited_huge_headers_dont_overflow() {
+    let unlimited = DecodeLimits::unlimited();
+    let too_large = |bytes: &[u8]| {
+        assert_eq!(
+            read_with_limits(bytes, &unlimited).unwrap_err(),
+            QoiError::InvalidNetpbm("image too large")
+        )
+    };
+    // width * height * depth overflows
+    too_large(b"P6\n4294967295 4294967295\n255\n\0\0\0");
+    too_large(b"P7\nWIDTH 4294967295\nHEIGHT 4294967295\nDEPTH 4\nMAXVAL 255\nENDHDR\n\0");
+    // the samples fit, their 2 bytes each don't
+    too_large(b"P5\n4294967295 4294967295\n65535\n\0\0");
+}
@@ -0,0 +1,22 @@
+// The codec has to build without std (only `alloc`), the crate is rebuilt that way on the host
+// into a separate target directory so it doesn't fight with the running `cargo test`.
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn builds_without_std() {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let output = Command::new(cargo)
+        .args(["build", "--lib", "--no-default-features", "--offline"])
+        .arg("--manifest-path")
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(Path::new(env!("CARGO_TARGET_TMPDIR")).join("no_std"))
+        .output()
+        .expect("couldn't run cargo");
+    assert!(
+        output.status.success(),
+        "the no_std build failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
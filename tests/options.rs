@@ -0,0 +1,90 @@
+// EncoderOptions: every combination must still decode to the pixels that were encoded.
+use qoi::qoi::decoder::decode;
+use qoi::qoi::encoder::{encode_, encode_with};
+use qoi::qoi::types::{Channels, ColorSpace, Pixel};
+use qoi::qoi::{EncoderOptions, Preset};
+
+fn image() -> Vec<Pixel> {
+    (0..400u32)
+        .map(|i| match (i / 25) % 4 {
+            0 => Pixel::new(i as u8, 10, 20, 255),
+            1 => Pixel::new(5, 5, 5, 255),
+            2 => Pixel::new((i % 7) as u8 * 30, 40, (i % 3) as u8 * 80, 128),
+            _ => Pixel::new(200, (i * 13) as u8, 9, 255),
+        })
+        .collect()
+}
+
+fn opaque(pixels: &[Pixel]) -> Vec<Pixel> {
+    pixels
+        .iter()
+        .map(|pixel| {
+            let (r, g, b, _) = pixel.extract();
+            Pixel::new(r, g, b, 255)
+        })
+        .collect()
+}
+
+#[test]
+fn default_options_match_encode_() {
+    let pixels = opaque(&image());
+    let old = encode_(&pixels, &mut [Pixel::default(); 64], 20, 20).unwrap();
+    assert_eq!(
+        encode_with(&pixels, 20, 20, &EncoderOptions::default()).unwrap(),
+        old
+    );
+}
+
+#[test]
+fn encode_stays_rgb() {
+    // translucent pixels don't switch encode_ to 4 chanels, their alpha is dropped
+    let pixels = image();
+    let encoded = encode_(&pixels, &mut [Pixel::default(); 64], 20, 20).unwrap();
+    assert_eq!(encoded[12], 3);
+    let rgb = EncoderOptions::new().chanels(Channels::Rgb);
+    assert_eq!(encode_with(&pixels, 20, 20, &rgb).unwrap(), encoded);
+    assert!(decode(&encoded, &mut [Pixel::default(); 64]).unwrap().0 == opaque(&pixels));
+}
+
+#[test]
+fn header_fields_follow_the_options() {
+    let pixels = opaque(&image());
+    let options = EncoderOptions::new()
+        .chanels(Channels::Rgba)
+        .colorspace(ColorSpace::Linear);
+    let encoded = encode_with(&pixels, 20, 20, &options).unwrap();
+    assert_eq!(&encoded[12..14], &[4, 1]);
+    assert!(decode(&encoded, &mut [Pixel::default(); 64]).unwrap().0 == pixels);
+}
+
+#[test]
+fn dropping_alpha() {
+    let pixels = image();
+    let dropped = encode_with(&pixels, 20, 20, &EncoderOptions::new().keep_alpha(false)).unwrap();
+    assert_eq!(dropped[12], 3);
+    assert!(decode(&dropped, &mut [Pixel::default(); 64]).unwrap().0 == opaque(&pixels));
+
+    // alpha is dropped as well when RGB is forced
+    let rgb = EncoderOptions::new().chanels(Channels::Rgb);
+    assert_eq!(encode_with(&pixels, 20, 20, &rgb).unwrap(), dropped);
+}
+
+#[test]
+fn speed_preset_never_uses_the_index() {
+    let pixels = image();
+    let fast = EncoderOptions::new().preset(Preset::Speed);
+    let encoded = encode_with(&pixels, 20, 20, &fast).unwrap();
+    assert!(decode(&encoded, &mut [Pixel::default(); 64]).unwrap().0 == pixels);
+
+    // walk the chunks, a tag 00 would be a QoiOpIndex
+    let mut i = 14;
+    while i < encoded.len() - 8 {
+        i += match encoded[i] {
+            0xFE => 4,
+            0xFF => 5,
+            tag if tag >> 6 == 0 => panic!("QoiOpIndex at offset {}", i),
+            tag if tag >> 6 == 2 => 2,
+            _ => 1,
+        };
+    }
+}
@@ -0,0 +1,548 @@
+// PNG and zlib: streams from the reference zlib, hand-built PNGs of every color type, bit
+// depth, filter and interlacing, and round trips through the writer.
+use qoi::qoi::QoiError;
+use qoi::qoi::encoder::bytestream_to_pixelstream;
+use qoi::qoi::png::{read, write_png, write_png_16};
+use qoi::qoi::types::{Channels, DynamicPixel, Pixel};
+use qoi::qoi::types16::Pixel16;
+use qoi::qoi::zlib::{
+    adler32, compress, crc32, decompress, decompress_with_limit, deflate, inflate,
+};
+
+// made by zlib 1.2.13 from `reference_text`, at level 9 with the fixed and the default
+// strategy, and at level 0
+const FIXED: &[u8] = &[
+    0x78, 0x01, 0x4b, 0x54, 0x28, 0x2c, 0xcd, 0x4c, 0xce, 0x56, 0x48, 0x2a, 0xca, 0x2f, 0xcf, 0x53,
+    0x48, 0xcb, 0xaf, 0x50, 0xc8, 0x2a, 0xcd, 0x2d, 0x28, 0x56, 0xc8, 0x2f, 0x4b, 0x2d, 0x52, 0x28,
+    0xc9, 0x48, 0x55, 0xc8, 0x49, 0xac, 0xaa, 0x54, 0x48, 0xc9, 0x4f, 0xd7, 0x51, 0x48, 0x24, 0x56,
+    0xa9, 0x42, 0x62, 0x7a, 0x62, 0x66, 0x9e, 0x42, 0x62, 0x5e, 0x0a, 0x3a, 0x0b, 0x00, 0x9d, 0xc1,
+    0x27, 0x85,
+];
+// made by zlib 1.2.13 from `reference_letters` at level 9, a single dynamic block
+const DYNAMIC: &[u8] = &[
+    0x78, 0xda, 0xed, 0xcc, 0xb1, 0x11, 0xc0, 0x00, 0x08, 0xc3, 0xc0, 0x59, 0x8d, 0x01, 0x1b, 0xf6,
+    0x1f, 0x20, 0xc9, 0x14, 0x69, 0x50, 0xad, 0x7b, 0x00, 0xc1, 0x6c, 0x6f, 0x94, 0x51, 0x43, 0x85,
+    0xc2, 0xb9, 0xcd, 0x55, 0x11, 0x63, 0xf5, 0x97, 0x3c, 0x60, 0x69, 0xd9, 0x9b, 0x7e, 0x0f, 0x71,
+    0x0a, 0xae, 0x58, 0x77, 0x32, 0xf0, 0x75, 0xca, 0x29, 0xa7, 0x9c, 0x72, 0xca, 0x29, 0xa7, 0xfc,
+    0xa6, 0x3c, 0x07, 0x2e, 0x16, 0xef,
+];
+
+fn reference_text() -> &'static [u8] {
+    b"a quick brown fox jumps over the lazy dog, a quick brown fox jumps over the lazy dog again and again and again"
+}
+
+fn reference_letters() -> Vec<u8> {
+    (0..2000).map(|i| b"abcdefghij"[(i * i / 7) % 10]).collect()
+}
+
+// the same sequence every run, with long repeats mixed in so matches are found
+fn mixed_bytes(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_F491u32;
+    let mut bytes = Vec::with_capacity(len);
+    while bytes.len() < len {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        let run = (state >> 24) as usize % 40 + 1;
+        let byte = if state & 0x100 == 0 {
+            state as u8 & 3
+        } else {
+            state as u8
+        };
+        bytes.extend(std::iter::repeat_n(byte, run));
+    }
+    bytes.truncate(len);
+    bytes
+}
+
+#[test]
+fn checksums() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    assert_eq!(adler32(&[]), 1);
+}
+
+#[test]
+fn zlib_reads_reference_streams() {
+    assert_eq!(decompress(FIXED).unwrap(), reference_text());
+    assert_eq!(decompress(DYNAMIC).unwrap(), reference_letters());
+    assert_eq!(
+        decompress(&stored_zlib(reference_text())).unwrap(),
+        reference_text()
+    );
+}
+
+#[test]
+fn zlib_round_trips() {
+    let noise: Vec<u8> = (0..70_000u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+        .collect();
+    for data in [
+        Vec::new(),
+        vec![7],
+        vec![0; 100_000],
+        reference_letters(),
+        mixed_bytes(300_000),
+        noise,
+    ] {
+        let compressed = compress(&data);
+        assert_eq!(decompress(&compressed).unwrap(), data);
+        assert_eq!(inflate(&deflate(&data)).unwrap(), data);
+    }
+    assert!(compress(&vec![0; 100_000]).len() < 200);
+}
+
+#[test]
+fn zlib_rejects_corrupt_streams() {
+    for len in 0..DYNAMIC.len() {
+        assert!(
+            decompress(&DYNAMIC[..len]).is_err(),
+            "prefix of {} bytes",
+            len
+        );
+    }
+    let mut bad_checksum = DYNAMIC.to_vec();
+    *bad_checksum.last_mut().unwrap() ^= 1;
+    assert!(matches!(
+        decompress(&bad_checksum),
+        Err(QoiError::InvalidDeflate(_))
+    ));
+    let mut bad_header = DYNAMIC.to_vec();
+    bad_header[1] ^= 1;
+    assert!(decompress(&bad_header).is_err());
+    // a block type of 3
+    assert!(decompress(&[0x78, 0x01, 0x07, 0, 0, 0, 0]).is_err());
+    assert!(decompress_with_limit(DYNAMIC, 1999).is_err());
+    assert_eq!(decompress_with_limit(DYNAMIC, 2000).unwrap().len(), 2000);
+}
+
+// the reflected CRC-32, a bit at a time
+fn crc(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+// a zlib stream of stored blocks only
+fn stored_zlib(raw: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = if raw.is_empty() {
+        vec![raw]
+    } else {
+        raw.chunks(65535).collect()
+    };
+    for (i, block) in blocks.iter().enumerate() {
+        bytes.push((i == blocks.len() - 1) as u8);
+        let len = block.len() as u16;
+        bytes.extend_from_slice(&len.to_le_bytes());
+        bytes.extend_from_slice(&(!len).to_le_bytes());
+        bytes.extend_from_slice(block);
+    }
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in raw {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    bytes.extend_from_slice(&((b << 16) | a).to_be_bytes());
+    bytes
+}
+
+fn chunk(bytes: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = bytes.len();
+    bytes.extend_from_slice(kind);
+    bytes.extend_from_slice(data);
+    let crc = crc(&bytes[start..]);
+    bytes.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// the scanlines with a filter byte each, row `y` uses filter `y % 5`
+fn filter(rows: &[Vec<u8>], step: usize) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for (y, row) in rows.iter().enumerate() {
+        let above = |i: usize| if y > 0 { rows[y - 1][i] } else { 0 };
+        let kind = (y % 5) as u8;
+        bytes.push(kind);
+        for i in 0..row.len() {
+            let left = if i >= step { row[i - step] } else { 0 };
+            let upper_left = if i >= step { above(i - step) } else { 0 };
+            let prediction = match kind {
+                0 => 0,
+                1 => left,
+                2 => above(i),
+                3 => ((left as u16 + above(i) as u16) / 2) as u8,
+                _ => paeth(left, above(i), upper_left),
+            };
+            bytes.push(row[i].wrapping_sub(prediction));
+        }
+    }
+    bytes
+}
+
+fn pack(samples: &[u16], bit_depth: u8) -> Vec<u8> {
+    match bit_depth {
+        16 => samples.iter().flat_map(|s| s.to_be_bytes()).collect(),
+        8 => samples.iter().map(|&s| s as u8).collect(),
+        _ => {
+            let per_byte = 8 / bit_depth as usize;
+            samples
+                .chunks(per_byte)
+                .map(|chunk| {
+                    chunk.iter().enumerate().fold(0u8, |byte, (j, &s)| {
+                        byte | (s as u8) << (8 - bit_depth as usize * (j + 1))
+                    })
+                })
+                .collect()
+        }
+    }
+}
+
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+// a PNG of the samples `pixel(x, y)` gives, with `chunks` between IHDR and the IDATs
+fn build(
+    (width, height): (usize, usize),
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+    chunks: &[(&[u8; 4], Vec<u8>)],
+    pixel: impl Fn(usize, usize) -> Vec<u16>,
+) -> Vec<u8> {
+    let samples_per_pixel = pixel(0, 0).len();
+    let step = (samples_per_pixel * bit_depth as usize).div_ceil(8);
+    let passes: &[_] = if interlaced { &ADAM7 } else { &[(0, 0, 1, 1)] };
+    let mut raw = Vec::new();
+    for &(x0, y0, dx, dy) in passes {
+        let rows: Vec<Vec<u8>> = (y0..height)
+            .step_by(dy)
+            .map(|y| {
+                let samples: Vec<u16> = (x0..width).step_by(dx).flat_map(|x| pixel(x, y)).collect();
+                pack(&samples, bit_depth)
+            })
+            .filter(|row| !row.is_empty())
+            .collect();
+        raw.extend(filter(&rows, step));
+    }
+    let mut bytes = b"\x89PNG\r\n\x1a\n".to_vec();
+    let mut header = (width as u32).to_be_bytes().to_vec();
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[bit_depth, color_type, 0, 0, interlaced as u8]);
+    chunk(&mut bytes, b"IHDR", &header);
+    chunk(&mut bytes, b"tEXt", b"Comment\0skipped");
+    for (kind, data) in chunks {
+        chunk(&mut bytes, kind, data);
+    }
+    // the data split over two IDATs
+    let compressed = stored_zlib(&raw);
+    let (first, second) = compressed.split_at(compressed.len() / 2);
+    chunk(&mut bytes, b"IDAT", first);
+    chunk(&mut bytes, b"IDAT", second);
+    chunk(&mut bytes, b"IEND", &[]);
+    bytes
+}
+
+const SIZE: (usize, usize) = (13, 11);
+
+fn rgba(bytes: &[u8]) -> Vec<[u16; 4]> {
+    read(bytes).unwrap().rgba().collect()
+}
+
+fn expected(f: impl Fn(usize, usize) -> [u16; 4]) -> Vec<[u16; 4]> {
+    (0..SIZE.1)
+        .flat_map(|y| (0..SIZE.0).map(move |x| (x, y)))
+        .map(|(x, y)| f(x, y))
+        .collect()
+}
+
+fn value(x: usize, y: usize, c: usize, max: u32) -> u16 {
+    ((x as u32 * 37 + y as u32 * 101 + c as u32 * 59).wrapping_mul(2_654_435_761) % (max + 1))
+        as u16
+}
+
+#[test]
+fn every_color_type_and_bit_depth() {
+    for interlaced in [false, true] {
+        for bit_depth in [1u8, 2, 4, 8, 16] {
+            let max = (1u32 << bit_depth) - 1;
+            let out = if bit_depth == 16 { 65535 } else { 255 };
+            let gray = |x, y| value(x, y, 0, max);
+            let png = build(SIZE, bit_depth, 0, interlaced, &[], |x, y| vec![gray(x, y)]);
+            let scaled = |x, y| (gray(x, y) as u32 * out / max) as u16;
+            let want = expected(|x, y| [scaled(x, y), scaled(x, y), scaled(x, y), out as u16]);
+            assert_eq!(rgba(&png), want, "gray {} bits", bit_depth);
+            assert_eq!(read(&png).unwrap().depth(), 1);
+            assert_eq!(read(&png).unwrap().interlaced(), interlaced);
+
+            let palette: Vec<u8> = (0..=max.min(255))
+                .flat_map(|i| [i as u8, 255 - i as u8, 7])
+                .collect();
+            let png = build(
+                SIZE,
+                bit_depth.min(8),
+                3,
+                interlaced,
+                &[(b"PLTE", palette)],
+                |x, y| vec![value(x, y, 0, max.min(255))],
+            );
+            let want = expected(|x, y| {
+                let i = value(x, y, 0, max.min(255));
+                [i, 255 - i, 7, 255]
+            });
+            assert_eq!(rgba(&png), want, "palette {} bits", bit_depth);
+        }
+        for bit_depth in [8u8, 16] {
+            let max = (1u32 << bit_depth) - 1;
+            let sample = |x, y, c| value(x, y, c, max);
+            let png = build(SIZE, bit_depth, 2, interlaced, &[], |x, y| {
+                (0..3).map(|c| sample(x, y, c)).collect()
+            });
+            let want = expected(|x, y| {
+                [
+                    sample(x, y, 0),
+                    sample(x, y, 1),
+                    sample(x, y, 2),
+                    max as u16,
+                ]
+            });
+            assert_eq!(rgba(&png), want, "rgb {} bits", bit_depth);
+
+            let png = build(SIZE, bit_depth, 4, interlaced, &[], |x, y| {
+                vec![sample(x, y, 0), sample(x, y, 3)]
+            });
+            let want = expected(|x, y| {
+                [
+                    sample(x, y, 0),
+                    sample(x, y, 0),
+                    sample(x, y, 0),
+                    sample(x, y, 3),
+                ]
+            });
+            assert_eq!(rgba(&png), want, "gray and alpha {} bits", bit_depth);
+
+            let png = build(SIZE, bit_depth, 6, interlaced, &[], |x, y| {
+                (0..4).map(|c| sample(x, y, c)).collect()
+            });
+            let want = expected(|x, y| {
+                [
+                    sample(x, y, 0),
+                    sample(x, y, 1),
+                    sample(x, y, 2),
+                    sample(x, y, 3),
+                ]
+            });
+            assert_eq!(rgba(&png), want, "rgba {} bits", bit_depth);
+            assert!(read(&png).unwrap().has_alpha());
+        }
+    }
+}
+
+#[test]
+fn transparency() {
+    // gray 2: the one transparent gray level
+    let png = build(SIZE, 2, 0, false, &[(b"tRNS", vec![0, 2])], |x, _| {
+        vec![x as u16 % 4]
+    });
+    let want = expected(|x, _| {
+        let gray = (x as u16 % 4) * 85;
+        [gray, gray, gray, if x % 4 == 2 { 0 } else { 255 }]
+    });
+    assert_eq!(rgba(&png), want);
+
+    // RGB 16: the one transparent color
+    let png = build(
+        SIZE,
+        16,
+        2,
+        true,
+        &[(b"tRNS", vec![0, 1, 0, 2, 0, 3])],
+        |x, y| vec![(x % 2) as u16, 2, 3 + y as u16 % 2],
+    );
+    let want = expected(|x, y| {
+        let clear = x % 2 == 1 && y % 2 == 0;
+        [
+            (x % 2) as u16,
+            2,
+            3 + y as u16 % 2,
+            if clear { 0 } else { 65535 },
+        ]
+    });
+    assert_eq!(rgba(&png), want);
+
+    // palette: tRNS shorter than the palette, the entries after it stay opaque
+    let png = build(
+        SIZE,
+        4,
+        3,
+        false,
+        &[
+            (b"PLTE", vec![1, 2, 3, 4, 5, 6, 7, 8, 9]),
+            (b"tRNS", vec![10, 20]),
+        ],
+        |x, y| vec![((x + y) % 3) as u16],
+    );
+    let want = expected(|x, y| match (x + y) % 3 {
+        0 => [1, 2, 3, 10],
+        1 => [4, 5, 6, 20],
+        _ => [7, 8, 9, 255],
+    });
+    assert_eq!(rgba(&png), want);
+    assert_eq!(read(&png).unwrap().depth(), 4);
+}
+
+fn pixels(width: u32, height: u32) -> Vec<Pixel> {
+    (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            Pixel::new(
+                (x * 9) as u8,
+                (y * 5) as u8,
+                ((x ^ y) * 3) as u8,
+                (x + 2 * y) as u8,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn written_files_read_back() {
+    for (width, height) in [(1, 1), (33, 7), (200, 120)] {
+        let image = pixels(width, height);
+        let png = write_png(&image, width, height, Channels::Rgba);
+        let read_back = read(&png).unwrap();
+        assert_eq!((read_back.width(), read_back.height()), (width, height));
+        assert_eq!(read_back.to_pixels(), image);
+
+        let png = write_png(&image, width, height, Channels::Rgb);
+        let opaque: Vec<Pixel> = image
+            .iter()
+            .map(|p| {
+                let (r, g, b, _) = p.extract();
+                Pixel::new(r, g, b, 255)
+            })
+            .collect();
+        assert_eq!(read(&png).unwrap().to_pixels(), opaque);
+        assert!(!read(&png).unwrap().has_alpha());
+
+        let image16: Vec<Pixel16> = image
+            .iter()
+            .map(|p| {
+                let (r, g, b, a) = p.extract();
+                Pixel16::new(r as u16 * 257, g as u16 * 31, b as u16, a as u16 * 100)
+            })
+            .collect();
+        let png = write_png_16(&image16, width, height, Channels::Rgba);
+        assert_eq!(read(&png).unwrap().bit_depth(), 16);
+        assert_eq!(read(&png).unwrap().to_pixels16(), image16);
+    }
+}
+
+#[test]
+fn png_input_to_pixelstream() {
+    let image = pixels(20, 10);
+    let png = write_png(&image, 20, 10, Channels::Rgba);
+    let (read_back, width, height, max_col_val) = bytestream_to_pixelstream(&png).unwrap();
+    assert_eq!((width, height, max_col_val), (20, 10, 255));
+    let read_back: Vec<Pixel> = read_back
+        .iter()
+        .map(|p| match p {
+            DynamicPixel::Pixel(p) => *p,
+            DynamicPixel::Pixel16(_) => panic!("8-bit PNG read as 16-bit"),
+        })
+        .collect();
+    assert_eq!(read_back, image);
+}
+
+#[test]
+fn malformed_png() {
+    let gray = |x: usize, _| vec![x as u16 % 2];
+    let good = build(SIZE, 1, 0, false, &[], gray);
+    assert!(read(&good).is_ok());
+
+    let invalid = |bytes: &[u8]| {
+        matches!(
+            read(bytes),
+            Err(QoiError::InvalidPng(_) | QoiError::InvalidDeflate(_))
+        )
+    };
+    for len in 0..good.len() {
+        assert!(invalid(&good[..len]), "prefix of {} bytes", len);
+    }
+    let mut bad_crc = good.clone();
+    bad_crc[29] ^= 1;
+    assert!(invalid(&bad_crc));
+    assert!(invalid(&build(SIZE, 3, 0, false, &[], gray)));
+    assert!(invalid(&build(
+        SIZE,
+        1,
+        0,
+        false,
+        &[(b"ABCD", vec![])],
+        gray
+    )));
+    assert!(invalid(&build(
+        SIZE,
+        1,
+        0,
+        false,
+        &[(b"PLTE", vec![0, 0, 0])],
+        gray
+    )));
+    assert!(invalid(&build(
+        SIZE,
+        8,
+        6,
+        false,
+        &[(b"tRNS", vec![0, 0])],
+        |_, _| vec![0; 4]
+    )));
+    // index 2 past a palette of 2 colors
+    assert!(invalid(&build(
+        SIZE,
+        2,
+        3,
+        false,
+        &[(b"PLTE", vec![0; 6])],
+        |_, _| vec![2]
+    )));
+    // a palette image without a palette
+    assert!(invalid(&build(SIZE, 8, 3, false, &[], |_, _| vec![0])));
+    // a row of 13 pixels at 8 bits read as 16 bits comes up short
+    let mut short = build(SIZE, 8, 0, false, &[], gray);
+    short[24] = 16;
+    let crc = crc(&short[12..29]);
+    short[29..33].copy_from_slice(&crc.to_be_bytes());
+    assert!(invalid(&short));
+}
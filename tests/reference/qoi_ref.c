@@ -0,0 +1,65 @@
+// Thin command line driver around the reference qoi.h, used by tests/differential.rs
+//
+//   qoi_ref encode <raw pixels> <width> <height> <channels> <output.qoi>
+//   qoi_ref decode <input.qoi> <output raw rgba>
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#define QOI_IMPLEMENTATION
+#include "qoi.h"
+
+static void *
+read_all(const char *path, int *size){
+  FILE *f = fopen(path, "rb");
+  if(!f) return NULL;
+  fseek(f, 0, SEEK_END);
+  *size = ftell(f);
+  fseek(f, 0, SEEK_SET);
+  void *data = malloc(*size > 0 ? *size : 1);
+  if(fread(data, 1, *size, f) != (size_t)*size){
+    free(data);
+    data = NULL;
+  }
+  fclose(f);
+  return data;
+}
+
+static int
+write_all(const char *path, const void *data, int size){
+  FILE *f = fopen(path, "wb");
+  if(!f) return 0;
+  int ok = fwrite(data, 1, size, f) == (size_t)size;
+  fclose(f);
+  return ok;
+}
+
+int
+main(int argc, char **argv){
+  int size;
+  if(argc == 7 && strcmp(argv[1], "encode") == 0){
+    void *pixels = read_all(argv[2], &size);
+    if(!pixels) return 2;
+    qoi_desc desc = {
+      .width = strtoul(argv[3], NULL, 10),
+      .height = strtoul(argv[4], NULL, 10),
+      .channels = atoi(argv[5]),
+      .colorspace = QOI_SRGB,
+    };
+    int len;
+    void *encoded = qoi_encode(pixels, &desc, &len);
+    if(!encoded) return 3;
+    return write_all(argv[6], encoded, len) ? 0 : 4;
+  }
+  if(argc == 4 && strcmp(argv[1], "decode") == 0){
+    void *encoded = read_all(argv[2], &size);
+    if(!encoded) return 2;
+    qoi_desc desc;
+    void *pixels = qoi_decode(encoded, size, &desc, 4);
+    if(!pixels) return 3;
+    return write_all(argv[3], pixels, desc.width * desc.height * 4) ? 0 : 4;
+  }
+  fprintf(stderr, "usage: qoi_ref encode <raw> <width> <height> <channels> <out> | decode <in> <out>\n");
+  return 1;
+}
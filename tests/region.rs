@@ -0,0 +1,65 @@
+// decode_region must give the same pixels as cropping the decoded image, without needing the
+// stream past the region.
+use qoi::qoi::decoder::decode;
+use qoi::qoi::types::Pixel;
+use qoi::qoi::{QoiError, decode_region};
+
+mod common;
+use common::stream;
+
+fn crop(pixels: &[Pixel], width: u32, x: u32, y: u32, w: u32, h: u32) -> Vec<Pixel> {
+    (y..y + h)
+        .flat_map(|row| {
+            let start = (row * width + x) as usize;
+            pixels[start..start + w as usize].to_vec()
+        })
+        .collect()
+}
+
+#[test]
+fn regions_match_the_cropped_image() {
+    let (encoded, pixels) = stream(53, 41);
+    assert!(decode(&encoded, &mut [Pixel::default(); 64]).unwrap().0 == pixels);
+    for (x, y, w, h) in [
+        (0, 0, 53, 41),
+        (0, 0, 1, 1),
+        (52, 40, 1, 1),
+        (10, 7, 20, 15),
+        (0, 13, 53, 2),
+        (31, 0, 22, 41),
+        (4, 4, 0, 9),
+    ] {
+        let region = decode_region(&encoded, x, y, w, h).unwrap();
+        assert!(
+            region == crop(&pixels, 53, x, y, w, h),
+            "{:?}",
+            (x, y, w, h)
+        );
+    }
+}
+
+#[test]
+fn decoding_stops_after_the_region() {
+    let (encoded, pixels) = stream(53, 41);
+    // nothing after the middle of the stream is needed for the top rows
+    let half = &encoded[..encoded.len() / 2];
+    assert!(decode(half, &mut [Pixel::default(); 64]).is_err());
+    let region = decode_region(half, 3, 0, 40, 4).unwrap();
+    assert!(region == crop(&pixels, 53, 3, 0, 40, 4));
+}
+
+#[test]
+fn out_of_bounds_regions_are_errors() {
+    let (encoded, _) = stream(53, 41);
+    for (x, y, w, h) in [
+        (0, 0, 54, 1),
+        (50, 0, 4, 1),
+        (0, 41, 1, 1),
+        (1, u32::MAX, 1, 2),
+    ] {
+        assert!(matches!(
+            decode_region(&encoded, x, y, w, h),
+            Err(QoiError::RegionOutOfBounds { .. })
+        ));
+    }
+}
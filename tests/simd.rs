@@ -0,0 +1,153 @@
+// The SIMD fast path must agree with the scalar code pixel for pixel, and the encoder built on it
+// with the pixel-at-a-time streaming encoder byte for byte.
+use qoi::qoi::QoiEncoder;
+use qoi::qoi::encoder::{encode_chanels, encode_with};
+use qoi::qoi::simd::{classify, classify_scalar, run_length, run_length_scalar};
+use qoi::qoi::types::{Channels, Pixel};
+use qoi::qoi::{EncoderOptions, Preset};
+
+fn noise(state: &mut u32) -> u32 {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+    *state
+}
+
+// mostly small steps so every class shows up, with the odd jump and alpha change
+fn pixels(len: usize, seed: u32) -> Vec<Pixel> {
+    let mut state = seed;
+    let mut pixel = [10u8, 200, 30, 255];
+    (0..len)
+        .map(|_| {
+            let roll = noise(&mut state);
+            match roll % 8 {
+                0 | 1 => {}
+                2 | 3 => {
+                    for (i, c) in pixel.iter_mut().take(3).enumerate() {
+                        *c = c
+                            .wrapping_add((roll >> (8 + 2 * i)) as u8 % 4)
+                            .wrapping_sub(2);
+                    }
+                }
+                4 | 5 => {
+                    let dg = (roll >> 8) as u8 % 64;
+                    pixel[1] = pixel[1].wrapping_add(dg).wrapping_sub(32);
+                    pixel[0] = pixel[0]
+                        .wrapping_add(dg)
+                        .wrapping_add((roll >> 16) as u8 % 20);
+                    pixel[2] = pixel[2]
+                        .wrapping_add(dg)
+                        .wrapping_sub((roll >> 24) as u8 % 20);
+                }
+                6 => pixel[3] = (roll >> 8) as u8,
+                _ => pixel = ((roll >> 1) | 0xFF00_0000).to_le_bytes(),
+            }
+            Pixel::new(pixel[0], pixel[1], pixel[2], pixel[3])
+        })
+        .collect()
+}
+
+// a screen capture: flat backgrounds, window borders and some anti-aliased text
+fn screen(width: u32, height: u32) -> Vec<Pixel> {
+    let mut state = 0xABCD;
+    let mut image = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = if y % 40 < 2 || x % 300 < 2 {
+                Pixel::new(60, 60, 70, 255)
+            } else if (y % 40) > 10
+                && (y % 40) < 22
+                && x % 300 < 200
+                && noise(&mut state).is_multiple_of(3)
+            {
+                let shade = noise(&mut state) as u8;
+                Pixel::new(shade, shade, shade.wrapping_add(3), 255)
+            } else {
+                Pixel::new(240, 240, 245, 255)
+            };
+            image.push(pixel);
+        }
+    }
+    image
+}
+
+#[test]
+fn classify_matches_scalar() {
+    for (len, seed) in [(0, 1), (1, 2), (7, 3), (8, 4), (9, 5), (1000, 6), (4099, 7)] {
+        let pixels = pixels(len, seed);
+        for opaque in [false, true] {
+            for prev in [Pixel::new(0, 0, 0, 255), Pixel::new(11, 199, 29, 255)] {
+                let (mut fast, mut slow) = (vec![0; len], vec![0; len]);
+                classify(&pixels, prev, opaque, &mut fast);
+                classify_scalar(&pixels, prev, opaque, &mut slow);
+                assert_eq!(fast, slow, "len {} opaque {}", len, opaque);
+            }
+        }
+    }
+}
+
+#[test]
+fn run_length_matches_scalar() {
+    let prev = Pixel::new(1, 2, 3, 255);
+    for len in 0..40 {
+        for end in 0..=len {
+            let mut pixels = vec![prev; len];
+            if end < len {
+                // only the alpha differs, which doesn't count when opaque
+                pixels[end] = Pixel::new(1, 2, 3, 7);
+            }
+            for opaque in [false, true] {
+                assert_eq!(
+                    run_length(&pixels, prev, opaque),
+                    run_length_scalar(&pixels, prev, opaque)
+                );
+            }
+        }
+    }
+}
+
+// the streaming encoder classifies one pixel at a time, without the block scans
+fn streamed(pixels: &[Pixel], width: u32, height: u32, chanels: u8) -> Vec<u8> {
+    let mut encoder = QoiEncoder::new(Vec::new(), width, height, chanels, 0).unwrap();
+    encoder.write_pixels(pixels).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[test]
+fn encoder_output_is_unchanged() {
+    let images = [
+        (pixels(64 * 48, 8), 64, 48),
+        (pixels(1, 9), 1, 1),
+        (vec![Pixel::new(0, 0, 0, 255); 1000], 1000, 1),
+        (screen(640, 120), 640, 120),
+    ];
+    for (image, width, height) in &images {
+        for chanels in [3, 4] {
+            let fast = encode_chanels(image, &mut [Pixel::default(); 64], *width, *height, chanels);
+            assert!(fast.unwrap() == streamed(image, *width, *height, chanels));
+        }
+    }
+}
+
+#[test]
+fn scalar_option_gives_the_same_output() {
+    for (image, width, height) in [(pixels(64 * 48, 3), 64, 48), (screen(640, 120), 640, 120)] {
+        for chanels in [Channels::Rgb, Channels::Rgba] {
+            let options = EncoderOptions::new().chanels(chanels);
+            let fast = encode_with(&image, width, height, &options).unwrap();
+            let scalar = encode_with(&image, width, height, &options.simd(false)).unwrap();
+            assert!(fast == scalar);
+        }
+    }
+}
+
+#[test]
+fn speed_preset_decodes_the_same() {
+    let image = screen(300, 80);
+    let options = EncoderOptions::new()
+        .chanels(Channels::Rgb)
+        .preset(Preset::Speed);
+    let fast = encode_with(&image, 300, 80, &options).unwrap();
+    let decoded = qoi::qoi::decode(&fast, &mut [Pixel::default(); 64]).unwrap();
+    assert!(decoded.0 == image);
+}
@@ -0,0 +1,139 @@
+// Round trips through QOI16, the 16-bit extension described in src/qoi/types16.rs
+use qoi::qoi::decoder::{decode_16, decode_to_p6_16_bit};
+use qoi::qoi::encoder::{bytestream_to_pixelstream, encode, encode_16};
+use qoi::qoi::netpbm::{read, write_ppm_16};
+use qoi::qoi::types::DynamicPixel;
+use qoi::qoi::types16::Pixel16;
+
+fn noise(state: &mut u32) -> u16 {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+    (*state >> 16) as u16
+}
+
+// a 16-bit P6 file: smooth ramps, flat areas, small deltas and noise
+fn p6_16(width: u32, height: u32) -> Vec<u8> {
+    let mut state = 0xC0FF_EE00;
+    let mut bytes = format!("P6\n{} {}\n65535\n", width, height).into_bytes();
+    for y in 0..height {
+        for x in 0..width {
+            let rgb = match (x / 10 + y / 10) % 4 {
+                0 => [1000 + x as u16 * 110, 1000 + x as u16 * 100, y as u16 * 90],
+                1 => [40000, 20000, 10000],
+                2 => [30000 + x as u16, 30000 + 2 * x as u16, 29990 + x as u16],
+                _ => [noise(&mut state), noise(&mut state), noise(&mut state)],
+            };
+            for sample in rgb {
+                bytes.extend_from_slice(&sample.to_be_bytes());
+            }
+        }
+    }
+    bytes
+}
+
+#[test]
+fn round_trip_16_bit_p6() {
+    let (width, height) = (83, 57);
+    let (image, w, h, max_col_val) = bytestream_to_pixelstream(&p6_16(width, height)).unwrap();
+    assert_eq!((w, h, max_col_val), (width, height, 65535));
+    let pixels: Vec<Pixel16> = image.iter().map(|p| p.as_pixel16().unwrap()).collect();
+
+    let mut array = [DynamicPixel::Pixel16(Pixel16::default()); 64];
+    let encoded = encode(&image, &mut array, w, h, max_col_val).unwrap();
+    assert_eq!(&encoded[..4], b"qo16");
+    assert_eq!(&encoded[encoded.len() - 8..], &[0, 0, 0, 0, 0, 0, 0, 1]);
+
+    let decoded = decode_16(&encoded, &mut [Pixel16::default(); 64]).unwrap();
+    assert_eq!((decoded.1, decoded.2), (width, height));
+    assert_eq!(decoded.0, pixels);
+    // P6 has no alpha, every pixel is opaque and the stream only has 3 chanels
+    assert!(pixels.iter().all(|pixel| pixel.extract().3 == u16::MAX));
+    assert_eq!(decoded.3, 3);
+}
+
+#[test]
+fn round_trip_16_bit_p6_bytes() {
+    let file = p6_16(31, 17);
+    let (image, w, h, max_col_val) = bytestream_to_pixelstream(&file).unwrap();
+    let mut array = [DynamicPixel::Pixel16(Pixel16::default()); 64];
+    let encoded = encode(&image, &mut array, w, h, max_col_val).unwrap();
+    let written = decode_to_p6_16_bit(&encoded, &mut [Pixel16::default(); 64]).unwrap();
+    assert_eq!(written, file);
+
+    // and the other way around, from pixels to a file and back
+    let pixels: Vec<Pixel16> = image.iter().map(|p| p.as_pixel16().unwrap()).collect();
+    let read_back = read(&write_ppm_16(&pixels, w, h)).unwrap();
+    assert_eq!(read_back.maxval(), 65535);
+    assert_eq!(read_back.to_pixels16(), pixels);
+}
+
+#[test]
+fn p6_maxval_is_rescaled_when_reading() {
+    // 256 already takes 2 bytes per sample
+    let mut file = b"P6\n2 1\n256\n".to_vec();
+    for sample in [0u16, 128, 256, 1, 255, 64] {
+        file.extend_from_slice(&sample.to_be_bytes());
+    }
+    let (image, _, _, max_col_val) = bytestream_to_pixelstream(&file).unwrap();
+    assert_eq!(max_col_val, 65535);
+    let pixels: Vec<Pixel16> = image.iter().map(|p| p.as_pixel16().unwrap()).collect();
+    assert_eq!(
+        pixels,
+        vec![
+            Pixel16::new(0, 32768, u16::MAX, u16::MAX),
+            Pixel16::new(256, 65279, 16384, u16::MAX),
+        ]
+    );
+
+    let mut array = [DynamicPixel::Pixel16(Pixel16::default()); 64];
+    let encoded = encode(&image, &mut array, 2, 1, max_col_val).unwrap();
+    let written = decode_to_p6_16_bit(&encoded, &mut [Pixel16::default(); 64]).unwrap();
+    let mut expected = b"P6\n2 1\n65535\n".to_vec();
+    for sample in [0u16, 32768, 65535, 256, 65279, 16384] {
+        expected.extend_from_slice(&sample.to_be_bytes());
+    }
+    assert_eq!(written, expected);
+
+    // up to 255 samples are a single byte and stay 8-bit
+    let (image, _, _, max_col_val) =
+        bytestream_to_pixelstream(b"P6\n1 1\n255\n\x01\x02\x03").unwrap();
+    assert_eq!(max_col_val, 255);
+    assert!(image[0].as_pixel().is_ok());
+}
+
+#[test]
+fn round_trip_16_bit_rgba() {
+    let mut state = 0x5EED;
+    let pixels: Vec<Pixel16> = (0..2000u32)
+        .map(|i| match (i / 50) % 3 {
+            0 => Pixel16::new(i as u16, i as u16 * 3, 7, u16::MAX - i as u16),
+            1 => Pixel16::new(1, 2, 3, 0),
+            _ => Pixel16::new(
+                noise(&mut state),
+                noise(&mut state),
+                noise(&mut state),
+                noise(&mut state),
+            ),
+        })
+        .collect();
+    let encoded = encode_16(&pixels, &mut [Pixel16::default(); 64], 40, 50, 65535).unwrap();
+    let decoded = decode_16(&encoded, &mut [Pixel16::default(); 64]).unwrap();
+    assert_eq!(decoded.3, 4);
+    assert_eq!(decoded.0, pixels);
+}
+
+#[test]
+fn smaller_max_col_val_is_rescaled() {
+    let pixels = vec![Pixel16::new(0, 512, 1023, u16::MAX)];
+    let encoded = encode_16(&pixels, &mut [Pixel16::default(); 64], 1, 1, 1023).unwrap();
+    let decoded = decode_16(&encoded, &mut [Pixel16::default(); 64]).unwrap();
+    assert_eq!(decoded.0, vec![Pixel16::new(0, 32800, u16::MAX, u16::MAX)]);
+}
+
+#[test]
+fn truncated_16_bit_stream_is_an_error() {
+    let pixels = vec![Pixel16::new(1000, 2000, 3000, u16::MAX); 3];
+    let encoded = encode_16(&pixels, &mut [Pixel16::default(); 64], 3, 1, 65535).unwrap();
+    assert!(decode_16(&encoded[..encoded.len() - 9], &mut [Pixel16::default(); 64]).is_err());
+}
@@ -0,0 +1,206 @@
+// QoiDecoder over any Read: the same pixels as decode whatever the reads return, and errors
+// reported at their offset in the whole stream. QoiEncoder over any Write: the same bytes as
+// encode_with however the pixels are split.
+use std::io::{Cursor, Read};
+
+use qoi::qoi::decoder::{QoiDecoder, decode};
+use qoi::qoi::encoder::{QoiEncoder, encode_with};
+use qoi::qoi::options::EncoderOptions;
+use qoi::qoi::types::Pixel;
+use qoi::qoi::{DecodeMode, QoiError};
+
+mod common;
+use common::{END_MARKER, header, stream};
+
+// hands out a single byte per call, so every chunk straddles two reads
+struct OneByte<R>(R);
+
+impl<R: Read> Read for OneByte<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(1);
+        self.0.read(&mut buf[..len])
+    }
+}
+
+fn collect<R: Read>(decoder: QoiDecoder<R>) -> Result<Vec<Pixel>, QoiError> {
+    decoder.collect()
+}
+
+#[test]
+fn rows_and_pixels_match_decode() {
+    let (encoded, pixels) = stream(37, 23);
+    assert!(decode(&encoded, &mut [Pixel::default(); 64]).unwrap().0 == pixels);
+
+    let mut decoder = QoiDecoder::new(Cursor::new(&encoded)).unwrap();
+    assert_eq!(
+        (decoder.width(), decoder.height(), decoder.chanels()),
+        (37, 23, 4)
+    );
+    let mut y = 0;
+    while let Some(row) = decoder.next_row().unwrap() {
+        assert!(row == &pixels[y * 37..(y + 1) * 37]);
+        y += 1;
+    }
+    assert_eq!((y, decoder.remaining()), (23, 0));
+
+    let decoder = QoiDecoder::new(Cursor::new(&encoded)).unwrap();
+    assert!(collect(decoder).unwrap() == pixels);
+}
+
+#[test]
+fn one_byte_reads() {
+    let (encoded, pixels) = stream(37, 23);
+    let decoder = QoiDecoder::new(OneByte(Cursor::new(&encoded))).unwrap();
+    assert!(collect(decoder).unwrap() == pixels);
+}
+
+#[test]
+fn strict_end_checks_report_absolute_offsets() {
+    let (encoded, _) = stream(37, 23);
+    let mut trailing = encoded.clone();
+    trailing.extend_from_slice(&[0xAB; 3]);
+    let missing = &encoded[..encoded.len() - 8];
+
+    // the same offsets whether the stream arrives at once or a byte at a time
+    for one_byte in [false, true] {
+        let open = |bytes: &[u8]| -> QoiDecoder<Box<dyn Read>> {
+            let reader: Box<dyn Read> = if one_byte {
+                Box::new(OneByte(Cursor::new(bytes.to_vec())))
+            } else {
+                Box::new(Cursor::new(bytes.to_vec()))
+            };
+            QoiDecoder::new(reader).unwrap()
+        };
+        assert_eq!(
+            collect(open(&trailing)).unwrap_err(),
+            QoiError::TrailingData {
+                offset: encoded.len()
+            }
+        );
+        assert_eq!(
+            collect(open(missing)).unwrap_err(),
+            QoiError::MissingEndMarker
+        );
+        assert!(collect(open(&trailing).with_mode(DecodeMode::Lenient)).is_ok());
+        assert!(collect(open(missing).with_mode(DecodeMode::Lenient)).is_ok());
+    }
+}
+
+#[test]
+fn empty_images_check_the_end_marker() {
+    for (width, height) in [(0, 5), (5, 0)] {
+        let header = header(b"qoif", width, height, 4, 0);
+        let good = [&header[..], &END_MARKER].concat();
+        let bad = [&header[..], &[0, 0, 0, 0, 0, 0, 0, 2]].concat();
+        assert!(decode(&bad, &mut [Pixel::default(); 64]).is_err());
+
+        assert!(
+            collect(QoiDecoder::new(Cursor::new(&good)).unwrap())
+                .unwrap()
+                .is_empty()
+        );
+        let expected = decode(&bad, &mut [Pixel::default(); 64]).unwrap_err();
+        assert_eq!(
+            collect(QoiDecoder::new(Cursor::new(&bad)).unwrap()).unwrap_err(),
+            expected
+        );
+        let mut decoder = QoiDecoder::new(Cursor::new(&bad)).unwrap();
+        assert_eq!(decoder.next_row().unwrap_err(), expected);
+        assert_eq!(decoder.next_row().unwrap(), None);
+        let decoder = QoiDecoder::new(Cursor::new(&header)).unwrap();
+        assert_eq!(collect(decoder).unwrap_err(), QoiError::MissingEndMarker);
+
+        let lenient = QoiDecoder::new(Cursor::new(&bad)).unwrap();
+        assert!(
+            collect(lenient.with_mode(DecodeMode::Lenient))
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
+
+#[test]
+fn truncated_streams_fail() {
+    let (encoded, _) = stream(37, 23);
+    let truncated = &encoded[..encoded.len() - 40];
+    let expected = decode(truncated, &mut [Pixel::default(); 64]).unwrap_err();
+    assert!(matches!(expected, QoiError::TruncatedChunk { .. }));
+
+    let items: Vec<_> = QoiDecoder::new(OneByte(Cursor::new(truncated)))
+        .unwrap()
+        .collect();
+    assert_eq!(items.last(), Some(&Err(expected.clone())));
+    assert!(items.len() < 37 * 23);
+
+    let mut decoder = QoiDecoder::new(Cursor::new(truncated)).unwrap();
+    let error = loop {
+        match decoder.next_row() {
+            Ok(Some(_)) => continue,
+            Ok(None) => panic!("a truncated stream decoded to the end"),
+            Err(err) => break err,
+        }
+    };
+    assert_eq!(error, expected);
+}
+
+#[test]
+fn encoder_counts_pixels() {
+    let pixels = [Pixel::new(1, 2, 3, 255); 6];
+
+    let mut encoder = QoiEncoder::new(Vec::new(), 3, 2, 3, 0).unwrap();
+    encoder.write_pixels(&pixels[..5]).unwrap();
+    assert_eq!(
+        encoder.finish().unwrap_err(),
+        QoiError::PixelCountMismatch {
+            expected: 6,
+            actual: 5
+        }
+    );
+
+    let mut encoder = QoiEncoder::new(Vec::new(), 3, 2, 3, 0).unwrap();
+    encoder.write_pixels(&pixels[..4]).unwrap();
+    assert_eq!(
+        encoder.write_pixels(&pixels[..3]).unwrap_err(),
+        QoiError::PixelCountMismatch {
+            expected: 6,
+            actual: 7
+        }
+    );
+    // the rejected pixels weren't taken, the image can still be completed
+    encoder.write_pixels(&pixels[..2]).unwrap();
+    assert!(encoder.finish().is_ok());
+
+    let mut encoder = QoiEncoder::new(Vec::new(), 3, 2, 3, 0).unwrap();
+    assert_eq!(
+        encoder.write_row(&pixels[..2]).unwrap_err(),
+        QoiError::RowLengthMismatch {
+            expected: 3,
+            actual: 2
+        }
+    );
+    encoder.write_row(&pixels[..3]).unwrap();
+    encoder.write_row(&pixels[3..]).unwrap();
+    assert!(encoder.finish().is_ok());
+}
+
+#[test]
+fn encoder_chunks_match_encode_with() {
+    let (_, pixels) = stream(37, 23);
+    let expected = encode_with(&pixels, 37, 23, &EncoderOptions::new()).unwrap();
+    // split points from a fixed xorshift, from single pixels to a few rows, so runs and the
+    // color index have to carry across calls
+    let mut state = 0x9E37_79B9u32;
+    for _ in 0..20 {
+        let mut encoder = QoiEncoder::new(Vec::new(), 37, 23, 4, 0).unwrap();
+        let mut rest = &pixels[..];
+        while !rest.is_empty() {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let len = (1 + state as usize % 120).min(rest.len());
+            encoder.write_pixels(&rest[..len]).unwrap();
+            rest = &rest[len..];
+        }
+        assert_eq!(encoder.finish().unwrap(), expected);
+    }
+}
@@ -0,0 +1,123 @@
+// The tiled container: tiles and regions must decode to the same pixels as the whole image.
+use qoi::qoi::decoder::decode;
+use qoi::qoi::types::{Channels, Pixel};
+use qoi::qoi::{EncoderOptions, QoiError, TiledImage, encode_tiled};
+
+fn image(width: u32, height: u32) -> Vec<Pixel> {
+    (0..height)
+        .flat_map(|y| {
+            (0..width).map(move |x| match (x / 9 + y / 5) % 3 {
+                0 => Pixel::new(x as u8, y as u8, (x * y) as u8, 255),
+                1 => Pixel::new(40, 40, 40, 255),
+                _ => Pixel::new((x * 7) as u8, 0, (y * 3) as u8, (x + y) as u8),
+            })
+        })
+        .collect()
+}
+
+fn crop(pixels: &[Pixel], width: u32, x: u32, y: u32, w: u32, h: u32) -> Vec<Pixel> {
+    (y..y + h)
+        .flat_map(|row| {
+            let start = (row * width + x) as usize;
+            pixels[start..start + w as usize].to_vec()
+        })
+        .collect()
+}
+
+#[test]
+fn tiles_and_regions_match_the_image() {
+    let (width, height) = (70, 45);
+    let pixels = image(width, height);
+    for tile_size in [1, 16, 32, 64, 1000] {
+        let bytes =
+            encode_tiled(&pixels, width, height, tile_size, &EncoderOptions::new()).unwrap();
+        let tiled = TiledImage::parse(&bytes).unwrap();
+        assert_eq!((tiled.width(), tiled.height()), (width, height));
+        assert_eq!(tiled.chanels(), Channels::Rgba);
+
+        for ty in 0..tiled.tiles_y() {
+            for tx in 0..tiled.tiles_x() {
+                let (x, y, w, h) = tiled.tile_bounds(tx, ty).unwrap();
+                let tile = tiled.decode_tile(tx, ty).unwrap();
+                assert!(tile == crop(&pixels, width, x, y, w, h));
+                // every tile is a QOI image of its own
+                let decoded = decode(
+                    tiled.tile_bytes(tx, ty).unwrap(),
+                    &mut [Pixel::default(); 64],
+                );
+                assert!(decoded.unwrap().0 == tile);
+            }
+        }
+        for (x, y, w, h) in [
+            (0, 0, width, height),
+            (5, 3, 40, 30),
+            (69, 44, 1, 1),
+            (17, 0, 0, 4),
+        ] {
+            let region = tiled.decode_region(x, y, w, h).unwrap();
+            assert!(
+                region == crop(&pixels, width, x, y, w, h),
+                "{:?} with tiles of {}",
+                (x, y, w, h),
+                tile_size
+            );
+        }
+    }
+}
+
+#[test]
+fn out_of_bounds_requests_are_errors() {
+    let pixels = image(20, 10);
+    let bytes = encode_tiled(&pixels, 20, 10, 8, &EncoderOptions::new()).unwrap();
+    let tiled = TiledImage::parse(&bytes).unwrap();
+    assert!(matches!(
+        tiled.decode_tile(3, 0),
+        Err(QoiError::RegionOutOfBounds { .. })
+    ));
+    assert!(matches!(
+        tiled.decode_region(15, 0, 6, 1),
+        Err(QoiError::RegionOutOfBounds { .. })
+    ));
+    assert!(matches!(
+        tiled.decode_region(u32::MAX, 0, 2, 1),
+        Err(QoiError::RegionOutOfBounds { .. })
+    ));
+}
+
+#[test]
+fn malformed_files_are_rejected() {
+    let pixels = image(20, 10);
+    let bytes = encode_tiled(&pixels, 20, 10, 8, &EncoderOptions::new()).unwrap();
+    assert!(matches!(
+        TiledImage::parse(&bytes[..17]),
+        Err(QoiError::InvalidTiled(_))
+    ));
+    assert!(matches!(
+        TiledImage::parse(&bytes[..40]),
+        Err(QoiError::InvalidTiled(_))
+    ));
+
+    let mut wrong = bytes.clone();
+    wrong[..4].copy_from_slice(b"qoif");
+    assert!(matches!(
+        TiledImage::parse(&wrong),
+        Err(QoiError::InvalidMagic(_))
+    ));
+
+    // two offsets swapped
+    let mut swapped = bytes.clone();
+    let (first, second) = (18 + 8, 18 + 16);
+    let entry = swapped[first..first + 8].to_vec();
+    swapped.copy_within(second..second + 8, first);
+    swapped[second..second + 8].copy_from_slice(&entry);
+    assert!(matches!(
+        TiledImage::parse(&swapped),
+        Err(QoiError::InvalidTiled(_))
+    ));
+
+    // a tile size that leaves a single tile, the table no longer matches the tiles
+    let mut resized = bytes;
+    resized[14..18].copy_from_slice(&64u32.to_be_bytes());
+    let result = TiledImage::parse(&resized).and_then(|tiled| tiled.decode_tile(0, 0));
+    assert!(result.is_err());
+}